@@ -0,0 +1,313 @@
+/**
+ * A tiny assembler for writing chip-8 programs by hand, one instruction per line, using the usual mnemonics
+ * (`CLS`, `LD V0, 0x10`, `DRW V0, V1, 5`, `JP start`), along with the disassembler that turns instructions back into them.
+ * A line ending in `:` is a label that jumps and calls can use instead of an address, and anything after a ; is ignored
+ */
+use std::{collections::HashMap, fmt};
+use crate::cpu::PROGRAM_START;
+
+/**
+ * A line of source that couldn't be assembled
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+  pub line: usize,
+  pub message: String
+}
+
+impl fmt::Display for AsmError {
+
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "line {}: {}", self.line, self.message)
+  }
+
+}
+
+/**
+ * The pieces of an operand that instructions care about
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operand {
+  // one of v[0] through v[F]
+  Register(u16),
+  // a plain number, or a label's address
+  Value(u16),
+  I,
+  // [I], the memory i points at
+  AtI,
+  DelayTimer,
+  SoundTimer,
+  // K, waiting on a key
+  Key,
+  // F, the font sprite for a digit
+  Font,
+  // B, the binary coded decimal digits
+  Bcd
+}
+
+/**
+ * Turn source into bytes, ready to load at PROGRAM_START
+ */
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+
+  // get rid of comments and blank lines, keeping the line numbers for errors
+  let lines: Vec<(usize, &str)> = source.lines().enumerate()
+    .map(|(number, line)| (number + 1, line.split(';').next().unwrap_or("").trim()))
+    .filter(|(_, line)| !line.is_empty())
+    .collect();
+
+  // first find where every label is, since jumps can go forwards
+  // every instruction is two bytes, so that's just a matter of counting them
+  let mut labels: HashMap<String, u16> = HashMap::new();
+  let mut addr = PROGRAM_START as u16;
+  for (number, line) in &lines {
+    match line.strip_suffix(':') {
+      Some(label) => {
+        let label = label.trim().to_ascii_uppercase();
+        if labels.insert(label.clone(), addr).is_some() {
+          return Err(AsmError { line: *number, message: format!("the label {} is used twice", label) });
+        }
+      },
+      None => addr += 2
+    }
+  }
+
+  // then put together each instruction
+  let mut bytes: Vec<u8> = vec![];
+  for (number, line) in &lines {
+
+    if line.ends_with(':') {
+      continue;
+    }
+
+    let instruction = assemble_line(line, &labels).map_err(|message| AsmError { line: *number, message })?;
+    bytes.extend_from_slice(&instruction.to_be_bytes());
+
+  }
+
+  return Ok(bytes);
+
+}
+
+/**
+ * Work out the instruction for a single line
+ */
+fn assemble_line(line: &str, labels: &HashMap<String, u16>) -> Result<u16, String> {
+
+  // the mnemonic comes first, followed by the operands split up by commas
+  let (mnemonic, rest) = match line.split_once(char::is_whitespace) {
+    Some((mnemonic, rest)) => (mnemonic, rest.trim()),
+    None => (line, "")
+  };
+  let mnemonic = mnemonic.to_ascii_uppercase();
+
+  let mut operands: Vec<Operand> = vec![];
+  if !rest.is_empty() {
+    for operand in rest.split(',') {
+      operands.push(parse_operand(operand.trim(), labels)?);
+    }
+  }
+
+  use Operand::*;
+
+  // make sure values fit in the part of the instruction they go into
+  let fits = | value: u16, max: u16 | -> Result<u16, String> {
+    if value > max {
+      return Err(format!("{:#X} is too big here, it can be at most {:#X}", value, max));
+    }
+    return Ok(value);
+  };
+
+  return match (mnemonic.as_str(), operands.as_slice()) {
+    ("CLS", []) => Ok(0x00E0),
+    ("RET", []) => Ok(0x00EE),
+    ("EXIT", []) => Ok(0x00FD),
+    ("LOW", []) => Ok(0x00FE),
+    ("HIGH", []) => Ok(0x00FF),
+    ("SYS", [Value(addr)]) => Ok(fits(*addr, 0xFFF)?),
+    ("JP", [Value(addr)]) => Ok(0x1000 | fits(*addr, 0xFFF)?),
+    ("JP", [Register(0), Value(addr)]) => Ok(0xB000 | fits(*addr, 0xFFF)?),
+    ("CALL", [Value(addr)]) => Ok(0x2000 | fits(*addr, 0xFFF)?),
+    ("SE", [Register(x), Value(kk)]) => Ok(0x3000 | x << 8 | fits(*kk, 0xFF)?),
+    ("SNE", [Register(x), Value(kk)]) => Ok(0x4000 | x << 8 | fits(*kk, 0xFF)?),
+    ("SE", [Register(x), Register(y)]) => Ok(0x5000 | x << 8 | y << 4),
+    ("LD", [Register(x), Value(kk)]) => Ok(0x6000 | x << 8 | fits(*kk, 0xFF)?),
+    ("ADD", [Register(x), Value(kk)]) => Ok(0x7000 | x << 8 | fits(*kk, 0xFF)?),
+    ("LD", [Register(x), Register(y)]) => Ok(0x8000 | x << 8 | y << 4),
+    ("OR", [Register(x), Register(y)]) => Ok(0x8001 | x << 8 | y << 4),
+    ("AND", [Register(x), Register(y)]) => Ok(0x8002 | x << 8 | y << 4),
+    ("XOR", [Register(x), Register(y)]) => Ok(0x8003 | x << 8 | y << 4),
+    ("ADD", [Register(x), Register(y)]) => Ok(0x8004 | x << 8 | y << 4),
+    ("SUB", [Register(x), Register(y)]) => Ok(0x8005 | x << 8 | y << 4),
+    // the shifts can leave out vy, in which case vx shifts itself whichever way the quirk is set
+    ("SHR", [Register(x)]) => Ok(0x8006 | x << 8 | x << 4),
+    ("SHR", [Register(x), Register(y)]) => Ok(0x8006 | x << 8 | y << 4),
+    ("SUBN", [Register(x), Register(y)]) => Ok(0x8007 | x << 8 | y << 4),
+    ("SHL", [Register(x)]) => Ok(0x800E | x << 8 | x << 4),
+    ("SHL", [Register(x), Register(y)]) => Ok(0x800E | x << 8 | y << 4),
+    ("SNE", [Register(x), Register(y)]) => Ok(0x9000 | x << 8 | y << 4),
+    ("LD", [I, Value(addr)]) => Ok(0xA000 | fits(*addr, 0xFFF)?),
+    ("RND", [Register(x), Value(kk)]) => Ok(0xC000 | x << 8 | fits(*kk, 0xFF)?),
+    ("DRW", [Register(x), Register(y), Value(n)]) => Ok(0xD000 | x << 8 | y << 4 | fits(*n, 0xF)?),
+    ("SKP", [Register(x)]) => Ok(0xE09E | x << 8),
+    ("SKNP", [Register(x)]) => Ok(0xE0A1 | x << 8),
+    ("LD", [Register(x), DelayTimer]) => Ok(0xF007 | x << 8),
+    ("LD", [Register(x), Key]) => Ok(0xF00A | x << 8),
+    ("LD", [DelayTimer, Register(x)]) => Ok(0xF015 | x << 8),
+    ("LD", [SoundTimer, Register(x)]) => Ok(0xF018 | x << 8),
+    ("ADD", [I, Register(x)]) => Ok(0xF01E | x << 8),
+    ("LD", [Font, Register(x)]) => Ok(0xF029 | x << 8),
+    ("LD", [Bcd, Register(x)]) => Ok(0xF033 | x << 8),
+    ("LD", [AtI, Register(x)]) => Ok(0xF055 | x << 8),
+    ("LD", [Register(x), AtI]) => Ok(0xF065 | x << 8),
+    // raw words, for data or anything without a mnemonic
+    ("DW", [Value(word)]) => Ok(*word),
+    _ => Err(format!("\"{}\" isn't an instruction this assembler knows", line))
+  };
+
+}
+
+/**
+ * Work out what a single operand refers to
+ */
+fn parse_operand(operand: &str, labels: &HashMap<String, u16>) -> Result<Operand, String> {
+
+  let upper = operand.to_ascii_uppercase();
+
+  // registers and the other special names first
+  match upper.as_str() {
+    "I" => return Ok(Operand::I),
+    "[I]" => return Ok(Operand::AtI),
+    "DT" => return Ok(Operand::DelayTimer),
+    "ST" => return Ok(Operand::SoundTimer),
+    "K" => return Ok(Operand::Key),
+    "F" => return Ok(Operand::Font),
+    "B" => return Ok(Operand::Bcd),
+    _ => ()
+  }
+  if upper.len() == 2 && upper.starts_with('V') {
+    if let Ok(register) = u16::from_str_radix(&upper[1..], 16) {
+      return Ok(Operand::Register(register));
+    }
+  }
+
+  // then numbers, in hex or decimal
+  let number = match upper.strip_prefix("0X") {
+    Some(hex) => u16::from_str_radix(hex, 16).ok(),
+    None => upper.parse::<u16>().ok()
+  };
+  if let Some(number) = number {
+    return Ok(Operand::Value(number));
+  }
+
+  // and anything else has to be a label
+  return match labels.get(&upper) {
+    Some(addr) => Ok(Operand::Value(*addr)),
+    None => Err(format!("\"{}\" isn't a register, number or label", operand))
+  };
+
+}
+
+/**
+ * Turn an instruction back into the mnemonic the assembler would take for it.
+ * Anything the assembler doesn't have a mnemonic for comes out as a raw DW word
+ */
+pub fn disassemble(instruction: u16) -> String {
+
+  let x = (instruction >> 8) & 0xF;
+  let y = (instruction >> 4) & 0xF;
+  let n = instruction & 0xF;
+  let kk = instruction & 0xFF;
+  let nnn = instruction & 0xFFF;
+
+  return match instruction & 0xF000 {
+    0x0000 => match instruction {
+      0x00E0 => "CLS".to_string(),
+      0x00EE => "RET".to_string(),
+      0x00FD => "EXIT".to_string(),
+      0x00FE => "LOW".to_string(),
+      0x00FF => "HIGH".to_string(),
+      _ => format!("SYS {:#05X}", nnn)
+    },
+    0x1000 => format!("JP {:#05X}", nnn),
+    0x2000 => format!("CALL {:#05X}", nnn),
+    0x3000 => format!("SE V{:X}, {:#04X}", x, kk),
+    0x4000 => format!("SNE V{:X}, {:#04X}", x, kk),
+    0x5000 if n == 0 => format!("SE V{:X}, V{:X}", x, y),
+    0x6000 => format!("LD V{:X}, {:#04X}", x, kk),
+    0x7000 => format!("ADD V{:X}, {:#04X}", x, kk),
+    0x8000 => match n {
+      0x0 => format!("LD V{:X}, V{:X}", x, y),
+      0x1 => format!("OR V{:X}, V{:X}", x, y),
+      0x2 => format!("AND V{:X}, V{:X}", x, y),
+      0x3 => format!("XOR V{:X}, V{:X}", x, y),
+      0x4 => format!("ADD V{:X}, V{:X}", x, y),
+      0x5 => format!("SUB V{:X}, V{:X}", x, y),
+      0x6 => format!("SHR V{:X}, V{:X}", x, y),
+      0x7 => format!("SUBN V{:X}, V{:X}", x, y),
+      0xE => format!("SHL V{:X}, V{:X}", x, y),
+      _ => format!("DW {:#06X}", instruction)
+    },
+    0x9000 if n == 0 => format!("SNE V{:X}, V{:X}", x, y),
+    0xA000 => format!("LD I, {:#05X}", nnn),
+    0xB000 => format!("JP V0, {:#05X}", nnn),
+    0xC000 => format!("RND V{:X}, {:#04X}", x, kk),
+    0xD000 => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+    0xE000 if kk == 0x9E => format!("SKP V{:X}", x),
+    0xE000 if kk == 0xA1 => format!("SKNP V{:X}", x),
+    0xF000 => match kk {
+      0x07 => format!("LD V{:X}, DT", x),
+      0x0A => format!("LD V{:X}, K", x),
+      0x15 => format!("LD DT, V{:X}", x),
+      0x18 => format!("LD ST, V{:X}", x),
+      0x1E => format!("ADD I, V{:X}", x),
+      0x29 => format!("LD F, V{:X}", x),
+      0x33 => format!("LD B, V{:X}", x),
+      0x55 => format!("LD [I], V{:X}", x),
+      0x65 => format!("LD V{:X}, [I]", x),
+      _ => format!("DW {:#06X}", instruction)
+    },
+    _ => format!("DW {:#06X}", instruction)
+  };
+
+}
+
+#[cfg(test)]
+mod tests {
+
+  use super::*;
+
+  // the instructions in some bytes, disassembled one line each
+  fn disassemble_all(bytes: &[u8]) -> Vec<String> {
+
+    return bytes.chunks_exact(2).map(| word | disassemble((word[0] as u16) << 8 | word[1] as u16)).collect();
+
+  }
+
+  #[test]
+  fn assembling_and_disassembling_round_trip() {
+
+    let source = [
+      "CLS", "LD V0, 0x10", "LD I, 0x20A", "DRW V0, V1, 5", "ADD V0, V1", "SHL VA, VB",
+      "SE V3, 0x04", "LD V2, K", "LD [I], V5", "RND VF, 0xFF", "JP V0, 0x300", "CALL 0x400", "RET"
+    ];
+
+    let bytes = assemble(&source.join("\n")).unwrap();
+    assert_eq!(bytes.len(), source.len() * 2);
+    assert_eq!(&bytes[0..4], &[0x00, 0xE0, 0x60, 0x10]);
+    assert_eq!(disassemble_all(&bytes), source);
+
+  }
+
+  #[test]
+  fn labels_become_addresses() {
+
+    let bytes = assemble("start:\n  LD V0, 1  ; count up\nloop:\n  ADD V0, 1\n  JP loop\n  JP start").unwrap();
+    assert_eq!(disassemble_all(&bytes), ["LD V0, 0x01", "ADD V0, 0x01", "JP 0x202", "JP 0x200"]);
+
+    // and anything that doesn't make sense says which line it's on
+    assert_eq!(assemble("CLS\nJP nowhere").unwrap_err().line, 2);
+
+  }
+
+}
@@ -0,0 +1,259 @@
+/**
+ * Plays the beep while the sound timer is running. XO-CHIP ROMs can also supply their own
+ * 16-byte sample pattern and pitch, which is played back one bit at a time
+ */
+use std::sync::{Arc, Mutex};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+// the pattern used when a ROM hasn't loaded its own: 4 bits on, 4 bits off makes a square wave
+pub const DEFAULT_PATTERN: [u8; 16] = [0xF0; 16];
+// the pitch register value that plays the pattern at 4000 bits per second
+pub const DEFAULT_PITCH: u8 = 64;
+// how many bits of the default pattern make up one wave
+const DEFAULT_PERIOD: f32 = 8.0;
+
+/**
+ * The shape of the beep. Only the default pattern gets reshaped, since a ROM's own pattern is meant to be played exactly as it is
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Waveform {
+  // what the original hardware made, and the default
+  Square,
+  Sine,
+  Triangle
+}
+
+impl Waveform {
+
+  /**
+   * Find a waveform from the name it's given on the command line
+   */
+  pub fn from_name(name: &str) -> Option<Self> {
+
+    return match name.to_ascii_lowercase().as_str() {
+      "square" => Some(Waveform::Square),
+      "sine" => Some(Waveform::Sine),
+      "triangle" => Some(Waveform::Triangle),
+      _ => None
+    };
+
+  }
+
+  /**
+   * The one after this, for switching between them with a key
+   */
+  pub fn next(&self) -> Self {
+
+    return match self {
+      Waveform::Square => Waveform::Sine,
+      Waveform::Sine => Waveform::Triangle,
+      Waveform::Triangle => Waveform::Square
+    };
+
+  }
+
+  /**
+   * The height of the wave from -1 to 1, at a position from 0 to 1 through one period.
+   * They all start at the middle of the way up (or the top, for square) and peak a quarter of the way through
+   */
+  pub fn sample(&self, position: f32) -> f32 {
+
+    return match self {
+      Waveform::Square => if position < 0.5 { 1.0 } else { -1.0 },
+      Waveform::Sine => (position * std::f32::consts::TAU).sin(),
+      Waveform::Triangle => if position < 0.25 {
+        position * 4.0
+      } else if position < 0.75 {
+        2.0 - position * 4.0
+      } else {
+        position * 4.0 - 4.0
+      }
+    };
+
+  }
+
+}
+
+/**
+ * Everything the audio thread needs to know to generate the waveform
+ */
+pub struct Tone {
+  // whether or not the sound timer is currently running
+  pub playing: bool,
+  // 128 bits of samples, played from the most significant bit of the first byte
+  pub pattern: [u8; 16],
+  pub pitch: u8,
+  pub waveform: Waveform,
+  // how far through the pattern playback is, measured in bits
+  phase: f32
+}
+
+impl Tone {
+
+  pub fn new() -> Self {
+
+    return Tone { playing: false, pattern: DEFAULT_PATTERN, pitch: DEFAULT_PITCH, waveform: Waveform::Square, phase: 0.0 };
+
+  }
+
+  /**
+   * How many bits of the pattern play per second, as defined by the XO-CHIP spec
+   */
+  pub fn playback_rate(&self) -> f32 {
+
+    return 4000.0 * 2f32.powf((self.pitch as f32 - 64.0) / 48.0);
+
+  }
+
+  /**
+   * Generate the next sample, moving through the pattern at the playback rate
+   */
+  pub fn next_sample(&mut self, sample_rate: f32) -> f32 {
+
+    // silence when the sound timer isn't running
+    if !self.playing {
+      return 0.0;
+    }
+
+    // the default pattern is a square wave, which can be swapped for a different shape with the same pitch
+    let sample = if self.waveform != Waveform::Square && self.pattern == DEFAULT_PATTERN {
+      self.waveform.sample(self.phase % DEFAULT_PERIOD / DEFAULT_PERIOD)
+    }
+    // otherwise find the bit we're currently on
+    else {
+      let bit = self.phase as usize % 128;
+      if (self.pattern[bit / 8] >> (7 - bit % 8)) & 1 == 1 { 1.0 } else { -1.0 }
+    };
+
+    // move forward, wrapping around the end of the pattern
+    self.phase = (self.phase + self.playback_rate() / sample_rate) % 128.0;
+
+    // keep the volume reasonable
+    return sample * 0.25;
+
+  }
+
+}
+
+pub struct Audio {
+  // the tone state, shared with the audio thread
+  pub tone: Arc<Mutex<Tone>>,
+  // the output stream, which plays for as long as it's kept around
+  // this is None when there's no audio device (or when running headless)
+  stream: Option<cpal::Stream>
+}
+
+impl Audio {
+
+  /**
+   * Open the default output device and start streaming the tone to it. If that doesn't work,
+   * the emulator carries on without sound
+   */
+  pub fn new() -> Self {
+
+    let tone = Arc::new(Mutex::new(Tone::new()));
+
+    let stream = match Audio::open_stream(tone.clone()) {
+      Ok(stream) => Some(stream),
+      Err(error) => {
+        log::warn!("Couldn't open an audio output, continuing without sound: {}", error);
+        None
+      }
+    };
+
+    return Audio { tone, stream };
+
+  }
+
+  /**
+   * Create audio that never makes a sound, for running without any outputs
+   */
+  pub fn silent() -> Self {
+
+    return Audio { tone: Arc::new(Mutex::new(Tone::new())), stream: None };
+
+  }
+
+  /**
+   * Set up a cpal stream that pulls samples from the shared tone
+   */
+  fn open_stream(tone: Arc<Mutex<Tone>>) -> Result<cpal::Stream, Box<dyn std::error::Error>> {
+
+    let host = cpal::default_host();
+    let device = host.default_output_device().ok_or("no output device available")?;
+
+    let config = device.default_output_config()?;
+    let sample_rate = config.sample_rate().0 as f32;
+    let channels = config.channels() as usize;
+
+    let stream = device.build_output_stream(
+      &config.into(),
+      move | data: &mut [f32], _: &cpal::OutputCallbackInfo | {
+        let mut tone = tone.lock().unwrap();
+        // every channel in a frame gets the same sample
+        for frame in data.chunks_mut(channels) {
+          let sample = tone.next_sample(sample_rate);
+          for channel in frame.iter_mut() {
+            *channel = sample;
+          }
+        }
+      },
+      | error | log::error!("Audio stream error: {}", error)
+    )?;
+
+    stream.play()?;
+
+    return Ok(stream);
+
+  }
+
+  /**
+   * Silence the beep and close the output stream. It's safe to call this more than once
+   */
+  pub fn stop(&mut self) {
+
+    self.tone.lock().unwrap().playing = false;
+
+    if let Some(stream) = self.stream.take() {
+      if let Err(error) = stream.pause() {
+        log::warn!("Couldn't pause the audio stream: {}", error);
+      }
+    }
+
+  }
+
+  /**
+   * Update what the audio thread plays, called once per frame from the cpu
+   */
+  pub fn update(&mut self, playing: bool, pattern: &[u8; 16], pitch: u8, waveform: Waveform) {
+
+    let mut tone = self.tone.lock().unwrap();
+    tone.playing = playing;
+    tone.pattern = *pattern;
+    tone.pitch = pitch;
+    tone.waveform = waveform;
+
+  }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+  use super::*;
+
+  #[test]
+  fn every_waveform_peaks_a_quarter_of_the_way_through() {
+
+    for waveform in [Waveform::Square, Waveform::Sine, Waveform::Triangle] {
+      assert!((waveform.sample(0.25) - 1.0).abs() < 1e-6, "{:?}", waveform);
+      assert!((waveform.sample(0.75) + 1.0).abs() < 1e-6, "{:?}", waveform);
+    }
+
+    // and the smooth ones start and cross back over the middle on the half periods
+    assert_eq!(Waveform::Triangle.sample(0.0), 0.0);
+    assert!(Waveform::Sine.sample(0.5).abs() < 1e-6);
+
+  }
+
+}
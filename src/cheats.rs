@@ -0,0 +1,64 @@
+/**
+ * Cheats are a list of memory addresses and the values to poke into them, written one per line like `2A4:05`.
+ * A `!` on the end (`2A4:05!`) holds the value there every frame instead of just setting it once, and anything after a # is ignored
+ */
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cheat {
+  pub addr: usize,
+  pub value: u8,
+  // whether the value is written every frame, or just once
+  pub hold: bool
+}
+
+/**
+ * A line of a cheat file that couldn't be understood
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheatError {
+  pub line: usize,
+  pub text: String
+}
+
+impl fmt::Display for CheatError {
+
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "line {}: \"{}\" should look like ADDRESS:VALUE, in hex", self.line, self.text)
+  }
+
+}
+
+/**
+ * Read every cheat out of a cheat file's text
+ */
+pub fn parse_cheats(text: &str) -> Result<Vec<Cheat>, CheatError> {
+
+  let mut cheats: Vec<Cheat> = vec![];
+
+  for (number, line) in text.lines().enumerate() {
+
+    // get rid of comments and any space around the cheat
+    let cheat = line.split('#').next().unwrap_or("").trim();
+    if cheat.is_empty() {
+      continue;
+    }
+
+    let error = CheatError { line: number + 1, text: cheat.to_string() };
+
+    let (cheat, hold) = match cheat.strip_suffix('!') {
+      Some(cheat) => (cheat, true),
+      None => (cheat, false)
+    };
+    let (addr, value) = cheat.split_once(':').ok_or_else(|| error.clone())?;
+
+    let addr = usize::from_str_radix(addr.trim().trim_start_matches("0x"), 16).map_err(|_| error.clone())?;
+    let value = u8::from_str_radix(value.trim().trim_start_matches("0x"), 16).map_err(|_| error.clone())?;
+
+    cheats.push(Cheat { addr, value, hold });
+
+  }
+
+  return Ok(cheats);
+
+}
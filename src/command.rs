@@ -0,0 +1,113 @@
+use std::path::PathBuf;
+
+/**
+ * Commands that drive the emulator from outside the window, like from a script or a test, without faking key presses.
+ * They're sent over a channel that the main loop checks every time around
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+  Pause,
+  Resume,
+  // run one instruction while paused
+  Step,
+  // start the current program over
+  Reset,
+  // swap to a different program
+  LoadRom(Vec<u8>),
+  // swap to the program in a file, which isn't read until the command is carried out
+  Load(PathBuf),
+  // change how many instructions run each frame
+  SetSpeed(u16),
+  // hold down or let go of a chip-8 key
+  PressKey(u8),
+  ReleaseKey(u8)
+}
+
+impl Command {
+
+  /**
+   * Read a command from a line of text, like `pause`, `step`, `speed 20`, `press 5` or `load roms/pong.ch8`.
+   * Keys are hex and speeds are decimal. Anything that doesn't make sense is None
+   */
+  pub fn parse(line: &str) -> Option<Self> {
+
+    let mut words = line.split_whitespace();
+    let name = words.next()?.to_ascii_lowercase();
+    let argument = words.next();
+
+    // keys go from 0 to F
+    let key = || -> Option<u8> {
+      let key = u8::from_str_radix(argument?.trim_start_matches("0x"), 16).ok()?;
+      if key > 0xF {
+        return None;
+      }
+      return Some(key);
+    };
+
+    return match name.as_str() {
+      "pause" => Some(Command::Pause),
+      "resume" => Some(Command::Resume),
+      "step" => Some(Command::Step),
+      "reset" => Some(Command::Reset),
+      "speed" => Some(Command::SetSpeed(argument?.parse().ok()?)),
+      "press" => Some(Command::PressKey(key()?)),
+      "release" => Some(Command::ReleaseKey(key()?)),
+      "load" => Some(Command::Load(PathBuf::from(argument?))),
+      _ => None
+    };
+
+  }
+
+}
+
+/**
+ * Read commands from stdin, one per line, and send them over to the main loop.
+ * stdin blocks, so this happens on another thread
+ */
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read_stdin(sender: std::sync::mpsc::Sender<Command>) {
+
+  use std::io::BufRead;
+
+  std::thread::spawn(move || {
+    for line in std::io::stdin().lock().lines() {
+      let line = match line {
+        Ok(line) => line,
+        Err(_) => return
+      };
+      match Command::parse(&line) {
+        Some(command) => if sender.send(command).is_err() {
+          return;
+        },
+        None => log::warn!("Ignoring unknown command {}", line)
+      }
+    }
+  });
+
+}
+
+#[cfg(test)]
+mod tests {
+
+  use super::*;
+
+  #[test]
+  fn lines_parse_into_commands() {
+
+    assert_eq!(Command::parse("pause"), Some(Command::Pause));
+    assert_eq!(Command::parse("  STEP  "), Some(Command::Step));
+    assert_eq!(Command::parse("speed 20"), Some(Command::SetSpeed(20)));
+    assert_eq!(Command::parse("press a"), Some(Command::PressKey(0xA)));
+    assert_eq!(Command::parse("release 0xF"), Some(Command::ReleaseKey(0xF)));
+    // the file isn't touched until the command's carried out, so it doesn't have to exist yet
+    assert_eq!(Command::parse("load roms/missing.ch8"), Some(Command::Load(PathBuf::from("roms/missing.ch8"))));
+
+    assert_eq!(Command::parse("press 10"), None);
+    assert_eq!(Command::parse("speed fast"), None);
+    assert_eq!(Command::parse("load"), None);
+    assert_eq!(Command::parse("jump"), None);
+    assert_eq!(Command::parse(""), None);
+
+  }
+
+}
@@ -2,441 +2,4340 @@
  * This struct reads and interprets instructions, handling memory and connecting with
  * the display and audio outputs as well as the keyboard inputs
  */
-use crate::{display::Display, keyboard::Keyboard};
+use crate::{display::{self, Display, DisplayError, DrawMode, SpriteBox}, keyboard::Keyboard, audio::{self, Audio, Waveform}, rewind::{self, RewindBuffer, SavedState}, quirks::{Quirks, QuirkProfile, KeyWait}, timing::CycleCosts, cheats::Cheat, detect, replay::{Replay, ReplayError, KeyEvent}, asm, command::Command, watch::{Watch, WatchTarget}, initial_state::{self, StateDirective, StateError}, custom_opcode::{CustomOpcode, OpcodeHandler}, settings::{self, RomSettings}};
+use std::{fmt, fs, io::Write, path::{Path, PathBuf}, collections::{HashSet, VecDeque}, rc::Rc, ops::Range};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+// where the SCHIP RPL user flags are kept between sessions
+const RPL_FILE: &str = "emul8_rpl.bin";
+// where the details of a crash get written, to attach to a bug report
+const CRASH_REPORT_FILE: &str = "emul8_crash.txt";
+// where each rom's speed, profile, palette and keys are remembered
+const SETTINGS_FILE: &str = "emul8_settings.txt";
+// how many instructions either side of the crash to disassemble in the report
+const CRASH_REPORT_WINDOW: usize = 5;
+
+// the built in digit sprites, 0 through F, each 5 bytes long
+pub const DEFAULT_FONT: [u8; 80] = [
+  0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+  0x20, 0x60, 0x20, 0x20, 0x70, // 1
+  0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+  0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+  0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+  0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+  0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+  0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+  0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+  0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+  0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+  0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+  0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+  0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+  0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+  0xF0, 0x80, 0xF0, 0x80, 0x80  // F
+];
+
+// SCHIP's large digits, each 10 bytes long (XO-CHIP adds A through F)
+pub const DEFAULT_BIG_FONT: [u8; 160] = [
+  0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+  0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+  0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+  0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+  0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+  0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+  0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+  0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+  0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+  0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+  0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+  0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+  0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+  0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+  0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+  0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0  // F
+];
+
+// where each font goes in memory, one right after the other
+pub const FONT_START: usize = 0x000;
+pub const BIG_FONT_START: usize = 0x050;
+
+// where programs are loaded and start running from, and where ETI-660 computer programs go instead
+pub const PROGRAM_START: usize = 0x200;
+pub const ETI_660_START: usize = 0x600;
+
+// the most instructions a step over will run before giving up on the subroutine ever returning
+const STEP_OVER_LIMIT: u64 = 1_000_000;
+
+// how many times faster the game runs while turbo is held
+pub const TURBO_FACTOR: u16 = 8;
+
+/**
+ * Problems the cpu can run into while executing a program
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuError {
+  // a 0x2nnn call was made with the stack already at its limit
+  StackOverflow,
+  // a 0x00EE return was made without a subroutine to return from
+  StackUnderflow,
+  // an instruction that isn't implemented (or doesn't exist) was encountered
+  UnknownOpcode(u16)
+}
+
+impl fmt::Display for CpuError {
+
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      CpuError::StackOverflow => write!(f, "stack overflow: too many nested subroutine calls"),
+      CpuError::StackUnderflow => write!(f, "stack underflow: returned without a subroutine to return from"),
+      CpuError::UnknownOpcode(instruction) => write!(f, "unknown opcode {:#06X}", instruction)
+    }
+  }
+
+}
+
+/**
+ * What a byte of memory is being used for, as far as the memory map can tell.
+ * They're in order of how much they matter, so when a few bytes share a spot on the map the biggest one wins
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MemoryRegion {
+  // nothing the emulator knows about, although the program might still be using it
+  Unused,
+  // where the rom was loaded
+  Program,
+  // the built in digit sprites, small and large
+  Font,
+  // where i (memory_addr) points
+  Index,
+  // the instruction that runs next
+  ProgramCounter
+}
+
+// how many bytes the memory map covers, which is all of regular chip-8 memory
+pub const MEMORY_MAP_SIZE: usize = 4096;
+
+/**
+ * A copy of the cpu's state at a moment in time, for debugging tools to display
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CpuSnapshot {
+  pub v: [u8; 16],
+  pub i: usize,
+  pub pc: usize,
+  pub stack: Vec<usize>,
+  pub delay_timer: u8,
+  pub sound_timer: u8,
+  // how the emulator itself is set up: instructions per frame (including turbo), and so on
+  pub speed: u16,
+  pub paused: bool,
+  pub muted: bool,
+  pub profile: QuirkProfile
+}
+
+/**
+ * A token in a hex program that couldn't be turned into bytes
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HexError {
+  pub token: String
+}
+
+impl fmt::Display for HexError {
+
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "\"{}\" isn't a valid hex byte sequence", self.token)
+  }
+
+}
+
+/**
+ * A program that couldn't be loaded
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoadError {
+  // the program was written as hex, and some of it wasn't valid
+  Hex(HexError),
+  // the program doesn't fit between where it's loaded and the end of memory
+  TooLarge { size: usize, available: usize }
+}
+
+impl fmt::Display for LoadError {
+
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      LoadError::Hex(error) => write!(f, "{}", error),
+      LoadError::TooLarge { size, available } => write!(f, "the program is {} bytes, but there's only room for {}", size, available)
+    }
+  }
+
+}
+
+impl From<HexError> for LoadError {
+
+  fn from(error: HexError) -> Self {
+    return LoadError::Hex(error);
+  }
+
+}
+
+/**
+ * A poke that wasn't allowed to happen
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PokeError {
+  // the address is past the end of memory
+  OutOfRange(usize),
+  // the address is where the font lives, which is protected unless allow_font_pokes is on
+  Protected(usize),
+  // there are only registers from V0 to VF
+  NoRegister(u8)
+}
+
+impl fmt::Display for PokeError {
+
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      PokeError::OutOfRange(addr) => write!(f, "{:#06X} is outside of memory", addr),
+      PokeError::Protected(addr) => write!(f, "{:#06X} is part of the font", addr),
+      PokeError::NoRegister(index) => write!(f, "there's no register V{:X}", index)
+    }
+  }
+
+}
+
+/**
+ * Whether the program is running, and if it isn't, why not
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunState {
+  Active(Activity),
+  // stopped by the user, a breakpoint, a watch or a crash until it's resumed, when it goes back to what it was doing
+  Paused(Activity),
+  // the program has finished, and only starts over if it's reset
+  Halted
+}
+
+/**
+ * What the program is doing while it isn't paused or halted
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Activity {
+  // instructions and timers both going
+  Running,
+  // just the instructions are stopped, with the timers left running, for debugging
+  CpuFrozen,
+  // or the other way around
+  TimersFrozen,
+  // 0xFx0A is waiting for a key to be pressed, which stops the timers too until it's back to running
+  AwaitingKey
+}
+
+/**
+ * What to do with a rom that's too big to fit in memory
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OversizePolicy {
+  // don't load it at all, which is the default since it's usually not a chip-8 program
+  Reject,
+  // load as much as fits and warn about the rest
+  Truncate
+}
+
+impl OversizePolicy {
+
+  /**
+   * Find a policy from the name it's given on the command line
+   */
+  pub fn from_name(name: &str) -> Option<Self> {
+
+    return match name.to_ascii_lowercase().as_str() {
+      "reject" => Some(OversizePolicy::Reject),
+      "truncate" => Some(OversizePolicy::Truncate),
+      _ => None
+    };
+
+  }
+
+}
+
+/**
+ * How much memory the cpu has
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryConfig {
+  // the original 4KB
+  Standard,
+  // XO-CHIP's 64KB
+  Extended
+}
+
+impl MemoryConfig {
+
+  pub fn size(&self) -> usize {
+
+    return match self {
+      MemoryConfig::Standard => 0x1000,
+      MemoryConfig::Extended => 0x10000
+    };
+
+  }
+
+  /**
+   * How much memory a profile comes with, which is all 64KB for XO-CHIP and the original 4KB otherwise
+   */
+  pub fn for_profile(profile: QuirkProfile) -> Self {
+
+    return match profile {
+      QuirkProfile::XoChip => MemoryConfig::Extended,
+      _ => MemoryConfig::Standard
+    };
+
+  }
+
+}
+
+/**
+ * Something to run at the end of every frame, which gets to look at the cpu as the frame left it
+ */
+pub type FrameCallback = Box<dyn FnMut(&Cpu)>;
 
 pub struct Cpu {
 
   // references to the structs that handle input/output
   pub display: Display,
   pub keyboard: Keyboard,
+  pub audio: Audio,
 
-  // 4096 bytes of memory, each byte as a u8
-  pub memory: [u8; 4096],
+  // memory, each byte as a u8, which is either 4KB or 64KB long depending on the memory config
+  // only the first 4096 bytes are addressable unless running in XO-CHIP mode
+  pub memory: Vec<u8>,
+  pub memory_config: MemoryConfig,
   // 16 8-bit registers
   pub v: [u8; 16],
   // address in memory
   pub memory_addr: usize,
   // address in the program instructions
   pub program_addr: usize,
+  // the program that was last loaded and where it went, so it can be started over
+  pub program: Vec<u8>,
+  pub load_address: usize,
   // timers for keeping track of delay & sound length
   pub delay_timer: u8,
   pub sound_timer: u8,
+  // XO-CHIP's 16-byte sample pattern and the pitch it's played back at
+  pub audio_pattern: [u8; 16],
+  pub pitch: u8,
+  // the shape of the beep, which is up to whoever's listening rather than the rom
+  pub waveform: Waveform,
+  // called with true when the sound timer starts running and false when it stops, for anything embedding the
+  // emulator that wants to make its own sound, along with whether it was running last time it was checked
+  pub on_sound: Option<Box<dyn FnMut(bool)>>,
+  pub sounding: bool,
 
   // state for how the game is running
-  pub paused: bool,
+  // pausing stops everything, while the freezes stop just the instructions or just the timers for debugging
+  pub run_state: RunState,
+  // keeps the beep quiet without stopping the sound timer, so games still behave the same
+  pub muted: bool,
   pub speed: u16,
+  // whether loading a new rom picks the quirk profile by looking at the rom
+  pub auto_profile: bool,
+  // how long each instruction takes, or None for every instruction to take the same time
+  pub cycle_costs: Option<CycleCosts>,
+  // while turbo is on, speed is multiplied by TURBO_FACTOR (speed itself is left alone so it can't get lost)
+  pub turbo: bool,
+  // set by a draw when the display_wait quirk is on, so nothing else runs until the next frame
+  pub waiting_for_frame: bool,
+  // show every draw as soon as it happens instead of once at the end of the frame, which can make fast sprites look smoother
+  pub render_each_draw: bool,
+  // where the last sprite this frame was drawn, if one was
+  pub last_sprite: Option<SpriteBox>,
+  // whether sprites flip pixels like they should, or only turn them on for debugging
+  pub draw_mode: DrawMode,
+  // where 0xCxkk gets its random numbers from, which can be seeded for reproducible runs
+  pub rng: StdRng,
+  // where every random number handed out is written as it's used, along with the address of the 0xCxkk that asked,
+  // so a bug report can include the exact sequence a run got (even if it crashed)
+  pub rng_log: Option<fs::File>,
+  // random numbers to hand out in order before going back to the rng, for reproducing a logged run
+  pub injected_rng: VecDeque<u8>,
+  // program addresses that pause execution when they're reached
+  pub breakpoints: HashSet<usize>,
+  // the breakpoint we last paused at, so resuming doesn't immediately pause there again
+  pub last_breakpoint: Option<usize>,
+  // memory addresses and registers that pause execution when they change
+  pub watches: Vec<Watch>,
+  // whether XO-CHIP extensions (like the full 64KB of memory) are enabled
+  pub xo_chip: bool,
+  // skip over unknown opcodes instead of stopping, remembering which ones have been warned about already
+  pub permissive: bool,
+  // whether roms too big for memory are turned away or cut short
+  pub oversize_policy: OversizePolicy,
+  pub unknown_opcodes: HashSet<u16>,
+  // warn (once for each) about 0x0nnn calls to machine code routines, which can't be emulated and are skipped
+  pub warn_on_sys: bool,
+  // log every sprite drawn, with where it went and whether it hit anything, for debugging a game's hit detection
+  pub log_collisions: bool,
+  // instructions added from outside, which get first go at anything they match
+  pub custom_opcodes: Vec<CustomOpcode>,
+  // called at the very end of every frame, once the timers and screen are up to date, for anything embedding the
+  // emulator that wants to look at how the frame ended up
+  pub on_frame: Option<FrameCallback>,
+  // which interpreter's take on the ambiguous instructions to follow, and the profile they last came from
+  pub quirks: Quirks,
+  // a key wait picked on the command line, which sticks around whichever profile the quirks come from
+  pub forced_key_wait: Option<KeyWait>,
+  pub profile: QuirkProfile,
+
+  pub stack: Vec<usize>,
+  // how many nested subroutine calls are allowed before overflowing
+  pub stack_limit: usize,
+
+  // the digit sprites that get loaded into memory, which can be swapped for custom ones
+  pub font: [u8; 80],
+  pub big_font: [u8; 160],
+
+  // SCHIP's eight RPL user flags, which some games use to save high scores
+  pub rpl: [u8; 8],
+  // the file the rpl flags persist to, or None to keep them in memory only
+  pub rpl_file: Option<PathBuf>,
+  // where each rom's settings are saved, which is None when they shouldn't be
+  pub settings_file: Option<PathBuf>,
+  // the settings the rom started with that weren't saved for it, which only get saved once they've been changed
+  pub unchanged_settings: RomSettings,
+  // the file crash reports are written to, or None to just log them
+  pub crash_report_file: Option<PathBuf>,
+
+  // history of recent frames for rewinding
+  pub history: RewindBuffer,
+  // cheats to poke into memory, which are applied at the start of every frame
+  pub cheats: Vec<Cheat>,
+  // whether pokes can change the font, which lives below 0x200
+  pub allow_font_pokes: bool,
+  // registers and memory to set up every time a program starts
+  pub initial_state: Vec<StateDirective>,
+
+  // the replay being recorded, and the one being played back along with how far through it is (in frames, and in key changes)
+  pub input_recording: Option<Replay>,
+  pub playback: Option<Replay>,
+  pub playback_frame: u32,
+  pub playback_event: usize,
+  // a replay that's waiting for a rom to be loaded before it can start
+  pub queued_replay: Option<Replay>,
 
-  pub stack: Vec<usize>
+  // whether memory has been written to since the last frame was saved to the rewind buffer
+  pub memory_dirty: bool
 
 }
 
 impl Cpu {
 
   /**
-   * Create all the necessary data for the cpu, and create instances of each input/output struct
+   * Create all the necessary data for the cpu, and create instances of each input/output struct.
+   * This fails if there's no way to draw to the window with the given graphics backends
    */
-  pub async fn new(window: &winit::window::Window) -> Self {
+  pub async fn new(window: &winit::window::Window, backends: wgpu::Backends) -> Result<Self, DisplayError> {
 
     // create an instance of display
-    let display = Display::new(window).await;
+    let display = Display::new(window, backends).await?;
+
+    let mut cpu = Cpu::with_outputs(display, Audio::new());
+
+    // pick up any rpl flags saved by a previous session
+    // the web has no file system to persist them to, so they only last as long as the page there
+    // crash reports on the web go to the console instead, for the same reason
+    if cfg!(not(target_arch = "wasm32")) {
+      cpu.rpl_file = Some(PathBuf::from(RPL_FILE));
+      cpu.crash_report_file = Some(PathBuf::from(CRASH_REPORT_FILE));
+      cpu.settings_file = Some(PathBuf::from(SETTINGS_FILE));
+    }
+    cpu.load_rpl();
+
+    return Ok(cpu);
+
+  }
+
+  /**
+   * Create a cpu that doesn't render anywhere, useful for benchmarking and fuzzing ROMs without a window
+   */
+  pub fn headless() -> Self {
+
+    return Cpu::with_outputs(Display::headless(), Audio::silent());
+
+  }
+
+  /**
+   * Set up the rest of the cpu around an already created display and audio output
+   */
+  fn with_outputs(display: Display, audio: Audio) -> Self {
+
     let keyboard = Keyboard::new();
 
     // create the memory
-    let memory: [u8; 4096] = [0; 4096];
+    let memory_config = MemoryConfig::Standard;
+    let memory: Vec<u8> = vec![0; memory_config.size()];
     let v: [u8; 16] = [0; 16];
     let memory_addr: usize = 0;
 
     // and the timers
     let delay_timer: u8 = 0;
     let sound_timer: u8 = 0;
+    let audio_pattern = audio::DEFAULT_PATTERN;
+    let pitch = audio::DEFAULT_PITCH;
+    let waveform = Waveform::Square;
+    let on_sound: Option<Box<dyn FnMut(bool)>> = None;
+    let sounding = false;
 
     // address in the program
-    let program_addr: usize = 0x200;
+    let program_addr: usize = PROGRAM_START;
+    let program: Vec<u8> = vec![];
+    let load_address = PROGRAM_START;
 
     let stack: Vec<usize> = vec![];
+    // the original hardware had room for 16 return addresses
+    let stack_limit: usize = 16;
+
+    // the fonts start out as the built in ones
+    let font = DEFAULT_FONT;
+    let big_font = DEFAULT_BIG_FONT;
+
+    // rpl flags start empty, and aren't saved anywhere unless a file is set
+    let rpl: [u8; 8] = [0; 8];
+    let rpl_file: Option<PathBuf> = None;
+    let settings_file: Option<PathBuf> = None;
+    let unchanged_settings = RomSettings::new();
+    let crash_report_file: Option<PathBuf> = None;
+
+    let history = RewindBuffer::new(rewind::DEFAULT_CAPACITY);
+    let memory_dirty = true;
+
+    let cheats: Vec<Cheat> = vec![];
+    let allow_font_pokes = false;
+    let initial_state: Vec<StateDirective> = vec![];
+    let input_recording: Option<Replay> = None;
+    let playback: Option<Replay> = None;
+    let playback_frame: u32 = 0;
+    let playback_event: usize = 0;
+    let queued_replay: Option<Replay> = None;
 
     // state for how the game is running
     let speed: u16 = 10;
-    let paused = false;
+    let turbo = false;
+    let cycle_costs: Option<CycleCosts> = None;
+    let auto_profile = false;
+    let run_state = RunState::Active(Activity::Running);
+    let muted = false;
+    let waiting_for_frame = false;
+    let render_each_draw = false;
+    let last_sprite: Option<SpriteBox> = None;
+    let draw_mode = DrawMode::Xor;
+    let rng = StdRng::from_entropy();
+    let rng_log: Option<fs::File> = None;
+    let injected_rng: VecDeque<u8> = VecDeque::new();
+    let breakpoints: HashSet<usize> = HashSet::new();
+    let last_breakpoint: Option<usize> = None;
+    let watches: Vec<Watch> = vec![];
+    let xo_chip = false;
+    let quirks = Quirks::new();
+    let forced_key_wait = None;
+    let profile = QuirkProfile::Modern;
+    let permissive = false;
+    let oversize_policy = OversizePolicy::Reject;
+    let unknown_opcodes: HashSet<u16> = HashSet::new();
+    let warn_on_sys = false;
+    let log_collisions = false;
+    let custom_opcodes: Vec<CustomOpcode> = Vec::new();
+    let on_frame: Option<FrameCallback> = None;
 
 
-    return Cpu { display, keyboard, memory, memory_addr, program_addr, v, delay_timer, sound_timer, stack, speed, paused };
+    return Cpu { display, keyboard, audio, audio_pattern, pitch, waveform, on_sound, sounding, memory, memory_config, memory_addr, program_addr, program, load_address, v, delay_timer, sound_timer, stack, stack_limit, font, big_font, rpl, rpl_file, settings_file, unchanged_settings, crash_report_file, history, cheats, allow_font_pokes, initial_state, input_recording, playback, playback_frame, playback_event, queued_replay, memory_dirty, speed, auto_profile, cycle_costs, turbo, run_state, muted, waiting_for_frame, render_each_draw, last_sprite, draw_mode, rng, rng_log, injected_rng, breakpoints, last_breakpoint, watches, xo_chip, permissive, oversize_policy, unknown_opcodes, warn_on_sys, log_collisions, custom_opcodes, on_frame, quirks, forced_key_wait, profile };
 
   }
 
   /**
-   * chip-8 contains 16 sprites loaded into the interpreter part of the memory. This function loads them in
+   * chip-8 contains 16 sprites loaded into the interpreter part of the memory. This function loads them in,
+   * along with the SCHIP large digits right after them
    */
   pub fn load_sprites_to_memory(&mut self) {
 
-    // create the definition of all the sprites
-    // each sprite is 5 bytes long
-    let sprites: [u8; 80] = [
-      0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-      0x20, 0x60, 0x20, 0x20, 0x70, // 1
-      0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-      0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-      0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-      0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-      0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-      0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-      0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-      0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-      0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-      0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-      0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-      0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-      0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-      0xF0, 0x80, 0xF0, 0x80, 0x80  // F
-    ];
-
     // load these into the system's memory
-    for (i, byte) in sprites.into_iter().enumerate() {
+    self.memory[FONT_START..FONT_START + 80].copy_from_slice(&self.font);
+    self.memory[BIG_FONT_START..BIG_FONT_START + 160].copy_from_slice(&self.big_font);
 
-      // move the sprite into the same spot in our general memory
-      self.memory[i] = byte;
+  }
 
-    }
+  /**
+   * Swap in a different set of 5 byte digit sprites, which stays in place through resets and new roms
+   */
+  pub fn load_font(&mut self, font: &[u8; 80]) {
+
+    self.font = *font;
+    self.load_sprites_to_memory();
+
+  }
+
+  /**
+   * Swap in a different set of SCHIP's large 10 byte digit sprites, which stays in place the same way
+   */
+  pub fn load_big_font(&mut self, font: &[u8; 160]) {
+
+    self.big_font = *font;
+    self.load_sprites_to_memory();
 
   }
 
   /**
    * Load the data from a ROM into the system's memory, starting from spot 0x200 as the spec defines
    */
-  pub fn load_program_to_memory(&mut self, bytes: Vec<u8>) {
+  pub fn load_program_to_memory(&mut self, bytes: Vec<u8>) -> Result<usize, LoadError> {
 
-    // iterate over the bytes, and add them to memory, starting from 0x200
-    for (i, byte) in bytes.into_iter().enumerate() {
+    return self.load_program_to_memory_at(bytes, PROGRAM_START);
 
-      self.memory[0x200 + i] = byte;
+  }
+
+  /**
+   * Load a ROM starting somewhere other than 0x200, like 0x600 for ETI-660 programs, and start running from there.
+   * Returns how many bytes were loaded, which is less than all of them if the ROM was too big and got truncated
+   */
+  pub fn load_program_to_memory_at(&mut self, bytes: Vec<u8>, load_address: usize) -> Result<usize, LoadError> {
+
+    // make sure the program fits (or can be cut down to) before touching anything
+    let length = self.check_fits(bytes.len(), load_address, self.memory_size())?;
+    if length < bytes.len() {
+      log::warn!("The program is {} bytes, but there's only room for {}, so the last {} were left out", bytes.len(), length, bytes.len() - length);
+    }
+
+    // hang on to the program in case it needs to be reset, all of it even if it was truncated
+    self.program = bytes.clone();
+    self.load_address = load_address;
+
+    // instructions are 2 bytes, so an odd length means the last instruction will be read with whatever is after it
+    if length % 2 == 1 {
+      log::debug!("The program is an odd number of bytes ({}), so its last byte is only half an instruction", length);
+    }
+
+    // iterate over the bytes, and add them to memory, starting from the load address
+    for (i, byte) in bytes.into_iter().take(length).enumerate() {
+
+      self.memory[load_address + i] = byte;
+
+    }
+
+    // every byte should have made it in, including an odd one on the end
+    debug_assert!(length == 0 || self.memory[load_address + length - 1] == self.program[length - 1]);
 
+    // the program starts running from wherever it was loaded
+    self.program_addr = load_address;
+
+    // there's nothing to rewind to from before the program existed
+    self.history.clear();
+    self.memory_dirty = true;
+    // and a new program hasn't finished yet
+    if self.run_state == RunState::Halted {
+      self.run_state = RunState::Active(Activity::Running);
     }
 
+    return Ok(length);
+
   }
 
   /**
-   * cycle runs 60 times per second, executing instructions
+   * Start the current program over from scratch, as if it had just been loaded. This is also how a halted program gets going again
    */
-  pub fn cycle(&mut self) {
+  pub fn reset(&mut self) {
 
-    // run however many instructions are specified in the speed variable
-    for _i in 0..self.speed {
+    // the program's copied rather than taken, so it's still there if reloading it somehow fails
+    let program = self.program.clone();
+    if let Err(error) = self.load_new_rom(program) {
+      log::error!("Couldn't reload the program: {}", error);
+    }
+
+  }
+
+  /**
+   * Swap to a different program while running, clearing out everything the last one left behind.
+   * If the new program can't be loaded, the old one carries on untouched
+   */
+  pub fn load_new_rom(&mut self, bytes: Vec<u8>) -> Result<usize, LoadError> {
 
-      // only run certain functions if the system is unpaused
-      if !self.paused && !self.keyboard.awaiting_keypress {
+    // hang on to the last rom's settings, and see if this one has any
+    self.save_rom_settings();
+    let saved = self.settings_file.as_ref().and_then(| path | settings::load_for(path, &bytes));
 
-        // if we just resumed from a keyboard-awaiting pause, write that keypress down
-        if self.keyboard.handle_resume {
-          self.handle_resume();
+    // match whichever interpreter the rom looks like it was written for, unless one was saved for it
+    let profile = if self.auto_profile {
+      Some(match saved.as_ref().and_then(| saved | saved.profile) {
+        Some(profile) => profile,
+        None => {
+          let mode = detect::detect_mode(&bytes, self.load_address);
+          log::info!("This looks like a {:?} ROM", mode);
+          mode.profile()
         }
+      })
+    } else {
+      None
+    };
 
-        // figure out the operation we're running
-        let instruction = (self.memory[self.program_addr] as u16) << 8 | self.memory[self.program_addr + 1] as u16;
-        // execute the instruction
-        self.execute_instruction(instruction);
-      }
+    // make sure it fits in the memory it'll have before switching over to it, so the last rom can carry on if it doesn't
+    let memory_size = profile.map_or(self.memory_size(), | profile | MemoryConfig::for_profile(profile).size());
+    self.check_fits(bytes.len(), self.load_address, memory_size)?;
+    if let Some(profile) = profile {
+      self.set_profile(profile);
+    }
+
+    // a rom that goes straight to high resolution might as well start there
+    let hires = self.auto_profile && detect::starts_in_hires(&bytes, self.load_address);
 
+    self.clear_state();
+    if hires {
+      self.display.set_resolution(display::HIRES_WIDTH, display::HIRES_HEIGHT);
     }
+    let size = self.load_program_to_memory_at(bytes, self.load_address)?;
+    self.set_up_initial_state();
+    // loading isn't a change any watches care about
+    self.sync_watches();
 
-    // only run if unpaused
-    if !self.paused && !self.keyboard.awaiting_keypress {
-      // update the timers
-      self.update_timers();
+    // anything that wasn't saved is whatever it's been left as, which isn't worth saving unless it's changed
+    let saved = saved.unwrap_or_else(RomSettings::new);
+    self.apply_rom_settings(&saved);
+    self.unchanged_settings = self.current_settings().changed_from(&saved);
+
+    // whatever replay was playing was for the last program, but one that's been waiting for a program can start now
+    self.playback = None;
+    if let Some(replay) = self.queued_replay.take() {
+      if let Err(error) = self.start_replay(replay) {
+        log::error!("Couldn't play the replay: {}", error);
+      }
     }
 
-    // cause a new render
-    // update the visual data and then render
-    self.display.update();
-    self.display.render();
+    return Ok(size);
 
   }
 
   /**
-   * If the timers are not equal to 0, lower their value by 1 per cycle
+   * Make sure a program of some length fits between where it'd be loaded and the end of memory_size bytes of memory, returning
+   * how much of it gets loaded. With the truncate policy that's as much as fits, otherwise a program that doesn't fit is an error
    */
-  pub fn update_timers(&mut self) {
+  fn check_fits(&self, size: usize, load_address: usize, memory_size: usize) -> Result<usize, LoadError> {
 
-    // if the delay timer is greater than 0, make it smaller :)
-    if self.delay_timer > 0 {
-      self.delay_timer -= 1;
-    }
-    if self.sound_timer > 0 {
-      self.sound_timer -= 1;
+    let available = memory_size.saturating_sub(load_address);
+    if size <= available {
+      return Ok(size);
     }
 
+    return match self.oversize_policy {
+      OversizePolicy::Reject => Err(LoadError::TooLarge { size, available }),
+      OversizePolicy::Truncate => Ok(available)
+    };
+
   }
 
   /**
-   * When resuming from a wait for key, that key must be stored in the location specified in the instruction
+   * Put everything back how it was when the cpu was created, except for the settings
    */
-  pub fn handle_resume(&mut self) {
+  fn clear_state(&mut self) {
 
-    // find the instruction tht induced the pause
-    let instruction = (self.memory[self.program_addr] as u16) << 8 | self.memory[self.program_addr + 1] as u16;
+    // wipe memory, the registers and the timers
+    self.memory = vec![0; self.memory_size()];
+    self.load_sprites_to_memory();
+    self.v = [0; 16];
+    self.memory_addr = 0;
+    self.stack.clear();
+    self.delay_timer = 0;
+    self.sound_timer = 0;
+    self.audio_pattern = audio::DEFAULT_PATTERN;
+    self.pitch = audio::DEFAULT_PITCH;
+    self.update_sound();
 
-    // find the x position to store the last keypress
-    let x = ((instruction & 0x0F00) >> 8) as usize;
+    // along with the screen, on every plane, which goes back to low resolution
+    self.display.set_resolution(display::WIDTH, display::HEIGHT);
+    self.display.plane = 1;
 
-    // finally, write the most recent keypress to v[x]
-    self.v[x] = self.keyboard.latest_key;
+    // and anything it was waiting on
+    self.keyboard.clear();
+    self.keyboard.stop_waiting();
+    self.waiting_for_frame = false;
+    self.last_sprite = None;
+    self.unknown_opcodes.clear();
+    self.run_state = RunState::Active(Activity::Running);
 
   }
 
   /**
-   * The big one: take in an instruction code and determine what to do
+   * Parse a program written as hex, like "00E0 A20A 6000", and load it into memory.
+   * Tokens can be split up by whitespace or commas and may start with 0x
    */
-  pub fn execute_instruction(&mut self, instruction: u16) {
+  pub fn load_program_from_hex(&mut self, hex: &str) -> Result<usize, LoadError> {
 
-    // update program address so the next instruction is run
-    self.program_addr += 2;
+    let bytes = parse_hex(hex)?;
+    return self.load_new_rom(bytes);
 
-    // x and y values, located at 0xy0 in the instruction, are used in
-    // many different instructions, so they should be grabbed now to
-    // reduce repetition of code
-    // use the & operation to isolate only the specific 4 bits we want
-    let x = ((instruction & 0x0F00) >> 8) as usize;
-    let y = ((instruction & 0x00F0) >> 4) as usize;
+  }
 
-    // match different instructions, starting off with the greatest value (the first 4 bits)
-    // and then narrowing down depending on the instruction
-    // instructions can be referenced in the technical specification, simple
-    // explainers will be included in comments here
-    match instruction & 0xF000 {
+  /**
+   * cycle runs 60 times per second, executing instructions
+   */
+  pub fn cycle(&mut self) {
 
-      // there's two options for what a 0x0 instruction could be
-      0x0000 => match instruction {
+    // it's a new frame, so anything that drew last frame can carry on
+    self.waiting_for_frame = false;
+    // and the sprite highlight only lasts a frame, unless nothing's going to run to replace it
+    if self.can_execute() {
+      self.last_sprite = None;
+    }
 
-        // clear the screen
-        0x00E0 => self.display.clear(),
-        // exit a subroutine by setting the program counter to the top of the stack
-        0x00EE => self.program_addr = self.stack.pop().unwrap() as usize,
-        // nothing else is real so it can be safely ignored
-        _ => ()
+    // put any cheats in place before the game gets a chance to use them
+    self.apply_cheats();
+    // let go of any debounced keys that have been held long enough
+    // (before the replay, so a recording sees the release on the same frame the game does)
+    self.keyboard.tick();
+    // along with the keys from a replay, or remember the keys for one
+    self.update_replay();
+
+    // if a key was pressed while we were waiting for one, write that keypress down and carry on
+    if self.run_state == RunState::Active(Activity::AwaitingKey) && self.keyboard.latest_key.is_some() {
+      self.handle_resume();
+    }
+
+    // run however many instructions are specified in the speed variable
+    // (or with cycle costs, however many fit in that many simple instructions' worth of time)
+    let budget = self.instructions_per_frame() as u32;
+    let mut spent: u32 = 0;
+    while spent < budget {
 
+      // nothing runs while paused, halted, waiting for a key or frozen
+      if !self.can_execute() {
+        break;
       }
 
-      // jump to address nnn where nnn is the last 12 bits in the instruction
-      0x1000 => self.program_addr = (instruction & 0x0FFF) as usize,
+      // stop before running the instruction if there's a breakpoint here
+      if self.check_breakpoint() {
+        break;
+      }
 
-      // add the current address to the stack and start a subroutine at the last 12 bits
-      0x2000 => {
-        self.stack.push(self.program_addr);
-        self.program_addr = (instruction & 0x0FFF) as usize;
-      },
+      // figure out the operation we're running
+      let addr = self.program_addr;
+      let instruction = self.fetch_instruction();
+      spent += self.instruction_cost(instruction);
+      // execute the instruction, halting if something went wrong so the window stays responsive
+      if let Err(error) = self.run_instruction(instruction) {
+        log::error!("Halting at {:#05X}: {}", addr, error);
+        self.save_crash_report(&self.crash_report(&error, addr, instruction));
+        self.pause();
+        break;
+      }
+      // and stop if it changed anything being watched
+      if self.check_watches() {
+        break;
+      }
 
-      // if the value of v[x] equals the second byte, skip the next instruction
-      0x3000 => if self.v[x] as u16 == instruction & 0x00FF {
-        self.program_addr += 2;
-      },
+      // put the sprite on screen straight away if every draw is being shown
+      if self.render_each_draw && instruction & 0xF000 == 0xD000 {
+        self.display.sprite_box = self.last_sprite;
+        self.display.render_now();
+      }
 
-      // if the value of v[x] DOESN'T equal the second byte, skip the next instruction
-      0x4000 => if self.v[x] as u16 != instruction & 0x00FF {
-        self.program_addr += 2;
-      },
+      // a draw with display_wait on ends the frame early
+      if self.waiting_for_frame {
+        break;
+      }
 
-      // if v[x] equals v[y], skip the next instruction
-      0x5000 => if self.v[x] == self.v[y] {
-        self.program_addr += 2;
-      },
+    }
 
-      // set v[x] to the second byte
-      0x6000 => self.v[x] = (instruction & 0x00FF) as u8,
+    // only run if unpaused (and not waiting on a key), and whichever of them isn't frozen
+    let cpu_running = self.can_execute();
+    let timers_running = self.timers_running();
+    // update the timers
+    if timers_running {
+      self.update_timers();
+    } else {
+      // which is what normally keeps the beep up to date, so it needs telling that it's stopped (or been muted) without them
+      self.update_sound();
+    }
+    // and remember how this frame ended up, in case we want to come back to it
+    if cpu_running || timers_running {
+      self.record_frame();
+    }
 
-      // add the second byte to v[x]
-      // make sure it overflows gracefully using bitwise operators
-      0x7000 => self.v[x] = ((self.v[x] as u16 + (instruction & 0xFF)) & 0xFF) as u8,
+    // let the display know if we're waiting on a key, so it can show that the game hasn't just frozen
+    self.display.awaiting_keypress = self.is_awaiting_key();
+    // and where the last sprite went, in case it's being highlighted
+    self.display.sprite_box = self.last_sprite;
 
+    // cause a new render
+    // update the visual data and then render
+    self.display.update();
+    self.display.render();
 
-      // 0x8000 series performs operations on the contents of the v store
-      // all use x and y values, specify different operation using the last
-      // four bits
-      0x8000 => match instruction & 0xF {
+    // now everything's settled, let whoever's listening see the frame
+    // the callback's taken out while it runs, since it needs the cpu it lives in
+    if let Some(mut callback) = self.on_frame.take() {
+      callback(self);
+      // unless it somehow got replaced in the meantime, it goes back for next frame
+      if self.on_frame.is_none() {
+        self.on_frame = Some(callback);
+      }
+    }
 
-        // sets store x to the value of store y
-        0x0 => self.v[x] = self.v[y],
-        // store bitwise OR on v[x] and v[y] in v[x]
-        0x1 => self.v[x] = self.v[x] | self.v[y],
-        // store bitwise AND on v[x] and v[y] in v[x]
-        0x2 => self.v[x] = self.v[x] & self.v[y],
-        // store bitwise XOR on v[x] and v[y] in v[x]
-        0x3 => self.v[x] = self.v[x] ^ self.v[y],
-        // add v[x] and v[y] together, storing extra bit in v[0xF]
-        0x4 => {
-          // add them together
-          let sum = self.v[x] as u16 + self.v[y] as u16;
-          // set v[15] to whether or not sum is greater than the max
-          self.v[15] = (sum > 255) as u8;
-          // set v[x] to the sum, cutting off any potential overflow
-          self.v[x] = (sum & 0xFF) as u8;
-        },
-        // v[y] is subtracted from v[x]. v[15] = v[y] > v[x]
-        0x5 => {
-          let difference = self.v[x] as i16 - self.v[y] as i16;
-          self.v[15] = (self.v[x] > self.v[y]) as u8;
-          self.v[x] = (difference & 0xFF) as u8;
-        },
-        // divide v[x] by 2, and set v[15] to the most significant bit of v[x]
-        0x6 => {
-          // if v[x] is >= 128, the 8th bit must be 1
-          self.v[15] = (self.v[x] >= 128) as u8;
-          self.v[x] /= 2;
-        },
-        // v[x] is subtracted from v[y]. v[15] = v[x] > v[y]
-        0x7 => {
-          let difference = self.v[y] as i16 - self.v[x] as i16;
-          self.v[15] = (self.v[y] > self.v[x]) as u8;
-          self.v[x] = (difference & 0xFF) as u8;
-        },
-        // multiply v[x] by 2, and set v[15] to the most significant bit of v[x]
-        0xE => {
-          let product = self.v[x] as u16 * 2;
-          // if v[x] is >= 128, the 8th bit must be 1
-          self.v[15] = (self.v[x] >= 128) as u8;
-          self.v[x] = (product & 0xFF) as u8;
-        },
+  }
 
-        // no other options
-        _ => ()
+  /**
+   * Execute a fixed number of instructions as fast as possible, returning how many actually ran.
+   * This stops early if the cpu pauses or starts waiting on a key, and only ticks the timers and
+   * display once every `speed` instructions, just like a real frame would. Any fault is returned
+   */
+  pub fn run_for(&mut self, instructions: u64) -> Result<u64, CpuError> {
 
-      },
+    let mut executed: u64 = 0;
+    // how much of the current simulated frame has been used up
+    let mut frame_spent: u32 = 0;
 
-      // skip next instruction if v[x] DOESN'T equal v[y]
-      0x9000 => if self.v[x] != self.v[y] {
-        self.program_addr += 2;
-      },
+    while executed < instructions {
 
-      // set the i store (memory_addr) to the last 12 bits of the instruction
-      0xA000 => self.memory_addr = instruction as usize & 0xFFF,
+      // same as in cycle, write down the keypress we just resumed from
+      if self.run_state == RunState::Active(Activity::AwaitingKey) && self.keyboard.latest_key.is_some() {
+        self.handle_resume();
+      }
 
-      // the program counter (program_addr) is set to the last 12 bits + v[0]
-      0xB000 => self.program_addr = (instruction as usize & 0xFFF) + self.v[0] as usize,
+      // nothing more can be done until the cpu is unpaused or a key is pressed, or ever if it's halted
+      if !self.can_execute() {
+        break;
+      }
 
-      // a random number between 0 and 255 is generated and ANDed with kk, then stored in v[x]
-      // where 0xCxkk
-      0xC000 => {
-        let kk = (instruction & 0xFF) as u8;
-        let random: u8 = rand::random();
-        // now store it
-        self.v[x] = random & kk;
-      },
+      if self.check_breakpoint() {
+        break;
+      }
 
-      // read sprite from memory at position i (memory_addr) for n bytes, rendering starting at x, y
-      // formatted as 0xDxyn
-      // the sprite XOR's onto the display, in simple words meaning it flips the state where the bit = 1
-      0xD000 => {
+      let instruction = self.fetch_instruction();
+      frame_spent += self.instruction_cost(instruction);
+      self.run_instruction(instruction)?;
+      executed += 1;
 
-        // get the length of bytes, which is the last 4 bits in the instruction
-        let n = instruction as usize & 0xF;
-        // whether or not a pixel was turned off (that needs to be stored in memory later)
-        self.v[15] = 0;
+      if self.check_watches() {
+        break;
+      }
 
-        // run through the bytes, which make up rows
-        for row in 0..n {
+      // once a frame's worth of instructions has run (or a draw is waiting for the next frame), update the timers and visual data
+      if frame_spent >= self.instructions_per_frame() as u32 || self.waiting_for_frame {
+        frame_spent = 0;
+        self.waiting_for_frame = false;
+        if !self.timers_frozen() {
+          self.update_timers();
+        }
+        self.display.update();
+      }
 
-          // grab the byte
-          let mut byte = self.memory[self.memory_addr + row];
-          // and now each bit, which make up the columns
-          for col in 0..8 {
+    }
 
-            // if the bit at the end is NOT zero, change the pixel!
-            if (byte & 0x80) > 0 {
-              // also keep track of whether a pixel was changed here
-              self.display.set_pixel(self.v[x] as i32 + col, self.v[y] as i32 + row as i32);
+    return Ok(executed);
 
-              // set the end number to 1
-              self.v[15] = 1;
+  }
 
-            }
+  /**
+   * Copy out a range of memory, cut down to fit inside the addressable space
+   */
+  pub fn dump_memory(&self, range: Range<usize>) -> Vec<u8> {
 
-            // shift the byte over by one to the left to move the next column to first
-            byte = byte << 1;
+    let end = range.end.min(self.memory_size());
+    let start = range.start.min(end);
 
-          }
+    return self.memory[start..end].to_vec();
 
-        }
+  }
 
-      },
+  /**
+   * Copy out v[0] through v[F]
+   */
+  pub fn dump_registers(&self) -> [u8; 16] {
 
-      // there's two options here
-      0xE000 => match instruction & 0xFF {
+    return self.v;
 
-        // skip next instruction if key stored in v[x] is pressed
-        0x9E => if self.keyboard.is_key_pressed(self.v[x]) {
-          self.program_addr += 2;
-        },
-        // skip next instruction if key stored in v[x] ISN'T pressed
-        0xA1 => if !self.keyboard.is_key_pressed(self.v[x]) {
-          self.program_addr += 2;
-        }
+  }
+
+  /**
+   * The program counter
+   */
+  pub fn get_pc(&self) -> usize {
+
+    return self.program_addr;
+
+  }
+
+  /**
+   * The i register (memory_addr)
+   */
+  pub fn get_i(&self) -> usize {
+
+    return self.memory_addr;
+
+  }
+
+  pub fn get_delay_timer(&self) -> u8 {
+
+    return self.delay_timer;
+
+  }
+
+  pub fn get_sound_timer(&self) -> u8 {
+
+    return self.sound_timer;
+
+  }
+
+  /**
+   * Save the current state to the rewind buffer. Memory and pixels are only copied if they've
+   * changed since the last frame, otherwise the previous frame's copy is shared
+   */
+  pub fn record_frame(&mut self) {
+
+    let previous = self.history.latest();
+
+    let memory = match previous {
+      Some(state) if !self.memory_dirty => state.memory.clone(),
+      _ => Rc::new(self.memory.clone())
+    };
+    let pixels = match previous {
+      Some(state) if *state.pixels == self.display.pixels => state.pixels.clone(),
+      _ => Rc::new(self.display.pixels.clone())
+    };
+
+    let state = SavedState {
+      v: self.v,
+      memory_addr: self.memory_addr,
+      program_addr: self.program_addr,
+      delay_timer: self.delay_timer,
+      sound_timer: self.sound_timer,
+      stack: self.stack.clone(),
+      memory,
+      pixels
+    };
+
+    self.history.push(state);
+    self.memory_dirty = false;
+
+  }
+
+  /**
+   * Go back the given number of frames, returning whether there was any history to go back to
+   */
+  pub fn rewind(&mut self, frames: usize) -> bool {
+
+    let state = match self.history.rewind(frames) {
+      Some(state) => state,
+      None => return false
+    };
+
+    self.v = state.v;
+    self.memory_addr = state.memory_addr;
+    self.program_addr = state.program_addr;
+    self.delay_timer = state.delay_timer;
+    self.sound_timer = state.sound_timer;
+    self.stack = state.stack;
+    // the saved memory might be a different size if the memory config has changed since
+    let length = state.memory.len().min(self.memory.len());
+    self.memory[..length].copy_from_slice(&state.memory[..length]);
+    // the frame might have been at a different resolution
+    if state.pixels.len() != self.display.height || state.pixels[0].len() != self.display.width {
+      self.display.set_resolution(state.pixels[0].len(), state.pixels.len());
+    }
+    self.display.pixels = (*state.pixels).clone();
+
+    // memory now matches the latest saved frame again
+    self.memory_dirty = false;
+    // and going back in time isn't a change any watches care about
+    self.sync_watches();
+
+    return true;
+
+  }
+
+  /**
+   * Pause whenever the program counter reaches addr
+   */
+  pub fn add_breakpoint(&mut self, addr: usize) {
+
+    self.breakpoints.insert(addr);
+
+  }
+
+  /**
+   * Stop pausing at addr
+   */
+  pub fn remove_breakpoint(&mut self, addr: usize) {
+
+    self.breakpoints.remove(&addr);
+
+  }
+
+  /**
+   * Pause whenever the value at target changes
+   */
+  pub fn add_watch(&mut self, target: WatchTarget) {
+
+    let value = self.watched_value(target);
+    self.watches.push(Watch { target, value });
+
+  }
+
+  /**
+   * Stop watching target, if it was being watched
+   */
+  pub fn remove_watch(&mut self, target: WatchTarget) {
+
+    self.watches.retain(|watch| watch.target != target);
+
+  }
+
+  /**
+   * The byte a watch is looking at right now
+   */
+  fn watched_value(&self, target: WatchTarget) -> u8 {
+
+    return match target {
+      WatchTarget::Memory(addr) => self.read_memory(addr),
+      WatchTarget::Register(register) => self.v[register & 0xF]
+    };
+
+  }
+
+  /**
+   * If anything being watched has changed, report it, pause and return true
+   */
+  fn check_watches(&mut self) -> bool {
+
+    // nothing to do most of the time
+    if self.watches.is_empty() {
+      return false;
+    }
+
+    let mut changed = false;
+    for i in 0..self.watches.len() {
+      let watch = self.watches[i];
+      let value = self.watched_value(watch.target);
+      if value != watch.value {
+        // the program counter has already moved past the instruction that did it
+        log::info!("{} changed from {:#04X} to {:#04X}, just before {:#05X}", watch.target, watch.value, value, self.program_addr);
+        self.watches[i].value = value;
+        changed = true;
+      }
+    }
+
+    if changed {
+      self.pause();
+    }
+    return changed;
+
+  }
+
+  /**
+   * Catch every watch up with its current value, after a change that isn't the program's doing
+   */
+  fn sync_watches(&mut self) {
 
-        // no other options
-        _ => ()
+    for i in 0..self.watches.len() {
+      self.watches[i].value = self.watched_value(self.watches[i].target);
+    }
+
+  }
+
+  /**
+   * If the program counter is at a breakpoint, pause and return true. Resuming from a
+   * breakpoint lets that instruction run rather than pausing on it again
+   */
+  fn check_breakpoint(&mut self) -> bool {
+
+    // we just resumed from this breakpoint, so carry on past it this time
+    if self.last_breakpoint == Some(self.program_addr) {
+      self.last_breakpoint = None;
+      return false;
+    }
+
+    if self.breakpoints.contains(&self.program_addr) {
+      self.pause();
+      self.last_breakpoint = Some(self.program_addr);
+      log::info!("Hit breakpoint at {:#05X}", self.program_addr);
+      return true;
+    }
+
+    return false;
+
+  }
+
+  /**
+   * Execute an instruction the same as execute_instruction, except that in permissive mode unknown opcodes are skipped.
+   * Each different one gets logged the first time it turns up
+   */
+  fn run_instruction(&mut self, instruction: u16) -> Result<usize, CpuError> {
 
+    return match self.execute_instruction(instruction) {
+      // the program counter has already moved past it, so there's nothing else to do
+      Err(CpuError::UnknownOpcode(opcode)) if self.permissive => {
+        let length = self.instruction_length(instruction);
+        if self.unknown_opcodes.insert(opcode) {
+          log::warn!("Skipping unknown opcode {:#06X} at {:#05X}", opcode, self.program_addr - length);
+        }
+        Ok(length)
       },
+      result => result
+    };
 
-      // there's nine options here
-      0xF000 => match instruction & 0xFF {
+  }
 
-        // put the value of the delay timer into v[x]
-        0x07 => self.v[x] = self.delay_timer,
+  /**
+   * Run exactly one instruction, even while paused, for stepping through a program in the debugger.
+   * Nothing happens if the program has finished or is waiting on a key
+   */
+  pub fn step(&mut self) -> Result<(), CpuError> {
 
-        // pause execution until a key is pressed
-        0x0A => {
-          // keyboard.rs handles this, simply just pause execution
-          self.keyboard.awaiting_keypress = true;
-        },
+    self.step_once()?;
+    return Ok(());
 
-        // set delay timer to v[x]
-        0x15 => self.delay_timer = self.v[x],
+  }
 
-        // set sound timer to v[x]
-        0x18 => self.sound_timer = self.v[x],
+  /**
+   * The step itself, returning whether the instruction changed anything being watched
+   */
+  fn step_once(&mut self) -> Result<bool, CpuError> {
 
-        // add v[x] to i (memory_addr)
-        0x1E => self.memory_addr += self.v[x] as usize,
+    if self.is_halted() {
+      return Ok(false);
+    }
 
-        // i (memory_addr) is set to the address for the sprite x,
-        // which is v[x] * 5 since the sprites start from position 0 and
-        // sprites are 5 bytes long
-        0x29 => self.memory_addr = self.v[x] as usize * 5,
+    // same as in cycle, write down the keypress we just resumed from
+    // (which might turn out not to have been a real one, leaving it still waiting)
+    if self.is_awaiting_key() && self.keyboard.latest_key.is_some() {
+      self.handle_resume();
+    }
+    if self.is_awaiting_key() {
+      return Ok(false);
+    }
 
-        // store the decimal digits of v[x] in memory locations i, i+1, and i+2
-        0x33 => {
-          // hundreds digit
-          self.memory[self.memory_addr] = self.v[x] / 100;
-          // tens digit - first eliminate the ones digit then the hundreds
-          self.memory[self.memory_addr + 1] = (self.v[x] / 10) % 10;
-          // finally, the ones digit
-          self.memory[self.memory_addr + 2] = self.v[x] % 10;
-        },
+    let instruction = self.fetch_instruction();
+    self.run_instruction(instruction)?;
 
-        // store v[0] through v[x] in memory, starting at memory_addr
-        0x55 => for i in 0..(x + 1) {
+    return Ok(self.check_watches());
 
-          self.memory[self.memory_addr + i] = self.v[i];
+  }
 
-        },
+  /**
+   * Step over a subroutine call, running until it returns. Anything that isn't a call is just a normal step.
+   * This stops early at breakpoints, or if the subroutine still hasn't returned after STEP_OVER_LIMIT instructions
+   */
+  pub fn step_over(&mut self) -> Result<(), CpuError> {
 
-        // read v[0] through v[15] from memory, starting at memory_addr
-        0x65 => for i in 0..(x + 1) {
+    let is_call = self.current_opcode() & 0xF000 == 0x2000;
+    // the call is done once the stack is back down to where it started
+    let depth = self.stack.len();
 
-          self.v[i] = self.memory[self.memory_addr + i];
+    if self.step_once()? || !is_call {
+      return Ok(());
+    }
 
-        },
+    let mut executed: u64 = 0;
+    while self.stack.len() > depth {
+
+      // anything that would stop the program running stops the step too
+      if self.is_halted() || self.is_awaiting_key() || self.check_breakpoint() {
+        return Ok(());
+      }
+      if executed >= STEP_OVER_LIMIT {
+        log::warn!("Gave up stepping over the call after {} instructions", STEP_OVER_LIMIT);
+        return Ok(());
+      }
+
+      if self.step_once()? {
+        return Ok(());
+      }
+      executed += 1;
+
+    }
+
+    return Ok(());
+
+  }
+
+  /**
+   * Take a copy of the registers, timers and stack so the debug tools don't need to poke at the cpu directly
+   */
+  pub fn debug_snapshot(&self) -> CpuSnapshot {
+
+    return CpuSnapshot {
+      v: self.v,
+      i: self.memory_addr,
+      pc: self.program_addr,
+      stack: self.stack.clone(),
+      delay_timer: self.delay_timer,
+      sound_timer: self.sound_timer,
+      speed: self.instructions_per_frame(),
+      paused: self.is_paused(),
+      muted: self.muted,
+      profile: self.profile
+    };
+
+  }
+
+  /**
+   * Write up everything about a crash that would help figure out what went wrong: the error, the instruction and where it was,
+   * all the registers and the stack, and a disassembly of the instructions around it
+   */
+  pub fn crash_report(&self, error: &CpuError, addr: usize, instruction: u16) -> String {
+
+    let mut report = String::from("emul8 crash report\n\n");
+    report += &format!("error: {}\n", error);
+    report += &format!("instruction: {:04X} at {:#05X}\n\n", instruction, addr);
 
-        // no other options
-        _ => ()
+    // the registers, four to a line
+    for (row, registers) in self.v.chunks(4).enumerate() {
+      let line: Vec<String> = registers.iter().enumerate().map(|(i, value)| format!("V{:X}={:02X}", row * 4 + i, value)).collect();
+      report += &line.join(" ");
+      report += "\n";
+    }
+    report += &format!("I={:04X} DT={:02X} ST={:02X}\n", self.memory_addr, self.delay_timer, self.sound_timer);
+    let stack: Vec<String> = self.stack.iter().map(|addr| format!("{:03X}", addr)).collect();
+    report += &format!("stack: [{}]\n\n", stack.join(", "));
+
+    // then the instructions around the crash, pointing out the one that did it
+    let start = addr.saturating_sub(CRASH_REPORT_WINDOW * 2);
+    for line_addr in (start..=addr + CRASH_REPORT_WINDOW * 2).step_by(2) {
+      let word = (self.read_memory(line_addr) as u16) << 8 | self.read_memory(line_addr + 1) as u16;
+      let marker = if line_addr == addr { ">" } else { " " };
+      report += &format!("{} {:03X}: {:04X}  {}\n", marker, line_addr, word, asm::disassemble(word));
+    }
+
+    return report;
+
+  }
+
+  /**
+   * Write a crash report to the crash report file, or to the log if there isn't one
+   */
+  pub fn save_crash_report(&self, report: &str) {
 
+    match &self.crash_report_file {
+      Some(path) => match fs::write(path, report) {
+        Ok(()) => log::error!("Wrote a crash report to {}", path.display()),
+        Err(error) => log::error!("Couldn't write a crash report to {}: {}\n{}", path.display(), error, report)
       },
+      None => log::error!("{}", report)
+    }
+
+  }
+
+  /**
+   * Read the rpl flags back from their file, if there is one from a previous session
+   */
+  pub fn load_rpl(&mut self) {
+
+    let path = match &self.rpl_file {
+      Some(path) => path,
+      None => return
+    };
 
-      // if any instruction is encountered that isn't yet implemented, give a todo
-      _ => {
-        println!("Instruction {} couldn't be processed", instruction);
-        todo!();
+    // a missing file just means nothing's been saved yet
+    if let Ok(bytes) = fs::read(path) {
+      for (i, byte) in bytes.into_iter().take(8).enumerate() {
+        self.rpl[i] = byte;
       }
+    }
+
+  }
+
+  /**
+   * Write the rpl flags out to their file so they're still around next session
+   */
+  pub fn save_rpl(&self) {
 
+    if let Some(path) = &self.rpl_file {
+      if let Err(error) = fs::write(path, self.rpl) {
+        log::warn!("Couldn't save the RPL flags to {}: {}", path.display(), error);
+      }
     }
 
   }
 
-}
\ No newline at end of file
+  /**
+   * The settings that get remembered for the rom that's running, which are the ones saved for it already
+   * and any that have been changed since it was loaded. A profile that was only ever detected isn't one of them
+   */
+  pub fn rom_settings(&self) -> RomSettings {
+
+    return self.current_settings().changed_from(&self.unchanged_settings);
+
+  }
+
+  // every setting as it is right now
+  fn current_settings(&self) -> RomSettings {
+
+    return RomSettings {
+      speed: Some(self.speed),
+      profile: Some(self.profile),
+      palette: Some(self.display.palette),
+      keys: self.keyboard.bindings()
+    };
+
+  }
+
+  /**
+   * Switch over to settings that were saved for a rom. The profile is left alone, since it's picked before loading
+   */
+  pub fn apply_rom_settings(&mut self, settings: &RomSettings) {
+
+    if let Some(speed) = settings.speed {
+      self.speed = speed.max(1);
+    }
+    if let Some(palette) = settings.palette {
+      self.display.set_palette(palette);
+    }
+    if let Some(keys) = settings.keys {
+      self.keyboard.set_bindings(keys);
+    }
+
+  }
+
+  /**
+   * Remember the settings for the rom that's running, so they come back next time it's loaded
+   */
+  pub fn save_rom_settings(&self) {
+
+    let path = match &self.settings_file {
+      Some(path) if !self.program.is_empty() => path,
+      _ => return
+    };
+
+    // a rom nobody's changed anything for doesn't need a line (and can't have had one, or it'd have settings)
+    let settings = self.rom_settings();
+    if settings == RomSettings::new() {
+      return;
+    }
+    if let Err(error) = settings::save_for(path, &self.program, &settings) {
+      log::warn!("Couldn't save this ROM's settings to {}: {}", path.display(), error);
+    }
+
+  }
+
+  /**
+   * Get ready for the emulator to close: stop any beep that's playing and make sure the rpl flags are saved.
+   * This is also run when the cpu is dropped, and doesn't matter if it runs twice
+   */
+  pub fn shutdown(&mut self) {
+
+    self.audio.stop();
+    self.save_rpl();
+    self.save_rom_settings();
+
+  }
+
+  /**
+   * Switch every quirk over to match a profile at once. The XO-CHIP profile also turns on XO-CHIP's extra instructions
+   */
+  pub fn set_profile(&mut self, profile: QuirkProfile) {
+
+    self.quirks = Quirks::from_profile(profile);
+    if let Some(key_wait) = self.forced_key_wait {
+      self.quirks.key_wait = key_wait;
+    }
+    self.profile = profile;
+    self.xo_chip = profile == QuirkProfile::XoChip;
+    // which also comes with the full 64KB of memory
+    self.set_memory_config(MemoryConfig::for_profile(profile));
+
+  }
+
+  /**
+   * Change the shape of the beep, which takes effect the next frame
+   */
+  pub fn set_waveform(&mut self, waveform: Waveform) {
+
+    self.waveform = waveform;
+
+  }
+
+  /**
+   * Run callback with true whenever the sound timer starts running and false when it runs out (or stops counting down,
+   * like when the program's paused), so the beep can be played by something other than the bundled audio. It hears
+   * about the timer even while muted
+   */
+  pub fn set_sound_callback(&mut self, callback: Box<dyn FnMut(bool)>) {
+
+    self.on_sound = Some(callback);
+
+  }
+
+  // let the callback know if the beep has started or stopped since last time
+  fn check_sound(&mut self, sounding: bool) {
+
+    if sounding == self.sounding {
+      return;
+    }
+    self.sounding = sounding;
+
+    if let Some(callback) = &mut self.on_sound {
+      callback(sounding);
+    }
+
+  }
+
+  /**
+   * Run callback once at the end of every frame, after the timers have ticked and the screen's been drawn
+   */
+  pub fn set_frame_callback(&mut self, callback: FrameCallback) {
+
+    self.on_frame = Some(callback);
+
+  }
+
+  /**
+   * Run handler in place of any instruction where the bits in mask equal the ones in value.
+   * These are checked before the built in instructions in the order they were added, so the first match wins
+   */
+  pub fn add_custom_opcode(&mut self, mask: u16, value: u16, handler: OpcodeHandler) {
+
+    self.custom_opcodes.push(CustomOpcode::new(mask, value, handler));
+
+  }
+
+  /**
+   * Always end waits for a key the same way, even when the profile changes
+   */
+  pub fn force_key_wait(&mut self, key_wait: KeyWait) {
+
+    self.forced_key_wait = Some(key_wait);
+    self.quirks.key_wait = key_wait;
+
+  }
+
+  /**
+   * How much of a frame an instruction takes up, which is always 1 unless cycle costs are turned on
+   */
+  pub fn instruction_cost(&self, instruction: u16) -> u32 {
+
+    return match &self.cycle_costs {
+      Some(costs) => costs.cost(instruction),
+      None => 1
+    };
+
+  }
+
+  /**
+   * Turn fast-forwarding on or off. More instructions run each frame, but the timers still count down at 60Hz
+   */
+  pub fn set_turbo(&mut self, turbo: bool) {
+
+    self.turbo = turbo;
+
+  }
+
+  /**
+   * How many instructions actually run in a frame, taking turbo into account
+   */
+  pub fn instructions_per_frame(&self) -> u16 {
+
+    if self.turbo {
+      return self.speed.saturating_mul(TURBO_FACTOR);
+    }
+    return self.speed;
+
+  }
+
+  /**
+   * Use a fixed seed for the random number generator, so runs can be reproduced exactly
+   */
+  pub fn seed_rng(&mut self, seed: u64) {
+
+    self.rng = StdRng::seed_from_u64(seed);
+
+  }
+
+  /**
+   * Start logging every random number 0xCxkk uses to path, one per line as the address of the instruction and the
+   * number, in hex. Each one is written as soon as it's used, so the log is still there if the emulator crashes
+   */
+  pub fn set_rng_log(&mut self, path: &Path) {
+
+    self.rng_log = match fs::File::create(path) {
+      Ok(file) => Some(file),
+      Err(error) => {
+        log::warn!("Couldn't create the random number log at {}: {}", path.display(), error);
+        None
+      }
+    };
+
+  }
+
+  /**
+   * Have 0xCxkk use these numbers in order instead of the rng (still ANDed with kk), until they run out
+   */
+  pub fn inject_rng(&mut self, values: Vec<u8>) {
+
+    self.injected_rng = values.into();
+
+  }
+
+  /**
+   * Read in a log written by set_rng_log and inject the numbers from it
+   */
+  pub fn inject_rng_log(&mut self, path: &Path) -> Result<(), String> {
+
+    let log = fs::read_to_string(path).map_err(|error| error.to_string())?;
+    let values = parse_rng_log(&log).map_err(|error| error.to_string())?;
+    self.inject_rng(values);
+
+    return Ok(());
+
+  }
+
+  /**
+   * Read the two bytes at the program address and combine them into a single instruction
+   */
+  pub fn fetch_instruction(&self) -> u16 {
+
+    return (self.read_memory(self.program_addr) as u16) << 8 | self.read_memory(self.program_addr + 1) as u16;
+
+  }
+
+  /**
+   * The instruction that's about to run, for debuggers to look at. Nothing runs and the program counter stays put
+   */
+  pub fn current_opcode(&self) -> u16 {
+
+    return self.fetch_instruction();
+
+  }
+
+  /**
+   * Work out what a byte of memory is being used for right now, for the memory map
+   */
+  pub fn memory_region(&self, addr: usize) -> MemoryRegion {
+
+    // instructions are two bytes, and so is the highlight
+    if addr == self.program_addr || addr == self.program_addr + 1 {
+      return MemoryRegion::ProgramCounter;
+    }
+    if addr == self.memory_addr {
+      return MemoryRegion::Index;
+    }
+    if (FONT_START..FONT_START + 80).contains(&addr) || (BIG_FONT_START..BIG_FONT_START + 160).contains(&addr) {
+      return MemoryRegion::Font;
+    }
+    if (self.load_address..self.load_address + self.program.len()).contains(&addr) {
+      return MemoryRegion::Program;
+    }
+
+    return MemoryRegion::Unused;
+
+  }
+
+  /**
+   * The region of every byte the memory map covers, in order.
+   * With XO-CHIP's extended memory only the first 4KB are shown, since that's where nearly everything happens
+   */
+  pub fn memory_map(&self) -> Vec<MemoryRegion> {
+
+    return (0..MEMORY_MAP_SIZE).map(| addr | self.memory_region(addr)).collect();
+
+  }
+
+  /**
+   * How many bytes of memory the current mode can address
+   */
+  pub fn memory_size(&self) -> usize {
+
+    return self.memory.len();
+
+  }
+
+  /**
+   * Switch to a different amount of memory, keeping whatever fits
+   */
+  pub fn set_memory_config(&mut self, config: MemoryConfig) {
+
+    self.memory_config = config;
+    self.memory.resize(config.size(), 0);
+    // history from before the switch won't line up with the new memory
+    self.history.clear();
+    self.memory_dirty = true;
+
+  }
+
+  /**
+   * Read a byte of memory, wrapping the address around the addressable space
+   */
+  pub fn read_memory(&self, addr: usize) -> u8 {
+
+    return self.memory[addr & (self.memory_size() - 1)];
+
+  }
+
+  /**
+   * Write a byte of memory, wrapping the address around the addressable space
+   */
+  pub fn write_memory(&mut self, addr: usize, value: u8) {
+
+    let addr = addr & (self.memory_size() - 1);
+    self.memory[addr] = value;
+    self.memory_dirty = true;
+
+  }
+
+  /**
+   * Write a byte of memory from outside of the program, like a debugger or a cheat would. Unlike write_memory, this
+   * doesn't wrap around, and won't touch the font unless allow_font_pokes is on
+   */
+  pub fn poke(&mut self, addr: usize, value: u8) -> Result<(), PokeError> {
+
+    if addr >= self.memory_size() {
+      return Err(PokeError::OutOfRange(addr));
+    }
+    if addr < PROGRAM_START && !self.allow_font_pokes {
+      return Err(PokeError::Protected(addr));
+    }
+
+    self.write_memory(addr, value);
+    return Ok(());
+
+  }
+
+  /**
+   * Read a byte of memory from outside of the program, which is 0 for anything past the end of memory
+   */
+  pub fn peek(&self, addr: usize) -> u8 {
+
+    if addr >= self.memory_size() {
+      return 0;
+    }
+    return self.memory[addr];
+
+  }
+
+  /**
+   * Set one of the registers from outside of the program, as long as index is one from 0 to F
+   */
+  pub fn set_register(&mut self, index: u8, value: u8) -> Result<(), PokeError> {
+
+    if index > 0xF {
+      return Err(PokeError::NoRegister(index));
+    }
+
+    self.v[index as usize] = value;
+    return Ok(());
+
+  }
+
+  /**
+   * Read one of the registers from outside of the program, which is 0 for anything past VF
+   */
+  pub fn get_register(&self, index: u8) -> u8 {
+
+    if index > 0xF {
+      return 0;
+    }
+    return self.v[index as usize];
+
+  }
+
+  /**
+   * Point I somewhere else in memory, as long as it's somewhere the program could actually use
+   */
+  pub fn set_i(&mut self, addr: usize) -> Result<(), PokeError> {
+
+    if addr >= self.memory_size() {
+      return Err(PokeError::OutOfRange(addr));
+    }
+
+    self.memory_addr = addr;
+    return Ok(());
+
+  }
+
+  /**
+   * Jump the program counter somewhere else in memory, which runs from there on the next step
+   */
+  pub fn set_pc(&mut self, addr: usize) -> Result<(), PokeError> {
+
+    if addr >= self.memory_size() {
+      return Err(PokeError::OutOfRange(addr));
+    }
+
+    self.program_addr = addr;
+    return Ok(());
+
+  }
+
+  /**
+   * Poke every cheat into memory, and forget the ones that only needed to happen once
+   */
+  pub fn apply_cheats(&mut self) {
+
+    if self.cheats.is_empty() {
+      return;
+    }
+
+    let cheats = std::mem::take(&mut self.cheats);
+    for cheat in cheats.iter() {
+      if let Err(error) = self.poke(cheat.addr, cheat.value) {
+        log::warn!("Couldn't apply a cheat: {}", error);
+      }
+    }
+    self.cheats = cheats.into_iter().filter(| cheat | cheat.hold).collect();
+
+  }
+
+  /**
+   * Read an initial state like `V0=0x10, I=0x300, [0x300]=0xAB` and set it up now, as well as whenever the program
+   * starts over. Nothing changes if any of it can't be understood
+   */
+  pub fn apply_initial_state(&mut self, text: &str) -> Result<(), StateError> {
+
+    self.initial_state = initial_state::parse_initial_state(text, self.memory_size())?;
+    self.set_up_initial_state();
+    self.sync_watches();
+
+    return Ok(());
+
+  }
+
+  /**
+   * Put every register and byte of the initial state in place
+   */
+  fn set_up_initial_state(&mut self) {
+
+    for directive in self.initial_state.clone() {
+      match directive {
+        StateDirective::Register(register, value) => self.v[register] = value,
+        StateDirective::I(addr) => self.memory_addr = addr,
+        StateDirective::Pc(addr) => self.program_addr = addr,
+        StateDirective::DelayTimer(value) => self.delay_timer = value,
+        StateDirective::SoundTimer(value) => self.sound_timer = value,
+        StateDirective::Memory(addr, value) => self.write_memory(addr, value)
+      }
+    }
+
+  }
+
+  /**
+   * Start recording every key that goes down or comes up. The program starts over with a fresh seed so that the
+   * replay can reproduce everything exactly
+   */
+  pub fn start_recording(&mut self) {
+
+    let seed: u64 = self.rng.gen();
+    self.reset();
+    self.seed_rng(seed);
+    self.input_recording = Some(Replay::new(settings::rom_hash(&self.program), seed));
+    self.keyboard.recorded_changes = Some(vec![]);
+
+  }
+
+  /**
+   * Stop recording, handing back the replay if there was one
+   */
+  pub fn stop_recording(&mut self) -> Option<Replay> {
+
+    self.keyboard.recorded_changes = None;
+    return self.input_recording.take();
+
+  }
+
+  /**
+   * Load a replay file and play it back from the start of the program. If there's no program yet, it starts
+   * as soon as one is loaded
+   */
+  pub fn play_replay(&mut self, path: &Path) -> Result<(), ReplayError> {
+
+    let bytes = fs::read(path).map_err(| error | ReplayError::Unreadable(error.to_string()))?;
+    let replay = Replay::from_bytes(&bytes)?;
+
+    if self.program.is_empty() {
+      self.queued_replay = Some(replay);
+      return Ok(());
+    }
+
+    return self.start_replay(replay);
+
+  }
+
+  /**
+   * Play a replay back from the start of the program, as long as it was recorded on the program that's loaded
+   */
+  pub fn start_replay(&mut self, replay: Replay) -> Result<(), ReplayError> {
+
+    if replay.rom_hash != settings::rom_hash(&self.program) {
+      return Err(ReplayError::WrongRom);
+    }
+
+    self.reset();
+    self.seed_rng(replay.seed);
+    self.playback = Some(replay);
+    self.playback_frame = 0;
+    self.playback_event = 0;
+
+    return Ok(());
+
+  }
+
+  /**
+   * Carry out a command sent from outside the window
+   */
+  pub fn handle_command(&mut self, command: Command) {
+
+    match command {
+      Command::Pause => self.pause(),
+      Command::Resume => self.resume(),
+      // stepping only makes sense while paused, since otherwise the next instruction's about to run anyway
+      Command::Step => if self.is_paused() {
+        if let Err(error) = self.step() {
+          log::error!("Stopped stepping at {:#05X}: {}", self.program_addr, error);
+        }
+      },
+      Command::Reset => self.reset(),
+      Command::LoadRom(bytes) => if let Err(error) = self.load_new_rom(bytes) {
+        log::error!("Couldn't load the ROM: {}", error);
+      },
+      Command::Load(path) => match fs::read(&path) {
+        Ok(bytes) => self.handle_command(Command::LoadRom(bytes)),
+        Err(error) => log::error!("Couldn't read {}: {}", path.display(), error)
+      },
+      // a speed of 0 would never run anything
+      Command::SetSpeed(speed) => self.speed = speed.max(1),
+      Command::PressKey(key_code) => self.keyboard.press(key_code),
+      Command::ReleaseKey(key_code) => self.keyboard.release(key_code)
+    }
+
+  }
+
+  /**
+   * Stop running until resume is called. A program that's finished stays finished
+   */
+  pub fn pause(&mut self) {
+
+    if let RunState::Active(activity) = self.run_state {
+      self.run_state = RunState::Paused(activity);
+    }
+
+  }
+
+  /**
+   * Carry on after a pause with whatever was going on before, like waiting for a key or a freeze
+   */
+  pub fn resume(&mut self) {
+
+    if let RunState::Paused(activity) = self.run_state {
+      self.run_state = RunState::Active(activity);
+    }
+
+  }
+
+  /**
+   * Pause if running, or resume if paused
+   */
+  pub fn toggle_pause(&mut self) {
+
+    if self.is_paused() {
+      self.resume();
+    } else {
+      self.pause();
+    }
+
+  }
+
+  /**
+   * Freeze just the instructions, leaving the timers running, or unfreeze them if they already are.
+   * Only one of the two can be frozen at a time, since freezing both is what pausing is for
+   */
+  pub fn toggle_cpu_freeze(&mut self) {
+
+    self.toggle_freeze(Activity::CpuFrozen);
+
+  }
+
+  /**
+   * Freeze just the timers, leaving the instructions running, or unfreeze them if they already are
+   */
+  pub fn toggle_timer_freeze(&mut self) {
+
+    self.toggle_freeze(Activity::TimersFrozen);
+
+  }
+
+  // swap between running and the freeze, or from the other freeze straight to this one.
+  // while paused this changes what it goes back to, and a wait for a key has nothing running to freeze
+  fn toggle_freeze(&mut self, freeze: Activity) {
+
+    let toggled = | activity: Activity | match activity {
+      Activity::AwaitingKey => Activity::AwaitingKey,
+      activity if activity == freeze => Activity::Running,
+      _ => freeze
+    };
+
+    self.run_state = match self.run_state {
+      RunState::Active(activity) => RunState::Active(toggled(activity)),
+      RunState::Paused(activity) => RunState::Paused(toggled(activity)),
+      RunState::Halted => RunState::Halted
+    };
+
+  }
+
+  pub fn is_paused(&self) -> bool {
+
+    return matches!(self.run_state, RunState::Paused(_));
+
+  }
+
+  /**
+   * Whether 0xFx0A is waiting for a key, even if it's paused in the middle of it
+   */
+  pub fn is_awaiting_key(&self) -> bool {
+
+    return matches!(self.run_state, RunState::Active(Activity::AwaitingKey) | RunState::Paused(Activity::AwaitingKey));
+
+  }
+
+  pub fn is_halted(&self) -> bool {
+
+    return self.run_state == RunState::Halted;
+
+  }
+
+  /**
+   * Whether instructions can run right now, which they can't while paused, halted, waiting for a key or frozen
+   */
+  pub fn can_execute(&self) -> bool {
+
+    return matches!(self.run_state, RunState::Active(Activity::Running | Activity::TimersFrozen));
+
+  }
+
+  /**
+   * Whether instructions are stopped, either by pausing or by freezing just the cpu
+   */
+  pub fn cpu_frozen(&self) -> bool {
+
+    return matches!(self.run_state, RunState::Paused(_) | RunState::Active(Activity::CpuFrozen));
+
+  }
+
+  /**
+   * Whether the delay and sound timers count down each frame, which they do while the program's running or just the cpu is frozen
+   */
+  fn timers_running(&self) -> bool {
+
+    return matches!(self.run_state, RunState::Active(Activity::Running | Activity::CpuFrozen));
+
+  }
+
+  /**
+   * Whether the delay and sound timers are stopped, either by pausing or by freezing just the timers
+   */
+  pub fn timers_frozen(&self) -> bool {
+
+    return matches!(self.run_state, RunState::Paused(_) | RunState::Active(Activity::TimersFrozen));
+
+  }
+
+  /**
+   * Once per frame, press and release the keys from the replay being played, and write down the keys that went down
+   * or came up for the one being recorded
+   */
+  fn update_replay(&mut self) {
+
+    // frames spent paused aren't part of the game, so they're left out
+    if self.cpu_frozen() {
+      return;
+    }
+
+    if let Some(replay) = &self.playback {
+      if self.playback_frame < replay.frames {
+        // every change that happened before this frame, in the same order. They were already debounced when
+        // they were recorded, so releases happen straight away
+        let events: Vec<KeyEvent> = replay.events[self.playback_event..].iter()
+          .take_while(| event | event.frame == self.playback_frame)
+          .copied()
+          .collect();
+        self.playback_event += events.len();
+        self.playback_frame += 1;
+        for event in events {
+          if event.down {
+            self.keyboard.press(event.key_code);
+          } else {
+            self.keyboard.release_now(event.key_code);
+          }
+        }
+      } else {
+        log::info!("The replay has finished");
+        self.playback = None;
+        self.keyboard.set_key_state_bitmask(0);
+      }
+    }
+
+    if let Some(replay) = &mut self.input_recording {
+      let frame = replay.frames;
+      let changes = self.keyboard.recorded_changes.get_or_insert_with(Vec::new);
+      replay.events.extend(changes.drain(..).map(| (key_code, down) | KeyEvent { frame, key_code, down }));
+      replay.frames += 1;
+    }
+
+  }
+
+  /**
+   * With the logic_resets_vf quirk, the logical instructions clear v[F] afterwards. This happens after the
+   * result is stored, so if v[F] was the destination the reset wins, just like on the COSMAC VIP
+   */
+  fn reset_vf(&mut self) {
+
+    if self.quirks.logic_resets_vf {
+      self.v[15] = 0;
+    }
+
+  }
+
+  /**
+   * With the load_store_increment quirk, Fx55 and Fx65 move i past the registers they just used
+   */
+  fn increment_after_load_store(&mut self, x: usize) {
+
+    if self.quirks.load_store_increment {
+      self.memory_addr += x + 1;
+    }
+
+  }
+
+  /**
+   * The registers from x to y inclusive, counting down instead if x is bigger than y
+   */
+  fn register_range(x: usize, y: usize) -> Box<dyn Iterator<Item = usize>> {
+
+    if x <= y {
+      return Box::new(x..=y);
+    }
+    return Box::new((y..=x).rev());
+
+  }
+
+  /**
+   * How many bytes an instruction takes up. Everything is 2 bytes except XO-CHIP's long-load, which has the address
+   * it loads straight after it
+   */
+  pub fn instruction_length(&self, instruction: u16) -> usize {
+
+    if self.xo_chip && instruction == 0xF000 {
+      return 4;
+    }
+    return 2;
+
+  }
+
+  /**
+   * Skip over the next instruction, however long it is
+   */
+  fn skip_instruction(&mut self) {
+
+    self.program_addr += self.instruction_length(self.fetch_instruction());
+
+  }
+
+  /**
+   * If the timers are not equal to 0, lower their value by 1 per cycle
+   */
+  pub fn update_timers(&mut self) {
+
+    // if the delay timer is greater than 0, make it smaller :)
+    if self.delay_timer > 0 {
+      self.delay_timer -= 1;
+    }
+    if self.sound_timer > 0 {
+      self.sound_timer -= 1;
+    }
+
+    self.update_sound();
+
+  }
+
+  // tell the audio, the display and the sound callback whether the beep's going this frame
+  fn update_sound(&mut self) {
+
+    // the beep plays for as long as the sound timer is counting down (unless it's muted),
+    // so it stops while the program's paused, halted, waiting for a key or has its timers frozen
+    let beeping = self.sound_timer > 0 && self.timers_running();
+    self.audio.update(beeping && !self.muted, &self.audio_pattern, self.pitch, self.waveform);
+    // and the screen can flash along with it
+    self.display.beep_flash = beeping;
+    // as can anything else that's listening
+    self.check_sound(beeping);
+
+  }
+
+  /**
+   * When resuming from a wait for key, that key must be stored in the location specified in the instruction
+   */
+  pub fn handle_resume(&mut self) {
+
+    // find the instruction tht induced the pause, which is just behind the program counter since it's already moved on
+    let instruction = (self.read_memory(self.program_addr - 2) as u16) << 8 | self.read_memory(self.program_addr - 1) as u16;
+
+    // find the x position to store the last keypress
+    let x = ((instruction & 0x0F00) >> 8) as usize;
+
+    // finally, write the most recent keypress to v[x]
+    // without a real key press to write, the wait isn't actually over, so carry on waiting rather than making one up
+    match self.keyboard.finish_wait() {
+      Some(key_code) => self.v[x] = key_code,
+      None => {
+        log::warn!("A wait for a key was resumed without a key being pressed, so it's still waiting");
+        return;
+      }
+    }
+
+    // and that's the wait over, whether or not it's paused
+    self.run_state = match self.run_state {
+      RunState::Active(Activity::AwaitingKey) => RunState::Active(Activity::Running),
+      RunState::Paused(Activity::AwaitingKey) => RunState::Paused(Activity::Running),
+      run_state => run_state
+    };
+
+  }
+
+  /**
+   * The big one: take in an instruction code and determine what to do, returning how many bytes it took up
+   */
+  pub fn execute_instruction(&mut self, instruction: u16) -> Result<usize, CpuError> {
+
+    // update program address so the next instruction is run, stepping over however long this one is
+    let length = self.instruction_length(instruction);
+    self.program_addr += length;
+
+    // custom opcodes go first, so they can stand in for built in instructions too
+    if let Some(index) = self.custom_opcodes.iter().position(| custom | custom.matches(instruction)) {
+
+      // the handler needs the whole cpu, so the list is taken out while it runs
+      let mut custom_opcodes = std::mem::take(&mut self.custom_opcodes);
+      (custom_opcodes[index].handler)(self, instruction);
+      // keeping any the handler added itself
+      custom_opcodes.append(&mut self.custom_opcodes);
+      self.custom_opcodes = custom_opcodes;
+
+      return Ok(length);
+
+    }
+
+    // x and y values, located at 0xy0 in the instruction, are used in
+    // many different instructions, so they should be grabbed now to
+    // reduce repetition of code
+    // use the & operation to isolate only the specific 4 bits we want
+    let x = ((instruction & 0x0F00) >> 8) as usize;
+    let y = ((instruction & 0x00F0) >> 4) as usize;
+
+    // match different instructions, starting off with the greatest value (the first 4 bits)
+    // and then narrowing down depending on the instruction
+    // instructions can be referenced in the technical specification, simple
+    // explainers will be included in comments here
+    match instruction & 0xF000 {
+
+      // there's two options for what a 0x0 instruction could be
+      0x0000 => match instruction {
+
+        // clear the screen
+        0x00E0 => self.display.clear(),
+        // exit a subroutine by setting the program counter to the top of the stack
+        0x00EE => match self.stack.pop() {
+          Some(addr) => self.program_addr = addr,
+          None => return Err(CpuError::StackUnderflow)
+        },
+        // SCHIP: exit the interpreter, leaving the last frame on screen
+        0x00FD => self.run_state = RunState::Halted,
+        // SCHIP: switch to low (64x32) or high (128x64) resolution, which clears the screen
+        0x00FE => self.display.set_resolution(display::WIDTH, display::HEIGHT),
+        0x00FF => self.display.set_resolution(display::HIRES_WIDTH, display::HIRES_HEIGHT),
+        // anything else is a call to a machine code routine on the original computer, which there's no way to run
+        // old ROMs sometimes have them anyway, and usually carry on fine without them
+        _ => if self.warn_on_sys && self.unknown_opcodes.insert(instruction) {
+          log::warn!("Skipping {:#06X} at {:#05X}, which calls a machine code routine that can't be emulated", instruction, self.program_addr - length);
+        }
+
+      }
+
+      // jump to address nnn where nnn is the last 12 bits in the instruction
+      0x1000 => {
+        let target = (instruction & 0x0FFF) as usize;
+        // jumping to this same instruction loops forever, which is how most programs signal they're done
+        if target == self.program_addr - length {
+          self.run_state = RunState::Halted;
+        }
+        self.program_addr = target;
+      },
+
+      // add the current address to the stack and start a subroutine at the last 12 bits
+      0x2000 => {
+        // real hardware only has so many levels, so catch runaway recursion here
+        if self.stack.len() >= self.stack_limit {
+          return Err(CpuError::StackOverflow);
+        }
+        self.stack.push(self.program_addr);
+        self.program_addr = (instruction & 0x0FFF) as usize;
+      },
+
+      // if the value of v[x] equals the second byte, skip the next instruction
+      0x3000 => if self.v[x] as u16 == instruction & 0x00FF {
+        self.skip_instruction();
+      },
+
+      // if the value of v[x] DOESN'T equal the second byte, skip the next instruction
+      0x4000 => if self.v[x] as u16 != instruction & 0x00FF {
+        self.skip_instruction();
+      },
+
+      // only 0x5xy0 is a real instruction here
+      0x5000 => match instruction & 0xF {
+
+        // if v[x] equals v[y], skip the next instruction
+        0x0 => if self.v[x] == self.v[y] {
+          self.skip_instruction();
+        },
+
+        // XO-CHIP: store v[x] through v[y] in memory starting at i (memory_addr), which isn't incremented
+        0x2 if self.xo_chip => for (offset, i) in Cpu::register_range(x, y).enumerate() {
+
+          self.write_memory(self.memory_addr + offset, self.v[i]);
+
+        },
+
+        // XO-CHIP: load v[x] through v[y] from memory starting at i (memory_addr), which isn't incremented
+        0x3 if self.xo_chip => for (offset, i) in Cpu::register_range(x, y).enumerate() {
+
+          self.v[i] = self.read_memory(self.memory_addr + offset);
+
+        },
+
+        // no other options, so this isn't a real instruction
+        _ => return Err(CpuError::UnknownOpcode(instruction))
+
+      },
+
+      // set v[x] to the second byte
+      0x6000 => self.v[x] = (instruction & 0x00FF) as u8,
+
+      // add the second byte to v[x], wrapping around past 255
+      // unlike 0x8xy4 there's no carry here, so v[15] is left untouched
+      0x7000 => self.v[x] = self.v[x].wrapping_add((instruction & 0xFF) as u8),
+
+
+      // 0x8000 series performs operations on the contents of the v store
+      // all use x and y values, specify different operation using the last
+      // four bits
+      0x8000 => match instruction & 0xF {
+
+        // sets store x to the value of store y
+        0x0 => self.v[x] = self.v[y],
+        // store bitwise OR on v[x] and v[y] in v[x]
+        0x1 => {
+          self.v[x] = self.v[x] | self.v[y];
+          self.reset_vf();
+        },
+        // store bitwise AND on v[x] and v[y] in v[x]
+        0x2 => {
+          self.v[x] = self.v[x] & self.v[y];
+          self.reset_vf();
+        },
+        // store bitwise XOR on v[x] and v[y] in v[x]
+        0x3 => {
+          self.v[x] = self.v[x] ^ self.v[y];
+          self.reset_vf();
+        },
+        // add v[x] and v[y] together, storing extra bit in v[0xF]
+        // for all of these, the flag is written last so it's what ends up in v[0xF] when x is F
+        0x4 => {
+          // add them together
+          let sum = self.v[x] as u16 + self.v[y] as u16;
+          // work out whether or not sum is greater than the max
+          let carry = (sum > 255) as u8;
+          // set v[x] to the sum, cutting off any potential overflow
+          self.v[x] = (sum & 0xFF) as u8;
+          self.v[15] = carry;
+        },
+        // v[y] is subtracted from v[x]. v[15] = v[x] >= v[y], so it's only 0 when it borrows
+        0x5 => {
+          let difference = self.v[x] as i16 - self.v[y] as i16;
+          let no_borrow = (self.v[x] >= self.v[y]) as u8;
+          self.v[x] = (difference & 0xFF) as u8;
+          self.v[15] = no_borrow;
+        },
+        // divide v[x] by 2, and set v[15] to the least significant bit of v[x], the one shifted out
+        0x6 => {
+          // the original shifted v[y] rather than v[x]
+          if self.quirks.shift_vy {
+            self.v[x] = self.v[y];
+          }
+          // odd numbers lose a 1 off the end
+          let shifted_out = self.v[x] & 1;
+          self.v[x] /= 2;
+          self.v[15] = shifted_out;
+        },
+        // v[x] is subtracted from v[y]. v[15] = v[y] >= v[x], so it's only 0 when it borrows
+        0x7 => {
+          let difference = self.v[y] as i16 - self.v[x] as i16;
+          let no_borrow = (self.v[y] >= self.v[x]) as u8;
+          self.v[x] = (difference & 0xFF) as u8;
+          self.v[15] = no_borrow;
+        },
+        // multiply v[x] by 2, and set v[15] to the most significant bit of v[x]
+        0xE => {
+          if self.quirks.shift_vy {
+            self.v[x] = self.v[y];
+          }
+          let product = self.v[x] as u16 * 2;
+          // if v[x] is >= 128, the 8th bit must be 1
+          let shifted_out = (self.v[x] >= 128) as u8;
+          self.v[x] = (product & 0xFF) as u8;
+          self.v[15] = shifted_out;
+        },
+
+        // no other options, so this isn't a real instruction
+        _ => return Err(CpuError::UnknownOpcode(instruction))
+
+      },
+
+      // only 0x9xy0 is a real instruction here
+      0x9000 => match instruction & 0xF {
+
+        // skip next instruction if v[x] DOESN'T equal v[y]
+        0x0 => if self.v[x] != self.v[y] {
+          self.skip_instruction();
+        },
+
+        // no other options, so this isn't a real instruction
+        _ => return Err(CpuError::UnknownOpcode(instruction))
+
+      },
+
+      // set the i store (memory_addr) to the last 12 bits of the instruction
+      0xA000 => self.memory_addr = instruction as usize & 0xFFF,
+
+      // the program counter (program_addr) is set to the last 12 bits + v[0]
+      // or with the jump_vx quirk, + v[x], where x is the same digit as the top of the address
+      0xB000 => {
+        let offset = if self.quirks.jump_vx { self.v[x] } else { self.v[0] };
+        self.program_addr = (instruction as usize & 0xFFF) + offset as usize;
+      },
+
+      // a random number between 0 and 255 is generated and ANDed with kk, then stored in v[x]
+      // where 0xCxkk
+      0xC000 => {
+        let kk = (instruction & 0xFF) as u8;
+        // logged numbers get used up first, if there are any
+        let random: u8 = match self.injected_rng.pop_front() {
+          Some(random) => random,
+          None => self.rng.gen()
+        };
+        if let Some(log) = &mut self.rng_log {
+          if let Err(error) = writeln!(log, "{:03X} {:02X}", self.program_addr - 2, random) {
+            // there's no point trying again for every number after this one
+            log::warn!("Couldn't write to the random number log, so it's stopped: {}", error);
+            self.rng_log = None;
+          }
+        }
+        // now store it
+        self.v[x] = random & kk;
+      },
+
+      // read sprite from memory at position i (memory_addr) for n bytes, rendering starting at x, y
+      // formatted as 0xDxyn
+      // the sprite XOR's onto the display, in simple words meaning it flips the state where the bit = 1
+      0xD000 => {
+
+        // get the length of bytes, which is the last 4 bits in the instruction
+        let n = instruction as usize & 0xF;
+
+        // the original waited for the screen to refresh before drawing, so nothing else happens this frame
+        if self.quirks.display_wait {
+          self.waiting_for_frame = true;
+        }
+
+        // the starting position wraps around the screen, but the sprite itself might not
+        let (screen_width, screen_height) = (self.display.width, self.display.height);
+        let base_x = self.v[x] as usize % screen_width;
+        let base_y = self.v[y] as usize % screen_height;
+
+        // remember where the sprite went, cut down to the screen when clipping
+        let (width, height) = if self.quirks.clip_sprites {
+          (8.min(screen_width - base_x), n.min(screen_height - base_y))
+        } else {
+          (8, n)
+        };
+        self.last_sprite = Some(SpriteBox { x: base_x, y: base_y, width, height });
+
+        // where the sprite data for the next plane starts
+        // with both XO-CHIP planes selected, the second plane's rows follow right after the first's
+        let mut sprite_addr = self.memory_addr;
+
+        // whether or not a pixel was turned off (that needs to be stored in memory later)
+        // this has to wait until the position's been read, since v[F] can be the coordinate too
+        self.v[15] = 0;
+
+        for plane in [1, 2] {
+
+          // skip planes that aren't selected
+          if self.display.plane & plane == 0 { continue; }
+
+          // run through the bytes, which make up rows
+          for row in 0..n {
+
+            // rows that fall off the bottom are cut off when clipping
+            if self.quirks.clip_sprites && base_y + row >= screen_height { break; }
+
+            // grab the byte
+            let mut byte = self.read_memory(sprite_addr + row);
+            // and now each bit, which make up the columns
+            for col in 0..8 {
+
+              // as are columns that fall off the right
+              if self.quirks.clip_sprites && base_x + col >= screen_width { break; }
+
+              // if the bit at the end is NOT zero, change the pixel!
+              if (byte & 0x80) > 0 {
+                // also keep track of whether the pixel was already on
+                let (pixel_x, pixel_y) = ((base_x + col) as i32, (base_y + row) as i32);
+                let collided = match self.draw_mode {
+                  DrawMode::Xor => self.display.set_pixel(pixel_x, pixel_y, plane),
+                  DrawMode::Or => self.display.or_pixel(pixel_x, pixel_y, plane)
+                };
+
+                // set the end number to 1 if it was
+                if collided {
+                  self.v[15] = 1;
+                }
+
+              }
+
+              // shift the byte over by one to the left to move the next column to first
+              byte = byte << 1;
+
+            }
+
+          }
+
+          sprite_addr += n;
+
+        }
+
+        // the same fields in the same order every time, so a log can be searched or picked apart by a script
+        if self.log_collisions {
+          log::info!("draw pc={:03X} x={} y={} rows={} collision={} vf={}", self.program_addr - 2, base_x, base_y, n, self.v[15] == 1, self.v[15]);
+        }
+
+      },
+
+      // there's two options here
+      0xE000 => match instruction & 0xFF {
+
+        // skip next instruction if key stored in v[x] is pressed
+        0x9E => if self.keyboard.is_key_pressed(self.v[x]) {
+          self.skip_instruction();
+        },
+        // skip next instruction if key stored in v[x] ISN'T pressed
+        0xA1 => if !self.keyboard.is_key_pressed(self.v[x]) {
+          self.skip_instruction();
+        }
+
+        // no other options, so this isn't a real instruction
+        _ => return Err(CpuError::UnknownOpcode(instruction))
+
+      },
+
+      // there's nine options here, plus the XO-CHIP long-load
+      0xF000 => match instruction & 0xFF {
+
+        // XO-CHIP: 0xF000 0xnnnn sets i (memory_addr) to the full 16-bit word following the instruction
+        // the program counter has already stepped over that word, so it's just behind it
+        0x00 if self.xo_chip && x == 0 => {
+          let addr = self.program_addr - 2;
+          self.memory_addr = (self.read_memory(addr) as usize) << 8 | self.read_memory(addr + 1) as usize;
+        },
+
+        // put the value of the delay timer into v[x]
+        0x07 => self.v[x] = self.delay_timer,
+
+        // pause execution until a key is pressed
+        0x0A => {
+          // keyboard.rs handles this (including whether it's a press or a release that ends it), simply just pause execution
+          // (a step taken while paused stays paused, resuming goes back to waiting)
+          self.keyboard.wait_for_key(self.quirks.key_wait);
+          self.run_state = match self.run_state {
+            RunState::Active(_) => RunState::Active(Activity::AwaitingKey),
+            RunState::Paused(_) => RunState::Paused(Activity::AwaitingKey),
+            RunState::Halted => RunState::Halted
+          };
+        },
+
+        // XO-CHIP: select which planes drawing and clearing affect, using x as the bitmask
+        0x01 if self.xo_chip => self.display.plane = x as u8 & 0x3,
+
+        // XO-CHIP: load the 16-byte audio pattern from memory, starting at i (memory_addr)
+        0x02 if self.xo_chip && x == 0 => for i in 0..16 {
+
+          self.audio_pattern[i] = self.read_memory(self.memory_addr + i);
+
+        },
+
+        // set delay timer to v[x]
+        0x15 => self.delay_timer = self.v[x],
+
+        // set sound timer to v[x]
+        0x18 => self.sound_timer = self.v[x],
+
+        // XO-CHIP: set the audio pattern's pitch to v[x]
+        0x3A if self.xo_chip => self.pitch = self.v[x],
+
+        // add v[x] to i (memory_addr)
+        0x1E => {
+          let sum = self.memory_addr + self.v[x] as usize;
+          // with the quirk, going past the end of memory sets v[F] and wraps around
+          if self.quirks.fx1e_overflow {
+            self.v[15] = (sum > 0xFFF) as u8;
+            self.memory_addr = sum & 0xFFF;
+          } else {
+            self.memory_addr = sum;
+          }
+        },
+
+        // i (memory_addr) is set to the address for the sprite x,
+        // which is v[x] * 5 past the start of the font since sprites are 5 bytes long
+        0x29 => self.memory_addr = FONT_START + self.v[x] as usize * 5,
+
+        // SCHIP: the same for the large sprites, which are 10 bytes long
+        0x30 => self.memory_addr = BIG_FONT_START + (self.v[x] & 0xF) as usize * 10,
+
+        // store the decimal digits of v[x] in memory locations i, i+1, and i+2
+        0x33 => {
+          // hundreds digit
+          self.write_memory(self.memory_addr, self.v[x] / 100);
+          // tens digit - first eliminate the ones digit then the hundreds
+          self.write_memory(self.memory_addr + 1, (self.v[x] / 10) % 10);
+          // finally, the ones digit
+          self.write_memory(self.memory_addr + 2, self.v[x] % 10);
+        },
+
+        // store v[0] through v[x] in memory, starting at memory_addr
+        0x55 => {
+          for i in 0..(x + 1) {
+            self.write_memory(self.memory_addr + i, self.v[i]);
+          }
+          self.increment_after_load_store(x);
+        },
+
+        // read v[0] through v[15] from memory, starting at memory_addr
+        0x65 => {
+          for i in 0..(x + 1) {
+            self.v[i] = self.read_memory(self.memory_addr + i);
+          }
+          self.increment_after_load_store(x);
+        },
+
+        // SCHIP: store v[0] through v[x] in the rpl flags, of which there are only 8
+        0x75 => {
+          for i in 0..(x.min(7) + 1) {
+            self.rpl[i] = self.v[i];
+          }
+          // save them straight away so they survive the emulator closing
+          self.save_rpl();
+        },
+
+        // SCHIP: read v[0] through v[x] back from the rpl flags
+        0x85 => for i in 0..(x.min(7) + 1) {
+
+          self.v[i] = self.rpl[i];
+
+        },
+
+        // no other options, so this isn't a real instruction
+        _ => return Err(CpuError::UnknownOpcode(instruction))
+
+      },
+
+      // if any instruction is encountered that isn't yet implemented, let the caller decide what to do
+      _ => return Err(CpuError::UnknownOpcode(instruction))
+
+    }
+
+    return Ok(length);
+
+  }
+
+}
+
+impl Drop for Cpu {
+
+  fn drop(&mut self) {
+    self.shutdown();
+  }
+
+}
+
+/**
+ * Turn a whitespace or comma separated string of hex into bytes. Each token needs an even number of digits
+ */
+pub fn parse_hex(hex: &str) -> Result<Vec<u8>, HexError> {
+
+  let mut bytes: Vec<u8> = vec![];
+
+  for token in hex.split(|c: char| c.is_whitespace() || c == ',').filter(|token| !token.is_empty()) {
+
+    let digits = token.strip_prefix("0x").or_else(|| token.strip_prefix("0X")).unwrap_or(token);
+    let error = || HexError { token: token.to_string() };
+
+    // every byte needs two digits, and only ascii digits can be sliced up safely
+    if digits.is_empty() || digits.len() % 2 != 0 || !digits.is_ascii() {
+      return Err(error());
+    }
+
+    for i in (0..digits.len()).step_by(2) {
+      bytes.push(u8::from_str_radix(&digits[i..i + 2], 16).map_err(|_| error())?);
+    }
+
+  }
+
+  return Ok(bytes);
+
+}
+
+/**
+ * Read the random numbers back out of a log written by set_rng_log. The number is the last thing on each line,
+ * so the addresses don't matter and a hand-written list of hex numbers works too
+ */
+pub fn parse_rng_log(log: &str) -> Result<Vec<u8>, HexError> {
+
+  let mut values: Vec<u8> = vec![];
+
+  for line in log.lines() {
+
+    let token = match line.split_whitespace().last() {
+      Some(token) => token,
+      None => continue
+    };
+    match u8::from_str_radix(token, 16) {
+      Ok(value) => values.push(value),
+      Err(_) => return Err(HexError { token: token.to_string() })
+    }
+
+  }
+
+  return Ok(values);
+
+}
+
+#[cfg(test)]
+mod tests {
+
+  use super::*;
+
+  // a headless cpu with a program loaded from hex, ready to step through
+  fn cpu_with(hex: &str) -> Cpu {
+
+    let mut cpu = Cpu::headless();
+    cpu.load_program_from_hex(hex).unwrap();
+    return cpu;
+
+  }
+
+  // the same, with XO-CHIP's instructions and memory
+  fn xo_cpu_with(hex: &str) -> Cpu {
+
+    let mut cpu = Cpu::headless();
+    cpu.set_profile(QuirkProfile::XoChip);
+    cpu.load_program_from_hex(hex).unwrap();
+    return cpu;
+
+  }
+
+  fn steps(cpu: &mut Cpu, count: usize) {
+
+    for _ in 0..count {
+      assert_eq!(cpu.run_for(1), Ok(1));
+    }
+
+  }
+
+  // every message logged on this thread since capture_logs, so a test can check what it logged.
+  // each test runs on its own thread, so they don't see each other's
+  thread_local! {
+    static LOGGED: std::cell::RefCell<Vec<String>> = const { std::cell::RefCell::new(vec![]) };
+  }
+
+  struct TestLogger;
+
+  impl log::Log for TestLogger {
+
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+      return true;
+    }
+
+    fn log(&self, record: &log::Record) {
+      LOGGED.with(| logged | logged.borrow_mut().push(record.args().to_string()));
+    }
+
+    fn flush(&self) {}
+
+  }
+
+  fn capture_logs() {
+
+    static LOGGER: TestLogger = TestLogger;
+    static INSTALL: std::sync::Once = std::sync::Once::new();
+    INSTALL.call_once(|| {
+      log::set_logger(&LOGGER).unwrap();
+      log::set_max_level(log::LevelFilter::Trace);
+    });
+    LOGGED.with(| logged | logged.borrow_mut().clear());
+
+  }
+
+  fn logged() -> Vec<String> {
+
+    return LOGGED.with(| logged | logged.borrow().clone());
+
+  }
+
+  #[test]
+  fn add_byte_leaves_vf_alone_when_it_overflows() {
+
+    // vf = 1, v0 = 0xFF, then v0 += 2
+    let mut cpu = cpu_with("6F01 60FF 7002");
+    steps(&mut cpu, 3);
+
+    assert_eq!(cpu.v[0], 0x01);
+    assert_eq!(cpu.v[15], 1);
+
+  }
+
+  #[test]
+  fn run_for_stops_after_the_instructions_asked_for() {
+
+    // a loop that never ends on its own
+    let mut cpu = cpu_with("7001 1200");
+
+    assert_eq!(cpu.run_for(100_000), Ok(100_000));
+    assert!(!cpu.is_halted());
+
+  }
+
+  #[test]
+  fn calls_overflow_the_stack_on_the_17th_level() {
+
+    // a subroutine that calls itself forever
+    let mut cpu = cpu_with("2200");
+    steps(&mut cpu, 16);
+    assert_eq!(cpu.stack.len(), 16);
+
+    assert_eq!(cpu.run_for(1), Err(CpuError::StackOverflow));
+    assert_eq!(cpu.stack.len(), 16);
+
+  }
+
+  #[test]
+  fn long_load_reaches_past_4k() {
+
+    // i = 0x1234, store 0x42 there, then clear v0 and read it back
+    let mut cpu = xo_cpu_with("F000 1234 6042 F055 6000 F000 1234 F065");
+    steps(&mut cpu, 6);
+
+    assert_eq!(cpu.memory_size(), 0x10000);
+    assert_eq!(cpu.read_memory(0x1234), 0x42);
+    assert_eq!(cpu.v[0], 0x42);
+    // the long loads are 4 bytes, so the program counter ended up just past the last instruction
+    assert_eq!(cpu.program_addr, 0x210);
+
+  }
+
+  #[test]
+  fn drawing_and_clearing_one_plane_leaves_the_other_alone() {
+
+    // draw a row of four pixels on plane 2, then the same row on plane 1, then clear plane 1
+    let mut cpu = xo_cpu_with("F201 A20C D001 F101 D001 00E0 F000");
+    steps(&mut cpu, 4);
+    assert_eq!(cpu.display.pixels[0][..5], [2, 2, 2, 2, 0]);
+
+    steps(&mut cpu, 1);
+    assert_eq!(cpu.display.pixels[0][..5], [3, 3, 3, 3, 0]);
+    // different planes never collide
+    assert_eq!(cpu.v[15], 0);
+
+    steps(&mut cpu, 1);
+    assert_eq!(cpu.display.pixels[0][..5], [2, 2, 2, 2, 0]);
+
+  }
+
+  #[test]
+  fn audio_pattern_and_pitch_are_stored() {
+
+    // load the pattern from 0x208, then set the pitch to 0x50
+    let mut cpu = xo_cpu_with("A208 F002 6050 F03A 00112233445566778899AABBCCDDEEFF");
+    steps(&mut cpu, 4);
+
+    assert_eq!(cpu.audio_pattern, [0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF]);
+    assert_eq!(cpu.pitch, 0x50);
+
+  }
+
+  #[test]
+  fn rpl_flags_round_trip() {
+
+    // v0 to v4 = 1 to 5, stored in the rpl flags, then wiped
+    let mut cpu = cpu_with("6001 6102 6203 6304 6405 F475 6000 6100 6200 6300 6400 F285");
+    steps(&mut cpu, 12);
+
+    assert_eq!(cpu.rpl[..5], [1, 2, 3, 4, 5]);
+    // only v0 to v2 were read back, so v3 and v4 are still clear
+    assert_eq!(cpu.v[..5], [1, 2, 3, 0, 0]);
+
+  }
+
+  #[test]
+  fn rpl_flags_stop_at_the_eighth() {
+
+    // storing up to v8 only has room for up to v7
+    let mut cpu = cpu_with("6777 6888 F875");
+    steps(&mut cpu, 3);
+
+    assert_eq!(cpu.rpl[7], 0x77);
+
+  }
+
+  #[test]
+  fn unknown_opcodes_are_errors_not_panics() {
+
+    let mut cpu = cpu_with("E0FF");
+
+    assert_eq!(cpu.run_for(1), Err(CpuError::UnknownOpcode(0xE0FF)));
+
+  }
+
+  #[test]
+  fn returning_with_an_empty_stack_is_an_error() {
+
+    let mut cpu = cpu_with("00EE");
+
+    assert_eq!(cpu.run_for(1), Err(CpuError::StackUnderflow));
+
+  }
+
+  #[test]
+  fn running_into_an_error_pauses_instead_of_panicking() {
+
+    let mut cpu = cpu_with("6001 00EE");
+    cpu.cycle();
+
+    assert!(cpu.is_paused());
+    assert_eq!(cpu.v[0], 1);
+
+  }
+
+  #[test]
+  fn skip_if_equal_needs_a_zero_low_nibble() {
+
+    // v0 == v1, so 5010 skips over the 6F01
+    let mut cpu = cpu_with("6005 6105 5010 6F01 00E0");
+    steps(&mut cpu, 3);
+    assert_eq!(cpu.program_addr, 0x208);
+
+    assert_eq!(cpu_with("5011").run_for(1), Err(CpuError::UnknownOpcode(0x5011)));
+    assert_eq!(cpu_with("9011").run_for(1), Err(CpuError::UnknownOpcode(0x9011)));
+
+  }
+
+  #[test]
+  fn register_ranges_store_and_load_in_either_direction() {
+
+    // v1 and v6 are markers either side of v2 to v5
+    let mut cpu = xo_cpu_with("61AA 66AA 6211 6322 6433 6544 A300 5252 6200 6300 6400 6500 5523");
+    steps(&mut cpu, 13);
+
+    assert_eq!(cpu.memory[0x300..0x304], [0x11, 0x22, 0x33, 0x44]);
+    // loading v5 down to v2 reverses them
+    assert_eq!(cpu.v[1..7], [0xAA, 0x44, 0x33, 0x22, 0x11, 0xAA]);
+    // and neither moves i
+    assert_eq!(cpu.memory_addr, 0x300);
+
+  }
+
+  #[test]
+  fn debug_snapshot_mirrors_the_cpu() {
+
+    let mut cpu = cpu_with("2206 00E0 00E0 6A42");
+    cpu.memory_addr = 0x321;
+    cpu.delay_timer = 12;
+    cpu.sound_timer = 34;
+    steps(&mut cpu, 2);
+
+    let snapshot = cpu.debug_snapshot();
+    assert_eq!(snapshot.v, cpu.v);
+    assert_eq!(snapshot.v[0xA], 0x42);
+    assert_eq!(snapshot.i, 0x321);
+    assert_eq!(snapshot.pc, 0x208);
+    assert_eq!(snapshot.stack, vec![0x202]);
+    assert_eq!((snapshot.delay_timer, snapshot.sound_timer), (12, 34));
+    assert_eq!(snapshot.speed, cpu.speed);
+    assert!(!snapshot.paused);
+
+  }
+
+  #[test]
+  fn breakpoints_pause_before_the_instruction_runs() {
+
+    let mut cpu = cpu_with("6001 6102 6203 1206");
+    cpu.add_breakpoint(0x202);
+    cpu.cycle();
+
+    assert!(cpu.is_paused());
+    assert_eq!(cpu.program_addr, 0x202);
+    assert_eq!(cpu.v[..3], [1, 0, 0]);
+
+    // resuming runs the instruction it stopped on instead of stopping again
+    cpu.resume();
+    cpu.cycle();
+    assert_eq!(cpu.v[..3], [1, 2, 3]);
+
+  }
+
+  #[test]
+  fn rewinding_restores_the_registers() {
+
+    // one loop around per frame, adding 1 to v0 each time
+    let mut cpu = cpu_with("7001 1200");
+    cpu.speed = 2;
+    for _frame in 0..10 {
+      cpu.cycle();
+    }
+    assert_eq!(cpu.v[0], 10);
+
+    assert!(cpu.rewind(5));
+    assert_eq!(cpu.v[0], 5);
+    assert_eq!(cpu.program_addr, 0x200);
+
+  }
+
+  #[test]
+  fn hex_programs_load_into_memory() {
+
+    let mut cpu = Cpu::headless();
+    assert_eq!(cpu.load_program_from_hex("00E0 a20a,0x6000\n12"), Ok(7));
+    assert_eq!(cpu.memory[0x200..0x208], [0x00, 0xE0, 0xA2, 0x0A, 0x60, 0x00, 0x12, 0x00]);
+
+  }
+
+  #[test]
+  fn bad_hex_is_an_error_and_loads_nothing() {
+
+    let mut cpu = cpu_with("1234");
+
+    for hex in ["00E0 A2G0", "ABC", "0x", "6000 ÿÿ"] {
+      assert!(matches!(cpu.load_program_from_hex(hex), Err(LoadError::Hex(_))), "{} should be an error", hex);
+    }
+    assert_eq!(cpu.memory[0x200..0x202], [0x12, 0x34]);
+
+  }
+
+  #[test]
+  fn sprites_are_clipped_at_the_right_edge() {
+
+    // a full row of 8 at x = 62
+    let mut cpu = cpu_with("603E A206 D011 FF00");
+    steps(&mut cpu, 3);
+
+    assert_eq!(cpu.display.pixels[0][62..], [1, 1]);
+    // the 6 that didn't fit aren't wrapped round to the left
+    assert!(cpu.display.pixels[0][..8].iter().all(| pixel | *pixel == 0));
+
+  }
+
+  #[test]
+  fn sprites_can_be_drawn_at_vf() {
+
+    // vf = 10 as the x and y, which has to be read before the collision flag clears it
+    let mut cpu = cpu_with("6F0A A206 DFF1 8000");
+    steps(&mut cpu, 3);
+
+    assert_eq!(cpu.display.pixels[10][10], 1);
+    assert_eq!(cpu.display.pixels[0][0], 0);
+    assert_eq!(cpu.v[15], 0);
+
+  }
+
+  #[test]
+  fn dump_memory_reads_back_what_fx55_stored() {
+
+    let mut cpu = cpu_with("60DE 61AD 62BE 63EF A400 F355");
+    steps(&mut cpu, 6);
+
+    assert_eq!(cpu.dump_memory(0x400..0x404), vec![0xDE, 0xAD, 0xBE, 0xEF]);
+    assert_eq!(cpu.dump_registers()[..4], [0xDE, 0xAD, 0xBE, 0xEF]);
+    assert_eq!((cpu.get_pc(), cpu.get_i()), (0x20C, 0x400));
+    // anything past the end of memory is cut off
+    assert_eq!(cpu.dump_memory(0xFFE..0x1010).len(), 2);
+    assert!(cpu.dump_memory(0x2000..0x2010).is_empty());
+
+  }
+
+  #[test]
+  fn eti_660_programs_load_and_start_at_0x600() {
+
+    let mut cpu = Cpu::headless();
+    cpu.load_program_to_memory_at(vec![0x60, 0x42, 0x12, 0x02], 0x600).unwrap();
+
+    // it's all up at 0x600, with nothing where a normal program would be
+    assert_eq!(cpu.program_addr, 0x600);
+    assert_eq!(&cpu.memory[0x600..0x604], &[0x60, 0x42, 0x12, 0x02]);
+    assert_eq!(cpu.memory[0x200], 0);
+
+    // and the first instruction to run is the one at 0x600
+    cpu.run_for(1).unwrap();
+    assert_eq!(cpu.v[0], 0x42);
+    assert_eq!(cpu.program_addr, 0x602);
+
+  }
+
+  #[test]
+  fn turbo_multiplies_the_speed_and_puts_it_back() {
+
+    let mut cpu = Cpu::headless();
+    cpu.speed = 12;
+
+    cpu.set_turbo(true);
+    assert_eq!(cpu.instructions_per_frame(), 12 * TURBO_FACTOR);
+
+    // changing the speed mid-hold is kept once it's let go of
+    cpu.speed = 15;
+    assert_eq!(cpu.instructions_per_frame(), 15 * TURBO_FACTOR);
+    cpu.set_turbo(false);
+    assert_eq!(cpu.instructions_per_frame(), 15);
+
+  }
+
+  #[test]
+  fn jump_with_offset_uses_v0_or_vx() {
+
+    // v0 is 3 and v2 is 5, then jump to 0x210 plus one of them
+    let program = "6003 6205 B210";
+
+    let mut cpu = cpu_with(program);
+    steps(&mut cpu, 3);
+    assert_eq!(cpu.program_addr, 0x213);
+
+    // the schip way, where the 2 in 210 picks v2
+    let mut cpu = cpu_with(program);
+    cpu.quirks.jump_vx = true;
+    steps(&mut cpu, 3);
+    assert_eq!(cpu.program_addr, 0x215);
+
+  }
+
+  #[test]
+  fn profiles_set_every_quirk_at_once() {
+
+    let mut cpu = Cpu::headless();
+    let flags = | quirks: Quirks | (quirks.shift_vy, quirks.load_store_increment, quirks.jump_vx, quirks.clip_sprites, quirks.logic_resets_vf);
+
+    // in order: shift v[y], load/store increment, jump + v[x], clip sprites and logic resets v[F]
+    cpu.set_profile(QuirkProfile::Modern);
+    assert_eq!(flags(cpu.quirks), (false, false, false, true, false));
+    cpu.set_profile(QuirkProfile::CosmacVip);
+    assert_eq!(flags(cpu.quirks), (true, true, false, true, true));
+    cpu.set_profile(QuirkProfile::Schip);
+    assert_eq!(flags(cpu.quirks), (false, false, true, true, false));
+    cpu.set_profile(QuirkProfile::XoChip);
+    assert_eq!(flags(cpu.quirks), (true, true, false, false, false));
+    assert_eq!(cpu.profile, QuirkProfile::XoChip);
+
+    // and a new cpu starts out modern
+    assert_eq!(Cpu::headless().quirks, Quirks::from_profile(QuirkProfile::Modern));
+
+  }
+
+  #[test]
+  fn logic_ops_reset_vf_only_with_the_quirk() {
+
+    // 3 (011) and 6 (110) make 7 with or, 2 with and and 5 with xor
+    for (op, result) in [(1, 7), (2, 2), (3, 5)] {
+      for reset in [false, true] {
+
+        // v[F] starts out as 9 so the reset shows
+        let mut cpu = cpu_with(&format!("6F09 6003 6106 801{}", op));
+        cpu.quirks.logic_resets_vf = reset;
+        steps(&mut cpu, 4);
+
+        assert_eq!(cpu.v[0], result, "8x1{} with the quirk {}", op, reset);
+        assert_eq!(cpu.v[15], if reset { 0 } else { 9 }, "8x1{} with the quirk {}", op, reset);
+
+      }
+    }
+
+    // and when v[F] is where the result goes, the reset still wins
+    let mut cpu = cpu_with("6F03 6106 8F11");
+    cpu.quirks.logic_resets_vf = true;
+    steps(&mut cpu, 3);
+    assert_eq!(cpu.v[15], 0);
+
+  }
+
+  #[test]
+  fn long_loads_are_four_bytes_and_everything_else_is_two() {
+
+    let mut cpu = xo_cpu_with("6001 F000 1234 3001 F000 5678 6102");
+
+    cpu.run_for(1).unwrap();
+    assert_eq!(cpu.program_addr, 0x202);
+    cpu.run_for(1).unwrap();
+    assert_eq!(cpu.program_addr, 0x206);
+    assert_eq!(cpu.memory_addr, 0x1234);
+
+    // skipping a long load skips all four of its bytes too
+    cpu.run_for(1).unwrap();
+    assert_eq!(cpu.program_addr, 0x20C);
+    cpu.run_for(1).unwrap();
+    assert_eq!(cpu.v[1], 0x02);
+    assert_eq!(cpu.memory_addr, 0x1234);
+
+  }
+
+  #[test]
+  fn display_wait_ends_the_frame_at_the_first_draw() {
+
+    let program = "D001 7001 D001 7001 7001 120A";
+
+    let mut cpu = cpu_with(program);
+    cpu.quirks.display_wait = true;
+    cpu.cycle();
+    assert_eq!(cpu.program_addr, 0x202);
+    assert_eq!(cpu.v[0], 0);
+    // the next frame picks up where it left off, until the next draw
+    cpu.cycle();
+    assert_eq!(cpu.program_addr, 0x206);
+    assert_eq!(cpu.v[0], 1);
+
+    // without it, the whole frame runs
+    let mut cpu = cpu_with(program);
+    cpu.cycle();
+    assert_eq!(cpu.v[0], 3);
+
+  }
+
+  #[test]
+  fn exiting_halts_and_stops_everything_after() {
+
+    let mut cpu = cpu_with("6001 00FD 6002");
+    cpu.cycle();
+    assert!(cpu.is_halted());
+    assert_eq!(cpu.v[0], 1);
+
+    // nothing more runs, however many frames go by or steps are taken
+    cpu.cycle();
+    cpu.run_for(1).unwrap();
+    assert_eq!(cpu.v[0], 1);
+    assert_eq!(cpu.program_addr, 0x204);
+
+    // a reset gets it going again
+    cpu.reset();
+    assert!(!cpu.is_halted());
+    assert_eq!(cpu.program_addr, 0x200);
+
+  }
+
+  #[test]
+  fn halting_in_the_middle_of_a_beep_stops_it() {
+
+    // start a half second beep, count to 100 and exit, or jump to itself to stop instead
+    for program in ["603C F018 7101 3164 1204 00FD", "603C F018 7101 3164 1204 120A"] {
+
+      let mut cpu = cpu_with(program);
+      cpu.set_profile(QuirkProfile::Schip);
+      let tone = cpu.audio.tone.clone();
+      cpu.cycle();
+      assert!(tone.lock().unwrap().playing);
+
+      while !cpu.is_halted() {
+        cpu.cycle();
+      }
+      assert_eq!(cpu.v[1], 100);
+      // the timer's left where it was, but nothing's counting it down, so the beep's over
+      assert!(cpu.sound_timer > 0);
+      assert!(!tone.lock().unwrap().playing);
+      assert!(!cpu.display.beep_flash);
+
+    }
+
+  }
+
+  #[test]
+  fn memory_is_bounded_by_its_size() {
+
+    let mut cpu = Cpu::headless();
+    for (config, size) in [(MemoryConfig::Standard, 0x1000), (MemoryConfig::Extended, 0x10000)] {
+
+      cpu.set_memory_config(config);
+      assert_eq!(cpu.memory_size(), size);
+
+      // addresses wrap around the end of memory
+      cpu.write_memory(size + 5, 0xAB);
+      assert_eq!(cpu.read_memory(5), 0xAB);
+      assert_eq!(cpu.read_memory(size + 5), 0xAB);
+
+      // a rom that fills everything after 0x200 fits, but a byte more doesn't
+      let available = size - 0x200;
+      assert_eq!(cpu.load_program_to_memory(vec![0x12; available]), Ok(available));
+      assert_eq!(cpu.memory[size - 1], 0x12);
+      assert_eq!(cpu.load_program_to_memory(vec![0x34; available + 1]), Err(LoadError::TooLarge { size: available + 1, available }));
+      // and isn't loaded at all
+      assert_eq!(cpu.memory[0x200], 0x12);
+
+    }
+
+  }
+
+  #[test]
+  fn the_screen_flashes_exactly_while_the_sound_timer_runs() {
+
+    // (the loop keeps adding so it doesn't count as the program jumping to itself to stop)
+    let mut cpu = cpu_with("6003 F018 7101 1204");
+    assert!(!cpu.display.beep_flash);
+
+    let mut flashed = 0;
+    for _ in 0..6 {
+      cpu.cycle();
+      assert_eq!(cpu.display.beep_flash, cpu.sound_timer > 0);
+      if cpu.display.beep_flash {
+        flashed += 1;
+      }
+    }
+
+    // the timer's set to 3 and the first frame takes one off
+    assert_eq!(flashed, 2);
+    assert!(!cpu.display.beep_flash);
+
+  }
+
+  #[test]
+  fn dropping_the_cpu_stops_the_beep() {
+
+    // keep hold of the tone the audio thread would be playing from
+    let mut cpu = cpu_with("6030 F018 7101 1204");
+    let tone = cpu.audio.tone.clone();
+    cpu.cycle();
+    assert!(tone.lock().unwrap().playing);
+
+    drop(cpu);
+    assert!(!tone.lock().unwrap().playing);
+
+  }
+
+  #[test]
+  fn cheap_instructions_fit_more_into_a_frame() {
+
+    // each time around the loop adds 1 to v0, with or without a slow draw in the middle
+    let added = | program: &str | {
+      let mut cpu = cpu_with(program);
+      cpu.speed = 16;
+      cpu.cycle_costs = Some(CycleCosts::new());
+      cpu.cycle();
+      return cpu.v[0];
+    };
+
+    // adds and jumps cost 1 each, so 16 instructions run
+    assert_eq!(added("7001 1200"), 8);
+    // but a draw costs 8 of its own
+    assert_eq!(added("7001 D011 1200"), 2);
+
+  }
+
+  #[test]
+  fn pokes_can_be_peeked_but_not_past_memory_or_into_the_font() {
+
+    let mut cpu = cpu_with("1200");
+    assert_eq!(cpu.poke(0x300, 0x42), Ok(()));
+    assert_eq!(cpu.peek(0x300), 0x42);
+
+    // past the end is an error, and nothing wraps around to the start
+    assert_eq!(cpu.poke(0x1300, 0x99), Err(PokeError::OutOfRange(0x1300)));
+    assert_eq!(cpu.peek(0x300), 0x42);
+    assert_eq!(cpu.peek(0x1300), 0);
+    // the font's off limits unless it's asked for
+    assert_eq!(cpu.poke(0x50, 0x99), Err(PokeError::Protected(0x50)));
+    cpu.allow_font_pokes = true;
+    assert_eq!(cpu.poke(0x50, 0x99), Ok(()));
+    assert_eq!(cpu.peek(0x50), 0x99);
+
+  }
+
+  #[test]
+  fn one_off_cheats_go_in_once_the_rom_runs() {
+
+    // cheats are read before the rom is picked, so they mustn't get used up until it starts
+    let mut cpu = Cpu::headless();
+    cpu.cheats = vec![Cheat { addr: 0x300, value: 0x11, hold: false }, Cheat { addr: 0x301, value: 0x22, hold: true }];
+    cpu.load_program_from_hex("A300 F165 7201 1204").unwrap();
+
+    // the program reads both of them back
+    cpu.cycle();
+    assert_eq!((cpu.v[0], cpu.v[1]), (0x11, 0x22));
+    // and only the held one is still being applied
+    assert_eq!(cpu.cheats.len(), 1);
+
+  }
+
+  #[test]
+  fn odd_length_roms_load_every_byte() {
+
+    let mut cpu = Cpu::headless();
+    assert_eq!(cpu.load_new_rom(vec![0x60, 0x42, 0x70]), Ok(3));
+    assert_eq!(&cpu.memory[0x200..0x203], &[0x60, 0x42, 0x70]);
+    // and nothing past the end of it
+    assert_eq!(cpu.memory[0x203], 0);
+
+  }
+
+  #[test]
+  fn swapping_roms_leaves_nothing_behind() {
+
+    // rom a calls a subroutine that sets some registers, draws, waits for a key and starts the delay timer
+    let mut cpu = cpu_with("2204 1202 6A05 A000 D005 6F30 FF15 F00A");
+    cpu.cycle();
+    assert!(cpu.is_awaiting_key());
+    assert!(cpu.display.pixels.iter().flatten().any(| pixel | *pixel != 0));
+
+    cpu.load_new_rom(vec![0x12, 0x00]).unwrap();
+    assert_eq!(cpu.program, vec![0x12, 0x00]);
+    assert_eq!(cpu.program_addr, 0x200);
+    assert_eq!(cpu.v, [0; 16]);
+    assert_eq!(cpu.memory_addr, 0);
+    assert!(cpu.stack.is_empty());
+    assert_eq!(cpu.delay_timer, 0);
+    assert!(!cpu.is_awaiting_key());
+    assert_eq!(cpu.keyboard.key_wait, None);
+    assert!(cpu.display.pixels.iter().flatten().all(| pixel | *pixel == 0));
+    // and none of rom a is left in memory
+    assert!(cpu.memory[0x202..0x210].iter().all(| byte | *byte == 0));
+
+  }
+
+  #[test]
+  fn roms_too_big_for_their_profile_leave_the_last_one_running() {
+
+    let mut cpu = Cpu::headless();
+    cpu.auto_profile = true;
+    cpu.load_program_from_hex("F000 1234 1204").unwrap();
+    assert_eq!(cpu.profile, QuirkProfile::XoChip);
+
+    // 8KB of plain chip-8 would only get 4KB of memory
+    let error = cpu.load_new_rom(vec![0x12; 0x2000]);
+    assert_eq!(error, Err(LoadError::TooLarge { size: 0x2000, available: 0xE00 }));
+    assert_eq!(cpu.profile, QuirkProfile::XoChip);
+    assert_eq!(cpu.memory_size(), 0x10000);
+    assert_eq!(cpu.program, vec![0xF0, 0x00, 0x12, 0x34, 0x12, 0x04]);
+
+  }
+
+  #[test]
+  fn roms_that_go_straight_to_hires_start_there() {
+
+    let mut cpu = Cpu::headless();
+    cpu.auto_profile = true;
+    cpu.load_program_from_hex("00FF 1202").unwrap();
+    assert_eq!(cpu.profile, QuirkProfile::Schip);
+    assert_eq!((cpu.display.width, cpu.display.height), (display::HIRES_WIDTH, display::HIRES_HEIGHT));
+
+  }
+
+  #[test]
+  fn replays_reproduce_the_recorded_game() {
+
+    // draw the 0 somewhere random while key 5 is held, then wait for a key and keep the one that ended the wait in v0
+    let program = "A000 C13F C21F 6305 E3A1 D125 F00A 1202";
+    // with debouncing on, so the replay has to get the releases on the same frame the game did
+    let run = | cpu: &mut Cpu, keys: bool | {
+      for frame in 0..40 {
+        if keys {
+          match frame % 6 {
+            0 => cpu.keyboard.press(0x5),
+            3 => cpu.keyboard.release(0x5),
+            // a tap that's over before the frame even starts
+            4 => { cpu.keyboard.press(0xA); cpu.keyboard.release(0xA); },
+            _ => ()
+          }
+        }
+        cpu.cycle();
+      }
+    };
+
+    let mut cpu = cpu_with(program);
+    cpu.keyboard.debounce_frames = 2;
+    cpu.start_recording();
+    run(&mut cpu, true);
+    let replay = cpu.stop_recording().unwrap();
+    assert_eq!(replay.frames, 40);
+    assert!(cpu.display.pixels.iter().flatten().any(| pixel | *pixel != 0));
+
+    // play it back through a file's worth of bytes, with nobody touching the keys
+    let mut played = cpu_with(program);
+    played.keyboard.debounce_frames = 2;
+    played.start_replay(Replay::from_bytes(&replay.to_bytes()).unwrap()).unwrap();
+    run(&mut played, false);
+
+    assert_eq!(played.display.pixels, cpu.display.pixels);
+    assert_eq!((played.v, played.program_addr), (cpu.v, cpu.program_addr));
+
+    // and it won't play over a different rom
+    let mut other = cpu_with("1200");
+    assert_eq!(other.start_replay(replay.clone()), Err(ReplayError::WrongRom));
+
+    // a replay that's ready before its rom starts once the rom's loaded
+    let mut waiting = Cpu::headless();
+    waiting.queued_replay = Some(replay);
+    waiting.load_program_from_hex(program).unwrap();
+    assert!(waiting.playback.is_some());
+
+  }
+
+  #[test]
+  fn the_last_sprite_drawn_is_remembered() {
+
+    let mut cpu = cpu_with("6105 6203 A000 D125 613E D124");
+    assert_eq!(cpu.last_sprite, None);
+
+    steps(&mut cpu, 4);
+    assert_eq!(cpu.last_sprite, Some(SpriteBox { x: 5, y: 3, width: 8, height: 5 }));
+
+    // one that's clipped by the right edge is cut down to the part that's on screen
+    steps(&mut cpu, 2);
+    assert_eq!(cpu.last_sprite, Some(SpriteBox { x: 62, y: 3, width: 2, height: 4 }));
+
+  }
+
+  #[test]
+  fn freezing_the_cpu_or_the_timers_leaves_the_other_running() {
+
+    // start the delay and sound timers, then keep adding to v1
+    let program = "6030 F015 F018 7101 1206";
+
+    // the beep carries on with the timers
+    let mut cpu = cpu_with(program);
+    let tone = cpu.audio.tone.clone();
+    cpu.cycle();
+    let (added, delay) = (cpu.v[1], cpu.delay_timer);
+    cpu.toggle_cpu_freeze();
+    for _ in 0..3 {
+      cpu.cycle();
+    }
+    assert_eq!(cpu.v[1], added);
+    assert_eq!(cpu.delay_timer, delay - 3);
+    assert!(tone.lock().unwrap().playing);
+    assert!(cpu.display.beep_flash);
+
+    // and stops when they're frozen, since the sound timer isn't counting down
+    let mut cpu = cpu_with(program);
+    let tone = cpu.audio.tone.clone();
+    cpu.cycle();
+    let (added, delay, sound) = (cpu.v[1], cpu.delay_timer, cpu.sound_timer);
+    cpu.toggle_timer_freeze();
+    for _ in 0..3 {
+      cpu.cycle();
+    }
+    assert!(cpu.v[1] > added);
+    assert_eq!(cpu.delay_timer, delay);
+    assert_eq!(cpu.sound_timer, sound);
+    assert!(!tone.lock().unwrap().playing);
+    assert!(!cpu.display.beep_flash);
+
+  }
+
+  #[test]
+  fn muting_keeps_the_beep_quiet_but_the_timer_going() {
+
+    let program = "6030 F018 7101 1204";
+    let mut cpu = cpu_with(program);
+    cpu.muted = true;
+    let tone = cpu.audio.tone.clone();
+
+    for _ in 0..5 {
+      cpu.cycle();
+      assert!(!tone.lock().unwrap().playing);
+    }
+    assert_eq!(cpu.sound_timer, 0x30 - 5);
+
+    // and it stays muted for the next rom
+    cpu.load_program_from_hex(program).unwrap();
+    cpu.cycle();
+    assert!(cpu.muted);
+    assert!(!tone.lock().unwrap().playing);
+
+  }
+
+  #[test]
+  fn pausing_in_the_middle_of_a_beep_stops_it() {
+
+    let mut cpu = cpu_with("603C F018 7101 1204");
+    let tone = cpu.audio.tone.clone();
+    cpu.cycle();
+    assert!(tone.lock().unwrap().playing);
+    assert!(cpu.display.beep_flash);
+
+    // the sound timer doesn't count down while paused, so the beep stops along with it
+    cpu.pause();
+    cpu.cycle();
+    assert!(!tone.lock().unwrap().playing);
+    assert!(!cpu.display.beep_flash);
+    assert_eq!(cpu.sound_timer, 0x3C - 1);
+
+    // and carries on once it's resumed, muting straight away if that was changed in the meantime
+    cpu.muted = true;
+    cpu.resume();
+    cpu.cycle();
+    assert!(!tone.lock().unwrap().playing);
+    assert!(cpu.display.beep_flash);
+    cpu.muted = false;
+    cpu.cycle();
+    assert!(tone.lock().unwrap().playing);
+
+  }
+
+  #[test]
+  fn every_draw_can_be_rendered_as_it_happens() {
+
+    // two draws in a frame that then loops without drawing
+    let program = "A000 D015 D015 1206";
+
+    let mut cpu = cpu_with(program);
+    cpu.cycle();
+    assert_eq!(cpu.display.renders.get(), 1);
+
+    // one for each draw, on top of the usual one at the end of the frame
+    let mut cpu = cpu_with(program);
+    cpu.render_each_draw = true;
+    cpu.cycle();
+    assert_eq!(cpu.display.renders.get(), 3);
+
+  }
+
+  #[test]
+  fn the_current_opcode_can_be_read_without_running_it() {
+
+    let mut cpu = cpu_with("A2F0 6001");
+    assert_eq!(cpu.current_opcode(), 0xA2F0);
+    assert_eq!(cpu.current_opcode(), 0xA2F0);
+    assert_eq!(cpu.program_addr, 0x200);
+    assert_eq!(cpu.memory_addr, 0);
+
+    cpu.run_for(1).unwrap();
+    assert_eq!(cpu.current_opcode(), 0x6001);
+
+  }
+
+  #[test]
+  fn crash_reports_point_out_the_opcode_and_where_it_was() {
+
+    let mut cpu = cpu_with("6012 5121 00E0");
+    cpu.run_for(1).unwrap();
+    let error = cpu.run_for(1).unwrap_err();
+    assert_eq!(error, CpuError::UnknownOpcode(0x5121));
+
+    let report = cpu.crash_report(&error, 0x202, 0x5121);
+    assert!(report.contains("instruction: 5121 at 0x202"), "{}", report);
+    assert!(report.contains("V0=12"), "{}", report);
+    // with the instruction marked in the disassembly around it
+    assert!(report.contains("> 202: 5121  DW 0x5121"), "{}", report);
+    assert!(report.contains("  204: 00E0  CLS"), "{}", report);
+
+  }
+
+  #[test]
+  fn custom_fonts_are_what_fx29_draws() {
+
+    // every digit is a box, except 3 which is a stripe down the middle
+    let mut font = [0; 80];
+    for digit in font.chunks_mut(5) {
+      digit.copy_from_slice(&[0xF0, 0x90, 0x90, 0x90, 0xF0]);
+    }
+    font[15..20].copy_from_slice(&[0x20; 5]);
+
+    let mut cpu = Cpu::headless();
+    cpu.load_font(&font);
+    // and it's still there after a rom is loaded
+    cpu.load_program_from_hex("6003 F029 D115").unwrap();
+    steps(&mut cpu, 3);
+
+    for row in 0..5 {
+      assert_eq!(&cpu.display.pixels[row][0..4], &[0, 0, 1, 0]);
+    }
+
+  }
+
+  #[test]
+  fn commands_drive_the_cpu() {
+
+    let mut cpu = cpu_with("7001 1200");
+
+    cpu.handle_command(Command::SetSpeed(4));
+    cpu.cycle();
+    assert_eq!(cpu.v[0], 2);
+
+    // paused, nothing runs until a step
+    cpu.handle_command(Command::Pause);
+    cpu.cycle();
+    assert!(cpu.is_paused());
+    assert_eq!(cpu.v[0], 2);
+    // which runs straight away, not waiting for the next frame
+    cpu.handle_command(Command::Step);
+    assert_eq!((cpu.v[0], cpu.program_addr), (3, 0x202));
+    cpu.cycle();
+    assert_eq!((cpu.v[0], cpu.program_addr), (3, 0x202));
+    assert!(cpu.is_paused());
+
+    cpu.handle_command(Command::Resume);
+    cpu.handle_command(Command::PressKey(0x5));
+    cpu.cycle();
+    assert!(!cpu.is_paused());
+    assert!(cpu.keyboard.is_key_pressed(0x5));
+    cpu.handle_command(Command::ReleaseKey(0x5));
+    assert!(!cpu.keyboard.is_key_pressed(0x5));
+
+    cpu.handle_command(Command::Reset);
+    assert_eq!((cpu.v[0], cpu.program_addr), (0, 0x200));
+
+    // a new rom replaces the old one, and one that can't be read leaves it be
+    cpu.handle_command(Command::LoadRom(vec![0x60, 0x09]));
+    cpu.handle_command(Command::Load(PathBuf::from("roms/there-is-no-such-rom.ch8")));
+    assert_eq!(cpu.program, vec![0x60, 0x09]);
+    cpu.handle_command(Command::SetSpeed(0));
+    assert_eq!(cpu.speed, 1);
+
+  }
+
+  #[test]
+  fn stepping_over_a_call_runs_the_whole_subroutine() {
+
+    // the subroutine at 0x206 runs four instructions before coming back
+    let mut cpu = cpu_with("2206 6101 1204 6001 7001 7001 00EE");
+    cpu.pause();
+
+    cpu.step_over().unwrap();
+    assert_eq!(cpu.program_addr, 0x202);
+    assert_eq!(cpu.v[0], 3);
+    assert!(cpu.stack.is_empty());
+
+    // anything else is just a normal step
+    cpu.step_over().unwrap();
+    assert_eq!(cpu.program_addr, 0x204);
+    assert_eq!(cpu.v[1], 1);
+
+  }
+
+  #[test]
+  fn fx1e_only_overflows_with_the_quirk() {
+
+    // i is near the top of memory, and v1 takes it past
+    let program = "6F07 AFF0 6120 F11E";
+
+    let mut cpu = cpu_with(program);
+    steps(&mut cpu, 4);
+    assert_eq!(cpu.memory_addr, 0x1010);
+    assert_eq!(cpu.v[15], 0x07);
+
+    let mut cpu = cpu_with(program);
+    cpu.quirks.fx1e_overflow = true;
+    steps(&mut cpu, 4);
+    assert_eq!(cpu.memory_addr, 0x010);
+    assert_eq!(cpu.v[15], 1);
+
+    // without going past, v[F] is cleared
+    let mut cpu = cpu_with("6F07 AFF0 6108 F11E");
+    cpu.quirks.fx1e_overflow = true;
+    steps(&mut cpu, 4);
+    assert_eq!(cpu.memory_addr, 0xFF8);
+    assert_eq!(cpu.v[15], 0);
+
+    // it's 0xFFF that counts, even with all 64KB of memory
+    let mut cpu = xo_cpu_with(program);
+    cpu.quirks.fx1e_overflow = true;
+    steps(&mut cpu, 4);
+    assert_eq!(cpu.memory_addr, 0x010);
+    assert_eq!(cpu.v[15], 1);
+
+  }
+
+  #[test]
+  fn permissive_mode_skips_unknown_opcodes() {
+
+    // nothing but opcodes that don't exist (one of them twice), then an exit
+    let program = "5121 8FFF E1FF 5121 F1FF 00FD";
+
+    let mut cpu = cpu_with(program);
+    cpu.permissive = true;
+    assert_eq!(cpu.run_for(100), Ok(6));
+    assert!(cpu.is_halted());
+    assert_eq!(cpu.unknown_opcodes, HashSet::from([0x5121, 0x8FFF, 0xE1FF, 0xF1FF]));
+
+    // without it, the first one's an error
+    let mut cpu = cpu_with(program);
+    assert_eq!(cpu.run_for(100), Err(CpuError::UnknownOpcode(0x5121)));
+    assert_eq!(cpu.program_addr, 0x202);
+
+  }
+
+  #[test]
+  fn subtracting_equal_values_doesnt_borrow() {
+
+    // 8xy5 and 8xy7 with v0 and v1 the same, so the result is 0 and there's nothing to borrow
+    let mut cpu = cpu_with("6005 6105 8015 6205 6305 8237");
+    steps(&mut cpu, 3);
+    assert_eq!((cpu.v[0], cpu.v[15]), (0, 1));
+    steps(&mut cpu, 3);
+    assert_eq!((cpu.v[2], cpu.v[15]), (0, 1));
+
+    // one less than it's taking away does borrow
+    let mut cpu = cpu_with("6004 6105 8015 6205 6304 8237");
+    steps(&mut cpu, 3);
+    assert_eq!((cpu.v[0], cpu.v[15]), (0xFF, 0));
+    steps(&mut cpu, 3);
+    assert_eq!((cpu.v[2], cpu.v[15]), (0xFF, 0));
+
+  }
+
+  #[test]
+  fn shifts_put_the_bit_that_falls_off_in_vf() {
+
+    // shifting right loses the low bit, so an odd number sets vf and an even one clears it
+    let mut cpu = cpu_with("6105 8116 6204 8226");
+    steps(&mut cpu, 2);
+    assert_eq!((cpu.v[1], cpu.v[15]), (0x02, 1));
+    steps(&mut cpu, 2);
+    assert_eq!((cpu.v[2], cpu.v[15]), (0x02, 0));
+
+    // and shifting left loses the high bit
+    let mut cpu = cpu_with("6181 811E 6241 822E");
+    steps(&mut cpu, 2);
+    assert_eq!((cpu.v[1], cpu.v[15]), (0x02, 1));
+    steps(&mut cpu, 2);
+    assert_eq!((cpu.v[2], cpu.v[15]), (0x82, 0));
+
+  }
+
+  #[test]
+  fn injected_random_numbers_come_out_in_order() {
+
+    let mut cpu = cpu_with("C1FF C20F C3F0");
+    cpu.inject_rng(vec![0xAB, 0xCD, 0xEF]);
+    steps(&mut cpu, 3);
+
+    // each still ANDed with kk
+    assert_eq!(&cpu.v[1..4], &[0xAB, 0x0D, 0xE0]);
+    assert!(cpu.injected_rng.is_empty());
+
+  }
+
+  #[test]
+  fn random_numbers_are_logged_as_they_are_used() {
+
+    let path = std::env::temp_dir().join(format!("emul8-rng-log-{}.txt", std::process::id()));
+    let mut cpu = cpu_with("C1FF C20F");
+    cpu.set_rng_log(&path);
+    cpu.inject_rng(vec![0x12, 0x34]);
+
+    // every number is there as soon as it's drawn, without waiting for the cpu to shut down
+    steps(&mut cpu, 1);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "200 12\n");
+    steps(&mut cpu, 1);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "200 12\n202 34\n");
+
+    // and the log reads back in as the same numbers
+    assert_eq!(parse_rng_log(&fs::read_to_string(&path).unwrap()).unwrap(), vec![0x12, 0x34]);
+    fs::remove_file(&path).unwrap();
+
+  }
+
+  #[test]
+  fn overlapping_sprites_erase_each_other_only_in_xor_mode() {
+
+    // the 0 from the font, then the 8 over the top of it
+    let program = "A000 D005 A028 D005";
+    let lit = | cpu: &Cpu, row: usize | (0..4).map(| x | cpu.display.pixels[row][x] != 0).collect::<Vec<bool>>();
+
+    let mut cpu = cpu_with(program);
+    steps(&mut cpu, 2);
+    assert_eq!(cpu.v[15], 0);
+    steps(&mut cpu, 2);
+    // the 0 and the 8 share every lit pixel, so they collide
+    assert_eq!(cpu.v[15], 1);
+    // and the middle row of the 8 rubs out the sides of the 0
+    assert_eq!(lit(&cpu, 2), vec![false, true, true, false]);
+    assert_eq!(lit(&cpu, 0), vec![false; 4]);
+
+    let mut cpu = cpu_with(program);
+    cpu.draw_mode = DrawMode::Or;
+    steps(&mut cpu, 2);
+    assert_eq!(cpu.v[15], 0);
+    steps(&mut cpu, 2);
+    // still colliding, but with both sprites left on screen
+    assert_eq!(cpu.v[15], 1);
+    assert_eq!(lit(&cpu, 2), vec![true; 4]);
+    assert_eq!(lit(&cpu, 0), vec![true; 4]);
+
+  }
+
+  #[test]
+  fn storing_to_a_watched_address_pauses() {
+
+    // v0 and v1 get stored at 0x300 and 0x301, then v1 keeps counting up
+    let mut cpu = cpu_with("6142 A300 F155 7101 1206");
+    // 0x300 gets written to, but with the 0 that's already there
+    cpu.add_watch(WatchTarget::Memory(0x300));
+    cpu.add_watch(WatchTarget::Memory(0x301));
+    cpu.cycle();
+
+    // it stops straight after the FX55, before anything else runs
+    assert!(cpu.is_paused());
+    assert_eq!(cpu.program_addr, 0x206);
+    assert_eq!(cpu.v[1], 0x42);
+    // with the watch caught up, so resuming doesn't stop again straight away
+    assert_eq!(cpu.watches[0].value, 0);
+    assert_eq!(cpu.watches[1].value, 0x42);
+
+  }
+
+  #[test]
+  fn programs_carry_on_from_the_initial_state() {
+
+    // the program starts by clearing v0, but the initial state skips that
+    let mut cpu = cpu_with("6000 8014 A302 F055 1208");
+    cpu.apply_initial_state("V0=0x10, V1=2, I=0x300, [0x300]=0xAB, PC=0x202").unwrap();
+    assert_eq!((cpu.v[0], cpu.v[1], cpu.memory_addr, cpu.program_addr), (0x10, 2, 0x300, 0x202));
+    assert_eq!(cpu.memory[0x300], 0xAB);
+
+    cpu.cycle();
+    assert_eq!(cpu.v[0], 0x12);
+    assert_eq!(cpu.memory[0x302], 0x12);
+    assert_eq!(cpu.memory[0x300], 0xAB);
+
+    // and a bad directive leaves everything alone
+    assert!(cpu.apply_initial_state("V0=0x1FF").is_err());
+    assert_eq!(cpu.v[0], 0x12);
+
+  }
+
+  #[test]
+  fn adding_into_vf_leaves_the_carry() {
+
+    // 0xFF + 0x01 carries, and 0x10 + 0x01 doesn't, whatever the sum was
+    let mut cpu = cpu_with("6FFF 6101 8F14 6F10 8F14");
+    steps(&mut cpu, 3);
+    assert_eq!(cpu.v[15], 1);
+    steps(&mut cpu, 2);
+    assert_eq!(cpu.v[15], 0);
+
+  }
+
+  #[test]
+  fn pausing_and_freezing_come_back_to_what_was_going_on() {
+
+    let mut cpu = cpu_with("7101 1200");
+    assert_eq!(cpu.run_state, RunState::Active(Activity::Running));
+
+    cpu.pause();
+    assert_eq!(cpu.run_state, RunState::Paused(Activity::Running));
+    cpu.resume();
+    assert_eq!(cpu.run_state, RunState::Active(Activity::Running));
+
+    // one freeze at a time, swapping straight from one to the other
+    cpu.toggle_cpu_freeze();
+    assert_eq!(cpu.run_state, RunState::Active(Activity::CpuFrozen));
+    cpu.toggle_timer_freeze();
+    assert_eq!(cpu.run_state, RunState::Active(Activity::TimersFrozen));
+    cpu.toggle_timer_freeze();
+    assert_eq!(cpu.run_state, RunState::Active(Activity::Running));
+
+    // a pause keeps the freeze for afterwards, and freezing while paused changes what it goes back to
+    cpu.toggle_cpu_freeze();
+    cpu.pause();
+    assert_eq!(cpu.run_state, RunState::Paused(Activity::CpuFrozen));
+    cpu.toggle_cpu_freeze();
+    cpu.resume();
+    assert_eq!(cpu.run_state, RunState::Active(Activity::Running));
+
+  }
+
+  #[test]
+  fn waits_for_keys_carry_on_through_a_pause() {
+
+    let mut cpu = cpu_with("F10A 7201 1202");
+    cpu.cycle();
+    assert_eq!(cpu.run_state, RunState::Active(Activity::AwaitingKey));
+    // there's nothing running to freeze
+    cpu.toggle_cpu_freeze();
+    assert_eq!(cpu.run_state, RunState::Active(Activity::AwaitingKey));
+
+    // a key that ends the wait while paused is only written down once it's resumed
+    cpu.pause();
+    assert_eq!(cpu.run_state, RunState::Paused(Activity::AwaitingKey));
+    cpu.keyboard.press(0x7);
+    cpu.keyboard.release(0x7);
+    cpu.cycle();
+    assert_eq!(cpu.run_state, RunState::Paused(Activity::AwaitingKey));
+    assert_eq!(cpu.v[1], 0);
+
+    cpu.resume();
+    assert_eq!(cpu.run_state, RunState::Active(Activity::AwaitingKey));
+    cpu.cycle();
+    assert_eq!(cpu.run_state, RunState::Active(Activity::Running));
+    assert_eq!(cpu.v[1], 0x7);
+    assert_eq!(cpu.keyboard.key_wait, None);
+
+  }
+
+  #[test]
+  fn stepping_through_a_wait_for_a_key() {
+
+    let mut cpu = cpu_with("F10A 7201 1204");
+    cpu.pause();
+    cpu.handle_command(Command::Step);
+    assert_eq!(cpu.run_state, RunState::Paused(Activity::AwaitingKey));
+
+    // steps can't get past the wait until a key ends it
+    cpu.handle_command(Command::Step);
+    assert_eq!(cpu.program_addr, 0x202);
+    cpu.keyboard.press(0x3);
+    cpu.keyboard.release(0x3);
+    cpu.handle_command(Command::Step);
+    assert_eq!((cpu.v[1], cpu.v[2]), (0x3, 1));
+    assert_eq!(cpu.run_state, RunState::Paused(Activity::Running));
+
+    // and steps are ignored unless it's paused
+    cpu.resume();
+    cpu.handle_command(Command::Step);
+    assert_eq!(cpu.program_addr, 0x204);
+
+  }
+
+  #[test]
+  fn cycles_only_run_what_the_run_state_lets_them() {
+
+    // start the delay timer, then keep adding to v1
+    let cycle_in = | run_state: RunState | {
+      let mut cpu = cpu_with("6030 F015 7101 1204");
+      cpu.cycle();
+      let (added, delay) = (cpu.v[1], cpu.delay_timer);
+      cpu.run_state = run_state;
+      cpu.cycle();
+      return (cpu.v[1] != added, cpu.delay_timer != delay);
+    };
+
+    // whether the instructions ran, and whether the timers did
+    assert_eq!(cycle_in(RunState::Active(Activity::Running)), (true, true));
+    assert_eq!(cycle_in(RunState::Active(Activity::CpuFrozen)), (false, true));
+    assert_eq!(cycle_in(RunState::Active(Activity::TimersFrozen)), (true, false));
+    assert_eq!(cycle_in(RunState::Active(Activity::AwaitingKey)), (false, false));
+    assert_eq!(cycle_in(RunState::Paused(Activity::Running)), (false, false));
+    assert_eq!(cycle_in(RunState::Halted), (false, false));
+
+  }
+
+  #[test]
+  fn machine_code_calls_are_warned_about_and_skipped() {
+
+    let mut cpu = cpu_with("0123 6001 0123");
+    cpu.warn_on_sys = true;
+
+    // it moves on to the next instruction like any other, with the warning written down so it only comes up once
+    cpu.step().unwrap();
+    assert_eq!(cpu.program_addr, 0x202);
+    assert_eq!(cpu.unknown_opcodes, HashSet::from([0x0123]));
+    steps(&mut cpu, 2);
+    assert_eq!(cpu.program_addr, 0x206);
+    assert_eq!(cpu.v[0], 1);
+    assert_eq!(cpu.unknown_opcodes.len(), 1);
+
+    // and without warnings it's skipped all the same
+    let mut cpu = cpu_with("0123 6001");
+    steps(&mut cpu, 2);
+    assert_eq!((cpu.program_addr, cpu.v[0]), (0x204, 1));
+    assert!(cpu.unknown_opcodes.is_empty());
+
+  }
+
+  #[test]
+  fn only_overlapping_draws_are_logged_as_collisions() {
+
+    capture_logs();
+    // the 0 from the font, drawn next to itself and then over itself
+    let mut cpu = cpu_with("A000 D005 6108 D105 D005");
+    cpu.log_collisions = true;
+    steps(&mut cpu, 5);
+
+    assert_eq!(logged(), vec![
+      "draw pc=202 x=0 y=0 rows=5 collision=false vf=0",
+      "draw pc=206 x=8 y=0 rows=5 collision=false vf=0",
+      "draw pc=208 x=0 y=0 rows=5 collision=true vf=1"
+    ]);
+
+    // and nothing's logged with it off
+    capture_logs();
+    steps(&mut cpu_with("A000 D005 D005"), 3);
+    assert!(logged().is_empty());
+
+  }
+
+  #[test]
+  fn each_key_wait_ends_on_its_own_key() {
+
+    // wait for a key into v3, then count up in v4 once it's over
+    let waiting_with = | key_wait: KeyWait | {
+      let mut cpu = cpu_with("F30A 7401 1202");
+      cpu.quirks.key_wait = key_wait;
+      cpu.cycle();
+      assert!(cpu.is_awaiting_key());
+      return cpu;
+    };
+    let over = | cpu: &mut Cpu | {
+      cpu.cycle();
+      return !cpu.is_awaiting_key();
+    };
+
+    // the first press is all it takes
+    let mut cpu = waiting_with(KeyWait::Press);
+    cpu.keyboard.press(0x5);
+    assert!(over(&mut cpu));
+    assert_eq!(cpu.v[3], 0x5);
+
+    // any key pressed during the wait ends it by coming up
+    let mut cpu = waiting_with(KeyWait::Release);
+    cpu.keyboard.press(0x5);
+    cpu.keyboard.press(0x6);
+    assert!(!over(&mut cpu));
+    cpu.keyboard.release(0x6);
+    assert!(over(&mut cpu));
+    assert_eq!(cpu.v[3], 0x6);
+
+    // but only the first one counts here
+    let mut cpu = waiting_with(KeyWait::FirstKey);
+    cpu.keyboard.press(0x5);
+    cpu.keyboard.press(0x6);
+    cpu.keyboard.release(0x6);
+    assert!(!over(&mut cpu));
+    cpu.keyboard.release(0x5);
+    assert!(over(&mut cpu));
+    assert_eq!(cpu.v[3], 0x5);
+    assert!(cpu.v[4] > 0);
+
+  }
+
+  #[test]
+  fn memory_regions_follow_the_registers() {
+
+    // a 6 byte program, pointing i past the end of it
+    let mut cpu = cpu_with("A300 6001 1204");
+    cpu.step().unwrap();
+
+    assert_eq!(cpu.memory_region(0x000), MemoryRegion::Font);
+    assert_eq!(cpu.memory_region(BIG_FONT_START + 159), MemoryRegion::Font);
+    assert_eq!(cpu.memory_region(0x100), MemoryRegion::Unused);
+    assert_eq!(cpu.memory_region(0x200), MemoryRegion::Program);
+    // the program counter covers both bytes of the next instruction, and wins over the program
+    assert_eq!(cpu.memory_region(0x202), MemoryRegion::ProgramCounter);
+    assert_eq!(cpu.memory_region(0x203), MemoryRegion::ProgramCounter);
+    assert_eq!(cpu.memory_region(0x205), MemoryRegion::Program);
+    assert_eq!(cpu.memory_region(0x206), MemoryRegion::Unused);
+    assert_eq!(cpu.memory_region(0x300), MemoryRegion::Index);
+
+    let map = cpu.memory_map();
+    assert_eq!(map.len(), MEMORY_MAP_SIZE);
+    assert_eq!(map[0x300], MemoryRegion::Index);
+
+  }
+
+  #[test]
+  fn draws_wrap_where_they_start_and_clip_the_rest() {
+
+    // the 8 from the font at x=70, then at x=62 and y=66
+    let mut cpu = cpu_with("A028 6046 6100 D015 603E 6142 D015");
+    cpu.quirks.clip_sprites = true;
+    steps(&mut cpu, 4);
+
+    // 70 wraps around to 6, and the whole sprite is drawn from there
+    let row = | cpu: &Cpu, y: usize, xs: Range<usize> | xs.map(| x | cpu.display.pixels[y][x]).collect::<Vec<u8>>();
+    assert_eq!(row(&cpu, 0, 4..12), vec![0, 0, 1, 1, 1, 1, 0, 0]);
+    assert_eq!(row(&cpu, 1, 4..12), vec![0, 0, 1, 0, 0, 1, 0, 0]);
+    assert_eq!(cpu.display.pixels[5][6], 0);
+
+    // 66 wraps to 2 in the same way, but this time the sprite runs off the right and is cut off there
+    steps(&mut cpu, 3);
+    assert_eq!(row(&cpu, 2, 60..64), vec![0, 0, 1, 1]);
+    assert_eq!(row(&cpu, 3, 60..64), vec![0, 0, 1, 0]);
+    assert_eq!(row(&cpu, 2, 0..4), vec![0, 0, 0, 0]);
+    assert_eq!(cpu.v[15], 0);
+
+  }
+
+  #[test]
+  fn custom_opcodes_get_first_go_at_matching_instructions() {
+
+    // 6005 would normally set v0, and 5011 isn't an instruction at all
+    let mut cpu = cpu_with("6005 6105 5011 6205");
+    let seen = Rc::new(std::cell::RefCell::new(vec![]));
+    let heard = seen.clone();
+    cpu.add_custom_opcode(0xFFFF, 0x6005, Box::new(| _cpu, _instruction | ()));
+    cpu.add_custom_opcode(0xF00F, 0x5001, Box::new(move | cpu, instruction | heard.borrow_mut().push((instruction, cpu.program_addr))));
+    steps(&mut cpu, 4);
+
+    // the no-op swallowed the 6005, leaving the instructions it doesn't match alone
+    assert_eq!(&cpu.v[0..3], &[0, 5, 5]);
+    // and the handler saw the whole instruction, after the program counter moved past it
+    assert_eq!(*seen.borrow(), vec![(0x5011, 0x206)]);
+    assert_eq!(cpu.custom_opcodes.len(), 2);
+
+  }
+
+  #[test]
+  fn only_settings_that_were_chosen_get_saved() {
+
+    let path = std::env::temp_dir().join(format!("emul8-rom-settings-{}.txt", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let mut cpu = Cpu::headless();
+    cpu.settings_file = Some(path.clone());
+    cpu.auto_profile = true;
+
+    // a rom that's just been played, with the profile it was detected as, doesn't get a line
+    cpu.load_program_from_hex("6005 1202").unwrap();
+    cpu.save_rom_settings();
+    assert!(!path.exists());
+
+    // changing the speed saves just the speed, and not the profile that was only detected
+    cpu.speed = 30;
+    cpu.save_rom_settings();
+    let hash = format!("{:016x}", settings::rom_hash(&cpu.program));
+    assert_eq!(fs::read_to_string(&path).unwrap(), format!("{} speed=30\n", hash));
+
+    // and it comes back next time, still as the only setting there is
+    cpu.load_program_from_hex("6005 1202").unwrap();
+    assert_eq!(cpu.speed, 30);
+    assert_eq!(cpu.rom_settings(), RomSettings { speed: Some(30), ..RomSettings::new() });
+    fs::remove_file(&path).unwrap();
+
+  }
+
+  #[test]
+  fn a_reset_that_fails_keeps_the_program() {
+
+    let mut cpu = cpu_with("6005 1202");
+
+    // nowhere near enough room to load it again
+    cpu.load_address = cpu.memory.len() - 1;
+    cpu.reset();
+    assert_eq!(cpu.program, vec![0x60, 0x05, 0x12, 0x02]);
+
+  }
+
+  #[test]
+  fn resuming_a_wait_without_a_key_keeps_waiting() {
+
+    let mut cpu = cpu_with("6107 F10A 7201 1204");
+    steps(&mut cpu, 2);
+    assert!(cpu.is_awaiting_key());
+
+    // nothing's been pressed, so there's nothing to put in v[1]
+    cpu.handle_resume();
+    assert_eq!(cpu.v[1], 0x07);
+    assert!(cpu.is_awaiting_key());
+    assert_eq!(cpu.keyboard.latest_key, None);
+
+  }
+
+  #[test]
+  fn roms_too_big_for_memory_are_rejected_or_truncated() {
+
+    // a byte more than fits after 0x200, with the last byte different so it's obvious whether it made it in
+    let mut rom = vec![0x12; 0xE00];
+    rom.push(0x34);
+
+    let mut cpu = cpu_with("6005 1202");
+    assert_eq!(cpu.load_new_rom(rom.clone()), Err(LoadError::TooLarge { size: 0xE01, available: 0xE00 }));
+    // with the last program left as it was
+    assert_eq!(cpu.program, vec![0x60, 0x05, 0x12, 0x02]);
+    assert_eq!(cpu.memory[0x200], 0x60);
+
+    // truncating loads as much as fits, and leaves off the rest
+    cpu.oversize_policy = OversizePolicy::Truncate;
+    assert_eq!(cpu.load_new_rom(rom), Ok(0xE00));
+    assert_eq!(cpu.memory[0x200], 0x12);
+    assert_eq!(cpu.memory[0xFFF], 0x12);
+    // and the byte that didn't fit doesn't wrap around onto the font
+    assert_eq!(cpu.memory.len(), 0x1000);
+    assert_eq!(cpu.read_memory(0x1000), 0xF0);
+
+  }
+
+  #[test]
+  fn the_sound_callback_hears_the_beep_start_and_stop() {
+
+    let heard = Rc::new(std::cell::RefCell::new(vec![]));
+    let hearing = heard.clone();
+    let mut cpu = cpu_with("6103 F118 7201 1204");
+    // even while muted
+    cpu.muted = true;
+    cpu.set_sound_callback(Box::new(move | sounding | hearing.borrow_mut().push(sounding)));
+
+    // it's told once a frame, when the timers tick
+    steps(&mut cpu, 2);
+    assert!(heard.borrow().is_empty());
+    cpu.update_timers();
+    assert_eq!(*heard.borrow(), vec![true]);
+
+    // the timer running down doesn't say anything until it's out
+    cpu.update_timers();
+    assert_eq!(*heard.borrow(), vec![true]);
+    cpu.update_timers();
+    assert_eq!(*heard.borrow(), vec![true, false]);
+
+  }
+
+  #[test]
+  fn registers_can_only_be_set_from_outside_if_they_exist() {
+
+    let mut cpu = cpu_with("6005 1202");
+
+    assert_eq!(cpu.set_register(0x0, 0x12), Ok(()));
+    assert_eq!(cpu.set_register(0xF, 0x34), Ok(()));
+    assert_eq!(cpu.get_register(0x0), 0x12);
+    assert_eq!(cpu.get_register(0xF), 0x34);
+
+    // there's no V10, so nothing changes and it reads as 0
+    assert_eq!(cpu.set_register(0x10, 0x56), Err(PokeError::NoRegister(0x10)));
+    assert_eq!(cpu.get_register(0x10), 0);
+    assert_eq!(cpu.v[0x0], 0x12);
+
+    // I and the program counter have to stay in memory
+    assert_eq!(cpu.set_i(0xFFF), Ok(()));
+    assert_eq!(cpu.set_i(0x1000), Err(PokeError::OutOfRange(0x1000)));
+    assert_eq!(cpu.memory_addr, 0xFFF);
+    assert_eq!(cpu.set_pc(0x1000), Err(PokeError::OutOfRange(0x1000)));
+    assert_eq!(cpu.program_addr, 0x200);
+
+  }
+
+  #[test]
+  fn the_frame_callback_sees_each_frame_once_it_has_run() {
+
+    let seen = Rc::new(std::cell::RefCell::new(vec![]));
+    let seeing = seen.clone();
+    // set the delay timer going, then count up forever
+    let mut cpu = cpu_with("6F05 FF15 7101 1204");
+    cpu.set_frame_callback(Box::new(move | cpu | seeing.borrow_mut().push((cpu.v[1], cpu.delay_timer))));
+
+    let mut after = vec![];
+    for _ in 0..3 {
+      cpu.cycle();
+      after.push((cpu.v[1], cpu.delay_timer));
+    }
+
+    // one call a frame, with the registers and timers as the frame left them
+    assert_eq!(*seen.borrow(), after);
+    assert!(after[0].0 > 0);
+    assert!(after[0].0 < after[1].0);
+    assert_eq!(after[2].1, 2);
+
+  }
+
+}
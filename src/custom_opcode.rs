@@ -0,0 +1,35 @@
+/**
+ * Custom opcodes let new instructions be tried out without touching the cpu itself. Each one matches instructions
+ * against a pattern and gets a look at them before the built in instructions do
+ */
+use crate::cpu::Cpu;
+
+// run with the cpu and the whole instruction in place of whatever would normally happen.
+// the program counter has already moved past the instruction by then
+pub type OpcodeHandler = Box<dyn FnMut(&mut Cpu, u16)>;
+
+pub struct CustomOpcode {
+  // the bits of the instruction that matter, and what they need to be
+  pub mask: u16,
+  pub value: u16,
+  pub handler: OpcodeHandler
+}
+
+impl CustomOpcode {
+
+  pub fn new(mask: u16, value: u16, handler: OpcodeHandler) -> Self {
+
+    return CustomOpcode { mask, value, handler };
+
+  }
+
+  /**
+   * Whether this should handle an instruction, like a mask of 0xF0FF and value of 0xF0FF matching FxFF
+   */
+  pub fn matches(&self, instruction: u16) -> bool {
+
+    return instruction & self.mask == self.value & self.mask;
+
+  }
+
+}
@@ -0,0 +1,221 @@
+/**
+ * ROM files don't say which interpreter they were written for, so this makes a best guess by
+ * looking for instructions that only exist in SCHIP or XO-CHIP
+ */
+use std::collections::HashSet;
+use crate::quirks::QuirkProfile;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+  Chip8,
+  Schip,
+  XoChip
+}
+
+impl Mode {
+
+  /**
+   * The quirk profile that suits ROMs written for this mode
+   */
+  pub fn profile(&self) -> QuirkProfile {
+
+    return match self {
+      Mode::Chip8 => QuirkProfile::Modern,
+      Mode::Schip => QuirkProfile::Schip,
+      Mode::XoChip => QuirkProfile::XoChip
+    };
+
+  }
+
+}
+
+/**
+ * Guess the mode a ROM was written for, from a ROM loaded at load_address. XO-CHIP builds on SCHIP, so XO-CHIP
+ * instructions win over SCHIP ones. Only instructions the program can actually get to count, since sprites and
+ * other data can easily look like an instruction that isn't really there
+ */
+pub fn detect_mode(bytes: &[u8], load_address: usize) -> Mode {
+
+  let mut mode = Mode::Chip8;
+
+  for instruction in reachable_instructions(bytes, load_address) {
+
+    if is_xo_chip_instruction(instruction) {
+      return Mode::XoChip;
+    }
+    if is_schip_instruction(instruction) {
+      mode = Mode::Schip;
+    }
+
+  }
+
+  return mode;
+
+}
+
+/**
+ * Whether the ROM switches to high resolution (00FF) before it draws anything or picks low resolution, following
+ * the program from the start the way it'd most likely run. Those ROMs might as well start out in high resolution
+ */
+pub fn starts_in_hires(bytes: &[u8], load_address: usize) -> bool {
+
+  let mut visited: HashSet<usize> = HashSet::new();
+  let mut stack: Vec<usize> = vec![];
+  let mut offset = 0;
+
+  // going around in a loop means it's not getting there
+  while visited.insert(offset) {
+
+    let instruction = match read_instruction(bytes, offset) {
+      Some(instruction) => instruction,
+      None => return false
+    };
+
+    let next = match instruction {
+      0x00FF => return true,
+      // low resolution, or a draw before the switch, or the end of the program
+      0x00FE | 0x00FD => return false,
+      _ if instruction & 0xF000 == 0xD000 => return false,
+      // subroutines are followed in and back out again
+      0x00EE => stack.pop(),
+      _ if instruction & 0xF000 == 0x2000 => {
+        stack.push(offset + 2);
+        next_offsets(instruction, offset, bytes, load_address).first().copied()
+      },
+      // as are jumps (skips just carry on to the next one)
+      _ => next_offsets(instruction, offset, bytes, load_address).first().copied()
+    };
+
+    match next {
+      Some(next) => offset = next,
+      None => return false
+    }
+
+  }
+
+  return false;
+
+}
+
+/**
+ * Every instruction the program could reach by running from the start, following jumps, calls and both sides of skips.
+ * Computed jumps (Bnnn) can't be followed, so anything only they lead to is left out
+ */
+fn reachable_instructions(bytes: &[u8], load_address: usize) -> Vec<u16> {
+
+  let mut instructions: Vec<u16> = vec![];
+  let mut visited: HashSet<usize> = HashSet::new();
+  let mut to_visit: Vec<usize> = vec![0];
+
+  while let Some(offset) = to_visit.pop() {
+
+    if !visited.insert(offset) {
+      continue;
+    }
+    let instruction = match read_instruction(bytes, offset) {
+      Some(instruction) => instruction,
+      None => continue
+    };
+
+    instructions.push(instruction);
+    to_visit.extend(next_offsets(instruction, offset, bytes, load_address));
+
+  }
+
+  return instructions;
+
+}
+
+// the two bytes at an offset into the ROM as an instruction, if they're both there
+fn read_instruction(bytes: &[u8], offset: usize) -> Option<u16> {
+
+  let high = *bytes.get(offset)?;
+  let low = *bytes.get(offset + 1)?;
+  return Some((high as u16) << 8 | low as u16);
+
+}
+
+// where the program could go after the instruction at offset, as offsets into the ROM
+fn next_offsets(instruction: u16, offset: usize, bytes: &[u8], load_address: usize) -> Vec<usize> {
+
+  let nnn = (instruction & 0x0FFF) as usize;
+  // addresses before the ROM can't be part of it
+  let target = nnn.checked_sub(load_address);
+  // the long-load is twice the length of everything else, which matters when it's the one being skipped
+  let length = | offset: usize | if read_instruction(bytes, offset) == Some(0xF000) { 4 } else { 2 };
+
+  return match instruction & 0xF000 {
+    // returning, exiting and computed jumps don't go anywhere that can be worked out from here
+    0x0000 if matches!(instruction, 0x00EE | 0x00FD) => vec![],
+    0xB000 => vec![],
+    0x1000 => target.into_iter().collect(),
+    // a call goes off to the subroutine, and comes back to the next instruction
+    0x2000 => target.into_iter().chain([offset + 2]).collect(),
+    0x3000 | 0x4000 | 0x5000 | 0x9000 => vec![offset + 2, offset + 2 + length(offset + 2)],
+    0xE000 if matches!(instruction & 0xFF, 0x9E | 0xA1) => vec![offset + 2, offset + 2 + length(offset + 2)],
+    _ => vec![offset + length(offset)]
+  };
+
+}
+
+// scrolling, exit, hi-res, big font and rpl instructions
+fn is_schip_instruction(instruction: u16) -> bool {
+
+  return matches!(instruction, 0x00C1..=0x00CF | 0x00FB..=0x00FF)
+    || matches!(instruction & 0xF0FF, 0xF030 | 0xF075 | 0xF085);
+
+}
+
+// scrolling up, register ranges, the long-load, plane selection and audio
+fn is_xo_chip_instruction(instruction: u16) -> bool {
+
+  return matches!(instruction, 0x00D1..=0x00DF | 0xF000 | 0xF002)
+    || matches!(instruction & 0xF00F, 0x5002 | 0x5003)
+    || matches!(instruction & 0xF0FF, 0xF001 | 0xF03A);
+
+}
+
+#[cfg(test)]
+mod tests {
+
+  use super::*;
+
+  #[test]
+  fn instructions_that_run_pick_the_mode() {
+
+    // plain chip-8 that just loops
+    assert_eq!(detect_mode(&[0x60, 0x01, 0x12, 0x02], 0x200), Mode::Chip8);
+    // hi-res, then the big font (fx30)
+    assert_eq!(detect_mode(&[0x00, 0xFF, 0xF0, 0x30, 0x12, 0x04], 0x200), Mode::Schip);
+    // a scroll inside a subroutine
+    assert_eq!(detect_mode(&[0x22, 0x04, 0x12, 0x02, 0x00, 0xC4, 0x00, 0xEE], 0x200), Mode::Schip);
+    // xo-chip beats schip, even when it's only reached by skipping
+    assert_eq!(detect_mode(&[0x00, 0xFF, 0x30, 0x00, 0xF0, 0x02, 0x12, 0x06], 0x200), Mode::XoChip);
+
+  }
+
+  #[test]
+  fn data_the_program_never_runs_doesnt_count() {
+
+    // jump over a sprite that happens to look like 00FF and F030, which are never run
+    assert_eq!(detect_mode(&[0x12, 0x06, 0x00, 0xFF, 0xF0, 0x30, 0x12, 0x06], 0x200), Mode::Chip8);
+    // and the same for data (that looks like the long-load) past the end of the program
+    assert_eq!(detect_mode(&[0x00, 0xE0, 0x12, 0x02, 0xF0, 0x00], 0x200), Mode::Chip8);
+    // jumps are followed relative to where the rom was loaded
+    assert_eq!(detect_mode(&[0x16, 0x04, 0x00, 0xE0, 0x00, 0xFF, 0x16, 0x04], 0x600), Mode::Schip);
+
+  }
+
+  #[test]
+  fn roms_that_switch_to_hires_first_start_there() {
+
+    assert!(starts_in_hires(&[0x00, 0xE0, 0x00, 0xFF, 0x12, 0x04], 0x200));
+    // from a subroutine too
+    assert!(starts_in_hires(&[0x22, 0x04, 0x12, 0x02, 0x00, 0xFF, 0x00, 0xEE], 0x200));
+    // but not after drawing something in low resolution first
+    assert!(!starts_in_hires(&[0xD0, 0x15, 0x00, 0xFF, 0x12, 0x04], 0x200));
+    assert!(!starts_in_hires(&[0x60, 0x01, 0x12, 0x02], 0x200));
+
+  }
+
+}
@@ -2,13 +2,18 @@
   Renders the actual state to the screen :)
 */
 use winit::window::Window;
-use std::borrow::Cow;
+use std::{borrow::Cow, cell::Cell, fmt, future::Future};
 use wgpu::util::DeviceExt;
 use bytemuck::{Pod, Zeroable};
+use instant::Instant;
+use crate::{overlay::{self, Overlay}, timing};
 
-// resolution of the display
-const WIDTH: usize = 64;
-const HEIGHT: usize = 32;
+// resolution of the display, which the overlay and keypad are always drawn at
+pub const WIDTH: usize = 64;
+pub const HEIGHT: usize = 32;
+// and SCHIP's high resolution mode
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
 
 // the vertices that make up a single pixel
 // basically we're gonna have a bunch of instances of this to fill the screen :)
@@ -30,53 +35,347 @@ const PIXEL_VERTICES: [f32; 12] = [
 #[derive(Clone, Copy, Pod, Zeroable, Debug)]
 pub struct Instance {
   pub pos: [u32; 2],
-  pub on: u32
+  // which palette entry to colour the pixel with
+  pub color_index: u32,
+  // how brightly the pixel glows, from 0 to 1, and how brightly it glowed the frame before
+  pub brightness: f32,
+  pub previous: f32,
+  // 1 if the key wait indicator and beep flash can show on the pixel, which is only the game's screen and not the keypad
+  pub indicators: u32
+}
+
+// the colours the overlay uses, which come straight after the four plane colours in the palette:
+// text, dimmed text, the backdrop behind it, and a highlight for things that need to stand out
+const OVERLAY_COLOURS: [[f32; 4]; 4] = [
+  [1.0, 0.85, 0.2, 1.0],
+  [0.6, 0.5, 0.1, 1.0],
+  [0.05, 0.05, 0.15, 1.0],
+  [0.9, 0.2, 0.2, 1.0]
+];
+
+/**
+ * The values passed to the shaders through the uniform buffer
+ */
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable, Debug)]
+pub struct Uniforms {
+  // the four plane colours followed by the overlay's colours
+  pub palette: [[f32; 4]; 8],
+  // how strongly the key wait indicator shows, then the beep flash
+  // (the rest keep the struct lined up for wgsl)
+  pub indicator: [f32; 4],
+  // which pixel style to draw with in the first value, then 1 if the colours are inverted
+  pub style: [u32; 4],
+  // how brightly unlit pixels show in the first value, then how far to blend from the last frame to the newest
+  pub levels: [f32; 4],
+  // how many pixels across and down the screen is, in the first two values
+  pub grid: [f32; 4]
+}
+
+/**
+ * The shapes pixels can be drawn as
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelStyle {
+  // plain squares
+  Sharp,
+  // circles that just touch their neighbours
+  Rounded,
+  // squares with scanlines and a soft glow, like an old CRT
+  Crt
+}
+
+impl PixelStyle {
+
+  // the style after this one, for cycling through them
+  pub fn next(&self) -> Self {
+
+    return match self {
+      PixelStyle::Sharp => PixelStyle::Rounded,
+      PixelStyle::Rounded => PixelStyle::Crt,
+      PixelStyle::Crt => PixelStyle::Sharp
+    };
+
+  }
+
+}
+
+/**
+ * How sprites are put onto the screen
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrawMode {
+  // flip the pixels under the sprite, which is how chip-8 really works
+  Xor,
+  // only ever turn pixels on, so sprites can be seen whole without erasing each other, for debugging
+  Or
+}
+
+impl DrawMode {
+
+  // the other mode, for switching between them
+  pub fn toggled(&self) -> Self {
+
+    return match self {
+      DrawMode::Xor => DrawMode::Or,
+      DrawMode::Or => DrawMode::Xor
+    };
+
+  }
+
+}
+
+/**
+ * Anything that can show a frame of chip-8 pixels, like a terminal
+ */
+pub trait Screen {
+  fn present(&mut self, pixels: &[Vec<u8>]);
+}
+
+/**
+ * The area a sprite was drawn over, for highlighting where it landed
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpriteBox {
+  pub x: usize,
+  pub y: usize,
+  pub width: usize,
+  pub height: usize
+}
+
+impl SpriteBox {
+
+  /**
+   * Whether a pixel is on the edge of the box, which wraps around the screen the same way unclipped sprites do
+   */
+  pub fn outlines(&self, x: usize, y: usize, width: usize, height: usize) -> bool {
+
+    // how far into the box the pixel is, counting around the edges of the screen
+    let dx = (x + width - self.x % width) % width;
+    let dy = (y + height - self.y % height) % height;
+
+    if dx >= self.width || dy >= self.height {
+      return false;
+    }
+    return dx == 0 || dy == 0 || dx == self.width - 1 || dy == self.height - 1;
+
+  }
+
+}
+
+/**
+ * Reasons the display couldn't set up wgpu for drawing to the window
+ */
+#[derive(Debug)]
+pub enum DisplayError {
+  // no gpu (not even a software one) can draw to the window
+  NoAdapter,
+  // an adapter was found but wouldn't hand over a device
+  Device(wgpu::RequestDeviceError),
+  // the adapter can't draw to the window in any format
+  IncompatibleSurface,
+  // one of the buffers is bigger than the device allows, with which limit it broke
+  ExceedsLimit(&'static str)
+}
+
+impl fmt::Display for DisplayError {
+
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      DisplayError::NoAdapter => write!(f, "couldn't find a compatible graphics adapter"),
+      DisplayError::Device(error) => write!(f, "couldn't get a graphics device: {}", error),
+      DisplayError::IncompatibleSurface => write!(f, "the graphics adapter can't draw to this window"),
+      DisplayError::ExceedsLimit(limit) => write!(f, "the graphics device's {} is too small to draw the screen", limit)
+    }
+  }
+
+}
+
+/**
+ * Find the wgpu backend a name like "vulkan" refers to, ignoring case
+ */
+pub fn backends_from_name(name: &str) -> Option<wgpu::Backends> {
+
+  return match name.trim().to_ascii_lowercase().as_str() {
+    "vulkan" | "vk" => Some(wgpu::Backends::VULKAN),
+    "dx12" | "d3d12" => Some(wgpu::Backends::DX12),
+    "dx11" | "d3d11" => Some(wgpu::Backends::DX11),
+    "metal" => Some(wgpu::Backends::METAL),
+    "gl" | "opengl" | "gles" => Some(wgpu::Backends::GL),
+    "webgpu" => Some(wgpu::Backends::BROWSER_WEBGPU),
+    "primary" => Some(wgpu::Backends::PRIMARY),
+    "all" => Some(wgpu::Backends::all()),
+    _ => None
+  };
+
+}
+
+/**
+ * Make sure the buffers the display uses fit inside a device's limits.
+ * This is just the sizes of the instance and uniform buffers and their strides, at the highest resolution
+ */
+pub fn check_limits(limits: &wgpu::Limits) -> Result<(), DisplayError> {
+
+  let instance_stride = std::mem::size_of::<Instance>() as u64;
+  let instance_buffer_size = instance_stride * (HIRES_WIDTH * HIRES_HEIGHT) as u64;
+  let uniform_buffer_size = std::mem::size_of::<Uniforms>() as u64;
+
+  if instance_buffer_size > limits.max_buffer_size {
+    return Err(DisplayError::ExceedsLimit("max_buffer_size"));
+  }
+  if instance_stride > limits.max_vertex_buffer_array_stride as u64 {
+    return Err(DisplayError::ExceedsLimit("max_vertex_buffer_array_stride"));
+  }
+  if uniform_buffer_size > limits.max_uniform_buffer_binding_size as u64 {
+    return Err(DisplayError::ExceedsLimit("max_uniform_buffer_binding_size"));
+  }
+  // the vertex position takes one attribute and the instances take four
+  if limits.max_vertex_attributes < 5 || limits.max_vertex_buffers < 2 {
+    return Err(DisplayError::ExceedsLimit("vertex attribute count"));
+  }
+
+  return Ok(());
+
 }
 
 /**
  * Display represents both all the visual data and the wgpu instances
  */
 pub struct Display {
-  pub pixels: [[bool; WIDTH]; HEIGHT], // the state of each pixel on the screen
+  // the state of each pixel on the screen, as a bitmask of which planes it's lit on, row by row
+  // regular chip-8 only ever uses the first plane, XO-CHIP can use both for four colours
+  pub pixels: Vec<Vec<u8>>,
+  // how many pixels across and down the screen is, which SCHIP can switch between
+  pub width: usize,
+  pub height: usize,
+  // bitmask of the planes that drawing and clearing currently affect
+  pub plane: u8,
+  // debugging tools drawn over the top of the pixels
+  pub overlay: Overlay,
+  // a colour for each combination of planes: off, plane 1 only, plane 2 only, and both planes
+  pub palette: [[f32; 4]; 4],
+
+  // phosphor fade: pixels that turn off glow for a few frames instead of vanishing, which hides flicker
+  pub phosphor: bool,
+  // how much brightness a faded pixel loses each update
+  pub decay_rate: f32,
+  // how brightly each pixel is glowing, and the colour it was when it was last lit
+  pub brightness: Vec<Vec<f32>>,
+  pub fade_colour: Vec<Vec<u8>>,
+  // smooth rendering draws as often as the monitor refreshes, blending from the last frame's brightness to the newest
+  // over the time between frames, so sprites glide instead of jumping on high refresh rate screens
+  pub smooth: bool,
+  pub previous_brightness: Vec<Vec<f32>>,
+  pub last_update: Instant,
+  pub blend: f32,
 
-  // now all the wgpu stuff
+  // whether to show that the game is waiting for a key, and whether it actually is
+  pub show_key_wait: bool,
+  pub awaiting_keypress: bool,
+  // counts up while the indicator is showing so it can pulse
+  pub indicator_frames: u32,
+  // flash the border while the sound timer is running, for anyone who can't hear the beep
+  pub flash_on_beep: bool,
+  pub beep_flash: bool,
+  // how the pixels are shaped
+  pub pixel_style: PixelStyle,
+  // how brightly unlit pixels still show, from 0 (not at all) to 1, for the look of an LCD
+  pub off_pixel_level: f32,
+  // swap the background and first plane's colours when drawing, leaving the pixels themselves alone
+  pub invert: bool,
+  // outline the last sprite drawn, to see where it landed and how it got clipped or wrapped
+  pub highlight_sprite: bool,
+  pub sprite_box: Option<SpriteBox>,
+  // space left around the game, in window pixels, so it doesn't run right up to the edges
+  pub margin: u32,
+  // a clickable keypad drawn below the game, which takes the bottom half of the window while it's shown
+  pub show_keypad: bool,
+  pub keypad: Overlay,
+  // how many times the screen has been drawn, counting the times it would have been when headless
+  pub renders: Cell<u64>,
+
+  // turning this off skips all the work of drawing, even with a window, so benchmarks only measure the cpu.
+  // pixels still change as the program draws, but nothing else about the screen is kept up to date
+  pub render_enabled: bool,
+
+  // now all the wgpu stuff, which is None when running headless
+  pub gpu: Option<Gpu>
+}
+
+/**
+ * All the wgpu state needed to actually draw the pixels to a window
+ */
+pub struct Gpu {
   pub surface: wgpu::Surface,
   pub device: wgpu::Device,
   pub queue: wgpu::Queue,
   pub render_pipeline: wgpu::RenderPipeline,
   pub config: wgpu::SurfaceConfiguration,
+  // the present modes the surface can actually use, so switching never asks for one it can't do
+  pub present_modes: Vec<wgpu::PresentMode>,
   pub vertex_buffer: wgpu::Buffer,
-  pub instance_buffer: wgpu::Buffer
+  pub instance_buffer: wgpu::Buffer,
+  // the on-screen keypad is drawn with the same pipeline, from its own instances
+  pub keypad_buffer: wgpu::Buffer,
+  pub uniform_buffer: wgpu::Buffer,
+  pub bind_group: wgpu::BindGroup
+}
+
+/**
+ * Ask for a hardware adapter, and then a software one if there isn't one. request is given whether to force the fallback (software) adapter
+ */
+pub async fn request_with_fallback<A, F: Future<Output = Option<A>>>(mut request: impl FnMut(bool) -> F) -> Result<A, DisplayError> {
+
+  if let Some(adapter) = request(false).await {
+    return Ok(adapter);
+  }
+  log::warn!("Couldn't find a hardware adapter, trying a software one");
+
+  if let Some(adapter) = request(true).await {
+    return Ok(adapter);
+  }
+  log::error!("Couldn't find a software adapter either");
+
+  return Err(DisplayError::NoAdapter);
+
 }
 
 impl Display {
 
   /**
-   * Create an instance of the display. This both sets up the pixels array and wgpu for rendering
+   * Create an instance of the display. This both sets up the pixels array and wgpu for rendering.
+   * If there's no gpu that can draw to the window, a software one is tried before giving up.
+   * Only the given backends are tried, which is usually all of them
    */
-  pub async fn new(window: &Window) -> Self {
+  pub async fn new(window: &Window, backends: wgpu::Backends) -> Result<Self, DisplayError> {
 
-    // create an array of pixels, all starting off false
-    let pixels = [[false; WIDTH]; HEIGHT];
+    // start off with all the visual data, which is everything but the wgpu stuff
+    let mut display = Display::headless();
 
     // create a wgpu instance! let's get going
     let size = window.inner_size();
-    let instance = wgpu::Instance::new(wgpu::Backends::all());
+    log::info!("Looking for an adapter with backends {:?}", backends);
+    let instance = wgpu::Instance::new(backends);
 
     let surface = unsafe { instance.create_surface(&window) };
 
-    let adapter = instance.request_adapter(&wgpu::RequestAdapterOptions {
+    // try for a real gpu first, then a software one, which is all that's around on a lot of VMs and CI machines
+    let adapter = request_with_fallback(| force_fallback_adapter | instance.request_adapter(&wgpu::RequestAdapterOptions {
       power_preference: wgpu::PowerPreference::default(),
-      force_fallback_adapter: false,
+      force_fallback_adapter,
       // make sure the adapter is compatible with the surface
       compatible_surface: Some(&surface)
-    }).await.expect("Couldn't find a compatible adapter :/");
+    })).await?;
+    log::info!("Drawing with {} ({:?})", adapter.get_info().name, adapter.get_info().backend);
 
     let (device, queue) = adapter.request_device(&wgpu::DeviceDescriptor {
       label: None,
       features: wgpu::Features::empty(),
       limits: wgpu::Limits::downlevel_webgl2_defaults().using_resolution(adapter.limits())
-    }, None).await.expect("Couldn't get the device :(");
+    }, None).await.map_err(DisplayError::Device)?;
+
+    // webgl2 in particular can be quite limited, so make sure everything fits before wgpu finds out the hard way
+    check_limits(&device.limits())?;
 
     // load the shaders
     let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
@@ -84,13 +383,28 @@ impl Display {
       source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shader.wgsl")))
     });
 
+    // the uniforms (like the palette) are available to both shaders
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("Uniform Bind Group Layout"),
+      entries: &[wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+          ty: wgpu::BufferBindingType::Uniform,
+          has_dynamic_offset: false,
+          min_binding_size: None
+        },
+        count: None
+      }]
+    });
+
     let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
       label: None,
-      bind_group_layouts: &[],
+      bind_group_layouts: &[&bind_group_layout],
       push_constant_ranges: &[]
     });
 
-    let swapchain_format = surface.get_supported_formats(&adapter)[0];
+    let swapchain_format = *surface.get_supported_formats(&adapter).first().ok_or(DisplayError::IncompatibleSurface)?;
 
     let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
       label: None,
@@ -105,9 +419,9 @@ impl Display {
             attributes: &wgpu::vertex_attr_array![0 => Float32x2]
           },
           wgpu::VertexBufferLayout {
-            array_stride: 3 * 4,
+            array_stride: 6 * 4,
             step_mode: wgpu::VertexStepMode::Instance,
-            attributes: &wgpu::vertex_attr_array![1 => Uint32x2, 2 => Uint32]
+            attributes: &wgpu::vertex_attr_array![1 => Uint32x2, 2 => Uint32, 3 => Float32, 4 => Float32, 5 => Uint32]
           }
         ]
       },
@@ -132,6 +446,9 @@ impl Display {
 
     surface.configure(&device, &config);
 
+    // find out which present modes are available for switching to later
+    let present_modes = surface.get_supported_modes(&adapter);
+
     // create the vertex buffer
     let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
       label: Some("Vertex Buffer"),
@@ -139,35 +456,141 @@ impl Display {
       usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST
     });
 
-    // generate the list of instances
-    let instances = Display::gen_instances(&pixels);
-    // and make an instance buffer
-    let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-      label: Some("Instance Buffer"),
-      contents: bytemuck::bytes_of(&instances),
-      usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST
+    // make an instance buffer from the list of instances, and the same kind of buffer for the keypad
+    let (instance_buffer, keypad_buffer) = display.create_instance_buffers(&device);
+
+    // and the uniform buffer, along with the bind group that hands it to the shaders
+    let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some("Uniform Buffer"),
+      contents: bytemuck::bytes_of(&display.gen_uniforms()),
+      usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("Uniform Bind Group"),
+      layout: &bind_group_layout,
+      entries: &[wgpu::BindGroupEntry {
+        binding: 0,
+        resource: uniform_buffer.as_entire_binding()
+      }]
     });
 
     // return an instance of Display using all the variables created
-    return Display { pixels, surface, device, queue, render_pipeline, config, vertex_buffer, instance_buffer };
+    let gpu = Gpu { surface, device, queue, render_pipeline, config, present_modes, vertex_buffer, instance_buffer, keypad_buffer, uniform_buffer, bind_group };
+    display.gpu = Some(gpu);
+    return Ok(display);
+
+  }
+
+  /**
+   * Create a display without a window or any wgpu state, for running the cpu without rendering
+   */
+  pub fn headless() -> Self {
+
+    return Display {
+      pixels: vec![vec![0; WIDTH]; HEIGHT],
+      width: WIDTH,
+      height: HEIGHT,
+      plane: 1,
+      overlay: Overlay::new(),
+      palette: [
+        [0.0, 0.0, 0.0, 1.0],
+        [1.0, 1.0, 1.0, 1.0],
+        [0.67, 0.67, 0.67, 1.0],
+        [0.33, 0.33, 0.33, 1.0]
+      ],
+      phosphor: false,
+      decay_rate: 0.25,
+      brightness: vec![vec![0.0; WIDTH]; HEIGHT],
+      fade_colour: vec![vec![0; WIDTH]; HEIGHT],
+      smooth: false,
+      previous_brightness: vec![vec![0.0; WIDTH]; HEIGHT],
+      last_update: Instant::now(),
+      blend: 1.0,
+      show_key_wait: true,
+      awaiting_keypress: false,
+      indicator_frames: 0,
+      flash_on_beep: false,
+      beep_flash: false,
+      pixel_style: PixelStyle::Sharp,
+      off_pixel_level: 0.0,
+      invert: false,
+      highlight_sprite: false,
+      sprite_box: None,
+      margin: 0,
+      show_keypad: false,
+      keypad: Overlay::new(),
+      renders: Cell::new(0),
+      render_enabled: true,
+      gpu: None
+    };
+
+  }
+
+  // the overlay and keypad are always 64x32, so at higher resolutions each of their cells covers a few pixels
+  fn overlay_cell(&self, image: &Overlay, x: usize, y: usize) -> u8 {
+
+    return image.cells[y * HEIGHT / self.height][x * WIDTH / self.width];
+
+  }
+
+  // generate the instances for the keypad, which is just its image at full brightness
+  // it's drawn on the same grid as the game so they can share the uniforms
+  pub fn gen_keypad_instances(&self) -> Vec<Instance> {
+
+    let mut instances: Vec<Instance> = Vec::with_capacity(self.width * self.height);
+
+    for y in 0..self.height {
+      for x in 0..self.width {
+        let cell = self.overlay_cell(&self.keypad, x, y);
+        instances.push(Instance {
+          pos: [x as u32, y as u32],
+          color_index: cell as u32,
+          brightness: if cell != overlay::TRANSPARENT { 1.0 } else { 0.0 },
+          previous: if cell != overlay::TRANSPARENT { 1.0 } else { 0.0 },
+          indicators: 0
+        });
+      }
+    }
+
+    return instances;
 
   }
 
   // generate a list of instances of the pixels to render
-  pub fn gen_instances(pixels: &[[bool; WIDTH]; HEIGHT]) -> [Instance; WIDTH * HEIGHT] {
+  pub fn gen_instances(&self) -> Vec<Instance> {
 
-    let mut instances: [Instance; WIDTH * HEIGHT] = [Instance {pos: [0, 0], on: 0}; WIDTH * HEIGHT];
+    let mut instances: Vec<Instance> = Vec::with_capacity(self.width * self.height);
 
     // loop through every tile and generate an instance for that position
-    for y in 0..HEIGHT {
+    for y in 0..self.height {
 
-      for x in 0..WIDTH {
+      for x in 0..self.width {
+
+        // anything on the overlay covers up the pixel underneath, otherwise use the
+        // colour the pixel was last lit with, at however bright it's still glowing
+        let cell = self.overlay_cell(&self.overlay, x, y);
+        // the sprite highlight goes on top of the game but under anything else on the overlay
+        let highlighted = match self.sprite_box {
+          Some(sprite_box) => self.highlight_sprite && sprite_box.outlines(x, y, self.width, self.height),
+          None => false
+        };
+        // (only the game's pixels blend between frames, the rest just shows as it is)
+        let (color_index, brightness, previous) = if cell != overlay::TRANSPARENT {
+          (cell, 1.0, 1.0)
+        } else if highlighted {
+          (overlay::TEXT, 1.0, 1.0)
+        } else {
+          (self.fade_colour[y][x], self.brightness[y][x], self.previous_brightness[y][x])
+        };
 
         // create the instance
-        instances[y * WIDTH + x] = Instance {
+        instances.push(Instance {
           pos: [x as u32, y as u32],
-          on: pixels[y][x] as u32
-        }
+          color_index: color_index as u32,
+          brightness,
+          previous,
+          indicators: 1
+        });
 
       }
 
@@ -177,61 +600,460 @@ impl Display {
 
   }
 
+  // put together the values for the uniform buffer
+  pub fn gen_uniforms(&self) -> Uniforms {
+
+    let mut palette = [[0.0; 4]; 8];
+    palette[..4].copy_from_slice(&self.palette);
+    palette[4..].copy_from_slice(&OVERLAY_COLOURS);
+
+    // pulse the indicator slowly, about once a second
+    let mut indicator = [0.0; 4];
+    if self.show_key_wait && self.awaiting_keypress {
+      indicator[0] = 0.5 - 0.5 * (self.indicator_frames as f32 * 0.1).cos();
+    }
+    if self.flash_on_beep && self.beep_flash {
+      indicator[1] = 1.0;
+    }
+
+    // the shader matches on the same numbers
+    let style = [self.pixel_style as u32, self.invert as u32, 0, 0];
+
+    // without smooth rendering, the newest frame is all that shows
+    let blend = if self.smooth { self.blend } else { 1.0 };
+    let levels = [self.off_pixel_level, blend, 0.0, 0.0];
+    let grid = [self.width as f32, self.height as f32, 0.0, 0.0];
+
+    return Uniforms { palette, indicator, style, levels, grid };
+
+  }
+
+  // make the buffers for the game's and the keypad's instances, sized for the current resolution
+  fn create_instance_buffers(&self, device: &wgpu::Device) -> (wgpu::Buffer, wgpu::Buffer) {
+
+    let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some("Instance Buffer"),
+      contents: bytemuck::cast_slice(&self.gen_instances()),
+      usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST
+    });
+    let keypad_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some("Keypad Buffer"),
+      contents: bytemuck::cast_slice(&self.gen_keypad_instances()),
+      usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST
+    });
+
+    return (instance_buffer, keypad_buffer);
+
+  }
+
+  /**
+   * Switch to a different number of pixels across and down, like SCHIP's 128x64 high resolution mode.
+   * The screen is cleared either way, and the instance buffers are made again to fit if the size changed
+   */
+  pub fn set_resolution(&mut self, width: usize, height: usize) {
+
+    self.pixels = vec![vec![0; width]; height];
+    self.brightness = vec![vec![0.0; width]; height];
+    self.fade_colour = vec![vec![0; width]; height];
+    self.previous_brightness = vec![vec![0.0; width]; height];
+    // a sprite that was highlighted at the old size might not even fit now
+    self.sprite_box = None;
+
+    if width == self.width && height == self.height {
+      return;
+    }
+    self.width = width;
+    self.height = height;
+    log::info!("The screen is now {}x{}", width, height);
+
+    // the old buffers are dropped once they're replaced, which frees them on the gpu
+    if let Some(gpu) = &self.gpu {
+      let (instance_buffer, keypad_buffer) = self.create_instance_buffers(&gpu.device);
+      if let Some(gpu) = &mut self.gpu {
+        gpu.instance_buffer = instance_buffer;
+        gpu.keypad_buffer = keypad_buffer;
+      }
+    }
+
+  }
+
+  // change how the pixels are shaped
+  pub fn set_pixel_style(&mut self, style: PixelStyle) {
+
+    self.pixel_style = style;
+
+  }
+
+  // change how brightly unlit pixels show, kept between 0 and 1
+  pub fn set_off_pixel_level(&mut self, level: f32) {
+
+    // NaN would end up in the shader otherwise
+    self.off_pixel_level = if level.is_nan() { 0.0 } else { level.clamp(0.0, 1.0) };
+
+  }
+
+  // swap the background and foreground colours, or swap them back
+  pub fn toggle_invert(&mut self) {
+
+    self.invert = !self.invert;
+
+  }
+
+  // the colour behind the game, which is the first plane's colour when inverted
+  pub fn background_colour(&self) -> [f32; 4] {
+
+    return if self.invert { self.palette[1] } else { self.palette[0] };
+
+  }
+
+  // make the surface match a new window size. a minimised window is 0x0, which can't be drawn to, so that's skipped
+  pub fn resize(&mut self, width: u32, height: u32) {
+
+    let gpu = match &mut self.gpu {
+      Some(gpu) => gpu,
+      None => return
+    };
+    if width == 0 || height == 0 {
+      return;
+    }
+
+    gpu.config.width = width;
+    gpu.config.height = height;
+    gpu.surface.configure(&gpu.device, &gpu.config);
+
+  }
+
+  // change how much space is left around the game, in window pixels. the background colour fills it
+  pub fn set_margin(&mut self, pixels: u32) {
+
+    self.margin = pixels;
+
+  }
+
+  // where the game gets drawn inside an area of the window, as x, y, width and height, after taking off the margin
+  // on every side. there's always at least a pixel left to draw in, however big the margin is
+  pub fn drawable_area(&self, x: f32, y: f32, width: f32, height: f32) -> (f32, f32, f32, f32) {
+
+    let margin = (self.margin as f32).min((width - 1.0) / 2.0).min((height - 1.0) / 2.0).max(0.0);
+    return (x + margin, y + margin, width - margin * 2.0, height - margin * 2.0);
+
+  }
+
+  // change the colours used for each combination of planes
+  pub fn set_palette(&mut self, palette: [[f32; 4]; 4]) {
+
+    self.palette = palette;
+
+  }
+
   // updates the instance buffer for the new data present
   pub fn update(&mut self) {
 
+    if !self.render_enabled {
+      return;
+    }
+
+    // the frame that's about to be replaced is what smooth rendering blends from
+    self.previous_brightness.clone_from(&self.brightness);
+    self.last_update = Instant::now();
+    self.blend = 0.0;
+    self.update_brightness();
+
+    // the indicator starts from nothing every time the game starts waiting
+    if self.awaiting_keypress {
+      self.indicator_frames += 1;
+    } else {
+      self.indicator_frames = 0;
+    }
+
+    self.upload();
+
+  }
+
+  /**
+   * Draw the pixels as they are right now, partway through a frame. Lit pixels show up straight away, but
+   * nothing fades since the fade happens once per frame
+   */
+  pub fn render_now(&mut self) {
+
+    if !self.render_enabled {
+      return;
+    }
+
+    for y in 0..self.height {
+      for x in 0..self.width {
+        if self.pixels[y][x] != 0 {
+          self.brightness[y][x] = 1.0;
+          self.fade_colour[y][x] = self.pixels[y][x];
+        } else if !self.phosphor {
+          self.brightness[y][x] = 0.0;
+        }
+      }
+    }
+
+    self.upload();
+    self.render();
+
+  }
+
+  /**
+   * Draw again partway between frames for smooth rendering, blending further towards the newest frame the longer it's been since it arrived
+   */
+  pub fn render_blended(&mut self) {
+
+    if !self.render_enabled {
+      return;
+    }
+
+    self.blend = timing::blend_factor(self.last_update.elapsed());
+
+    // only the uniforms change, so that's all that needs sending
+    if let Some(gpu) = &self.gpu {
+      gpu.queue.write_buffer(&gpu.uniform_buffer, 0, bytemuck::bytes_of(&self.gen_uniforms()));
+    }
+    self.render();
+
+  }
+
+  /**
+   * Send the instances and uniforms over to the gpu
+   */
+  pub fn upload(&self) {
+
+    // there's nothing to upload to when headless (or when rendering's off)
+    let gpu = match &self.gpu {
+      Some(gpu) if self.render_enabled => gpu,
+      _ => return
+    };
+
     // generate a new list of instances based on the updated data
-    let new_instances = Display::gen_instances(&self.pixels);
+    let new_instances = self.gen_instances();
     // and write it to the buffer
-    self.queue.write_buffer(&self.instance_buffer, 0, bytemuck::bytes_of(&new_instances));
+    gpu.queue.write_buffer(&gpu.instance_buffer, 0, bytemuck::cast_slice(&new_instances));
+    // along with the uniforms, in case anything like the palette changed
+    gpu.queue.write_buffer(&gpu.uniform_buffer, 0, bytemuck::bytes_of(&self.gen_uniforms()));
+    // and the keypad, if it's showing
+    if self.show_keypad {
+      gpu.queue.write_buffer(&gpu.keypad_buffer, 0, bytemuck::cast_slice(&self.gen_keypad_instances()));
+    }
+
+  }
+
+  // lit pixels are at full brightness, and unlit ones fade away if phosphor is on (or vanish if it isn't)
+  pub fn update_brightness(&mut self) {
+
+    for y in 0..self.height {
+      for x in 0..self.width {
+
+        if self.pixels[y][x] != 0 {
+          self.brightness[y][x] = 1.0;
+          self.fade_colour[y][x] = self.pixels[y][x];
+        } else if self.phosphor {
+          self.brightness[y][x] = (self.brightness[y][x] - self.decay_rate).max(0.0);
+        } else {
+          self.brightness[y][x] = 0.0;
+        }
+
+      }
+    }
+
+  }
+
+  // flip a pixel on the given plane (1 or 2), as long as that plane is selected
+  pub fn set_pixel(&mut self, x: i32, y: i32, plane: u8) -> bool {
+
+    let (ux, uy) = self.wrap(x, y);
+
+    // planes that aren't selected are left alone
+    let plane = plane & self.plane;
+
+    // set the pixel on this plane to whatever it currently isn't
+    self.pixels[uy][ux] ^= plane;
+
+    // return whether the pixel was erased (which means the plane's bit is now off)
+    return plane != 0 && self.pixels[uy][ux] & plane == 0;
 
   }
 
-  // update the display
-  pub fn set_pixel(&mut self, x: i32, y: i32) -> bool {
+  // turn a pixel on for the given plane without ever turning it off, for the OR draw mode
+  pub fn or_pixel(&mut self, x: i32, y: i32, plane: u8) -> bool {
+
+    let (ux, uy) = self.wrap(x, y);
+    let plane = plane & self.plane;
+
+    // a pixel that was already on would have been erased by a regular draw, so it still counts as a collision
+    let collided = plane != 0 && self.pixels[uy][ux] & plane != 0;
+    self.pixels[uy][ux] |= plane;
+
+    return collided;
+
+  }
+
+  // bring coordinates that are just off the screen back on from the other side
+  fn wrap(&self, x: i32, y: i32) -> (usize, usize) {
+
+    let width = self.width as i32;
+    let height = self.height as i32;
 
     // chip8 coords wrap around if negative
     // unsigned integer versions must be used so coordinates work right
     let ux: usize;
     let uy: usize;
     if x < 0 {
-      ux = (x + WIDTH as i32) as usize;
-    } else if x >= WIDTH as i32 {
-      ux = (x - WIDTH as i32) as usize;
+      ux = (x + width) as usize;
+    } else if x >= width {
+      ux = (x - width) as usize;
     } else {
       ux = x as usize;
     }
     if y < 0 {
-      uy = (y + HEIGHT as i32) as usize;
-    } else if y >= HEIGHT as i32 {
-      uy = (y - HEIGHT as i32) as usize;
+      uy = (y + height) as usize;
+    } else if y >= height {
+      uy = (y - height) as usize;
     } else {
       uy = y as usize;
     }
 
-    // set the pixel to whatever it currently isn't
-    self.pixels[uy][ux] = !self.pixels[uy][ux];
+    return (ux, uy);
+
+  }
 
-    // return whether the pixel was erased (which equals the inverse of what it was just set to)
-    return !self.pixels[uy][ux];
+  // a copy of which pixels are lit, on any plane
+  pub fn get_framebuffer(&self) -> Vec<Vec<bool>> {
+
+    return self.pixels.iter().map(| row | row.iter().map(| pixel | *pixel != 0).collect()).collect();
+
+  }
+
+  // replace the whole screen, with every lit pixel on the first plane
+  pub fn set_framebuffer(&mut self, framebuffer: &[Vec<bool>]) {
+
+    for (pixels, row) in self.pixels.iter_mut().zip(framebuffer.iter()) {
+      for (pixel, lit) in pixels.iter_mut().zip(row.iter()) {
+        *pixel = *lit as u8;
+      }
+    }
 
   }
 
-  // completely clear the screen
+  // the screen packed into bytes (256 of them at 64x32), one bit per pixel with the leftmost pixel in the most
+  // significant bit, the same way sprites are laid out in chip-8 memory
+  pub fn as_packed_bits(&self) -> Vec<u8> {
+
+    let mut bits: Vec<u8> = vec![0; self.width * self.height / 8];
+    for y in 0..self.height {
+      for x in 0..self.width {
+        if self.pixels[y][x] != 0 {
+          bits[(y * self.width + x) / 8] |= 0x80 >> (x % 8);
+        }
+      }
+    }
+
+    return bits;
+
+  }
+
+  // how many pixels are different from an expected frame, counting a pixel lit on any plane as lit
+  pub fn diff(&self, other: &[Vec<bool>]) -> usize {
+
+    return self.pixels.iter().flatten().zip(other.iter().flatten())
+      .filter(| (pixel, lit) | (**pixel != 0) != **lit)
+      .count();
+
+  }
+
+  // a fingerprint of the frame, which stays the same between runs and builds (unlike std's hasher)
+  // this is 64 bit FNV-1a over the plane bitmask of every pixel
+  pub fn hash(&self) -> u64 {
+
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for pixel in self.pixels.iter().flatten() {
+      hash ^= *pixel as u64;
+      hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    return hash;
+
+  }
+
+  // clear the screen, only on the selected planes
   pub fn clear(&mut self) {
 
-    // set every pixel value to false (empty)
-    self.pixels = [[false; WIDTH]; HEIGHT];
+    // turn off the selected planes' bits for every pixel
+    for row in self.pixels.iter_mut() {
+      for pixel in row.iter_mut() {
+        *pixel &= !self.plane;
+      }
+    }
+
+  }
+
+  // switch to a different present mode, falling back to Fifo (which every surface supports) if the
+  // requested one isn't available. returns the mode that's actually in use
+  pub fn set_present_mode(&mut self, mode: wgpu::PresentMode) -> wgpu::PresentMode {
+
+    // headless displays don't present at all
+    let gpu = match &mut self.gpu {
+      Some(gpu) => gpu,
+      None => return mode
+    };
+
+    let mode = if gpu.present_modes.contains(&mode) {
+      mode
+    } else {
+      log::warn!("Present mode {:?} isn't supported, falling back to Fifo", mode);
+      wgpu::PresentMode::Fifo
+    };
+
+    // the surface needs to be reconfigured for the change to take effect
+    gpu.config.present_mode = mode;
+    gpu.surface.configure(&gpu.device, &gpu.config);
+    log::info!("Present mode is now {:?}", mode);
+
+    return mode;
+
+  }
+
+  // move on to the next present mode: Fifo (vsync) -> Mailbox -> Immediate (lowest latency) -> back to Fifo,
+  // skipping any that aren't supported
+  pub fn cycle_present_mode(&mut self) {
+
+    let gpu = match &self.gpu {
+      Some(gpu) => gpu,
+      None => return
+    };
+
+    let order = [wgpu::PresentMode::Fifo, wgpu::PresentMode::Mailbox, wgpu::PresentMode::Immediate];
+    let current = order.iter().position(| mode | *mode == gpu.config.present_mode).unwrap_or(0);
+
+    // find the next mode in the order that the surface supports, which will come back round to Fifo at worst
+    let next = (1..=order.len())
+      .map(| offset | order[(current + offset) % order.len()])
+      .find(| mode | gpu.present_modes.contains(mode))
+      .unwrap_or(wgpu::PresentMode::Fifo);
+
+    self.set_present_mode(next);
 
   }
 
   // render will actually paint the pixels ooh that's WGPU time
   pub fn render(&self) {
-    
-    let frame = self.surface.get_current_texture().expect("Couldn't get the current texture");
+
+    if !self.render_enabled {
+      return;
+    }
+    self.renders.set(self.renders.get() + 1);
+
+    // nothing to render to when headless
+    let gpu = match &self.gpu {
+      Some(gpu) => gpu,
+      None => return
+    };
+
+    let frame = gpu.surface.get_current_texture().expect("Couldn't get the current texture");
     let view = frame.texture.create_view(&wgpu::TextureViewDescriptor::default());
 
-    let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    let mut encoder = gpu.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
 
     {
 
@@ -241,23 +1063,309 @@ impl Display {
           view: &view,
           resolve_target: None,
           ops: wgpu::Operations {
-            load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+            // clear to the background colour
+            load: wgpu::LoadOp::Clear(wgpu::Color {
+              r: self.background_colour()[0] as f64,
+              g: self.background_colour()[1] as f64,
+              b: self.background_colour()[2] as f64,
+              a: 1.0
+            }),
             store: true
           }
         })],
         depth_stencil_attachment: None
       });
 
-      pass.set_pipeline(&self.render_pipeline);
-      pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-      pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
-      pass.draw(0..6, 0..(WIDTH as u32 * HEIGHT as u32));
+      pass.set_pipeline(&gpu.render_pipeline);
+      pass.set_bind_group(0, &gpu.bind_group, &[]);
+      pass.set_vertex_buffer(0, gpu.vertex_buffer.slice(..));
+      // with the keypad showing, the game goes in the top half of the window and the keypad in the bottom
+      // the margin is worked out from the surface's size every time, so it stays right after a resize
+      let width = gpu.config.width as f32;
+      let height = gpu.config.height as f32;
+      let game_height = if self.show_keypad { height / 2.0 } else { height };
+      let (game_x, game_y, game_width, game_height) = self.drawable_area(0.0, 0.0, width, game_height);
+      pass.set_viewport(game_x, game_y, game_width, game_height, 0.0, 1.0);
+
+      let pixel_count = (self.width * self.height) as u32;
+      pass.set_vertex_buffer(1, gpu.instance_buffer.slice(..));
+      pass.draw(0..6, 0..pixel_count);
+
+      if self.show_keypad {
+        pass.set_viewport(0.0, height / 2.0, width, height / 2.0, 0.0, 1.0);
+        pass.set_vertex_buffer(1, gpu.keypad_buffer.slice(..));
+        pass.draw(0..6, 0..pixel_count);
+      }
 
     }
 
-    self.queue.submit(Some(encoder.finish()));
+    gpu.queue.submit(Some(encoder.finish()));
     frame.present();
 
   }
 
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+
+  use super::*;
+
+  #[test]
+  fn phosphor_fades_a_cleared_pixel_a_little_each_update() {
+
+    let mut display = Display::headless();
+    display.phosphor = true;
+    display.set_pixel(3, 4, 1);
+    display.update();
+    assert_eq!(display.brightness[4][3], 1.0);
+
+    display.clear();
+    let mut last = 1.0;
+    while last > 0.0 {
+      display.update();
+      let brightness = display.brightness[4][3];
+      assert!(brightness < last, "{} should be dimmer than {}", brightness, last);
+      last = brightness;
+    }
+    assert_eq!(last, 0.0);
+
+  }
+
+  #[test]
+  fn instances_are_coloured_by_their_planes() {
+
+    let mut display = Display::headless();
+    display.plane = 3;
+    // plane 1 only, plane 2 only, both, and neither
+    display.set_pixel(0, 0, 1);
+    display.set_pixel(1, 0, 2);
+    display.set_pixel(2, 0, 1);
+    display.set_pixel(2, 0, 2);
+    display.update();
+
+    let colours: Vec<u32> = display.gen_instances()[..4].iter().map(| instance | instance.color_index).collect();
+    assert_eq!(colours, vec![1, 2, 3, 0]);
+    assert_eq!(display.gen_instances().len(), WIDTH * HEIGHT);
+
+  }
+
+  #[test]
+  fn packed_bits_and_the_framebuffer_match_up() {
+
+    // a diagonal line across the top left, and the last pixel on the screen
+    let mut grid = vec![vec![false; WIDTH]; HEIGHT];
+    for (i, row) in grid.iter_mut().enumerate().take(8) {
+      row[i] = true;
+    }
+    grid[HEIGHT - 1][WIDTH - 1] = true;
+
+    let mut display = Display::headless();
+    display.set_framebuffer(&grid);
+    assert_eq!(display.get_framebuffer(), grid);
+
+    // each of the first 8 rows has one bit set in its first byte, moving right a bit each row
+    let bits = display.as_packed_bits();
+    assert_eq!(bits.len(), 256);
+    for i in 0..8 {
+      assert_eq!(bits[i * 8], 0x80 >> i);
+    }
+    assert_eq!(bits[255], 0x01);
+    assert_eq!(bits.iter().map(| byte | byte.count_ones()).sum::<u32>(), 9);
+
+    // unpacking the bits gives the grid back
+    let unpacked: Vec<Vec<bool>> = (0..HEIGHT).map(| y | (0..WIDTH).map(| x | bits[(y * WIDTH + x) / 8] & (0x80 >> (x % 8)) != 0).collect()).collect();
+    assert_eq!(unpacked, grid);
+
+  }
+
+  #[test]
+  fn the_software_adapter_is_tried_when_there_is_no_hardware_one() {
+
+    // stand-ins for wgpu, which only has a software adapter, or a hardware one, or neither
+    let mut asked: Vec<bool> = vec![];
+    let adapter = pollster::block_on(request_with_fallback(| fallback | {
+      asked.push(fallback);
+      std::future::ready(fallback.then_some("software"))
+    }));
+    assert_eq!(adapter.unwrap(), "software");
+    assert_eq!(asked, vec![false, true]);
+
+    let adapter = pollster::block_on(request_with_fallback(| fallback | std::future::ready((!fallback).then_some("hardware"))));
+    assert_eq!(adapter.unwrap(), "hardware");
+
+    let adapter = pollster::block_on(request_with_fallback(| _ | std::future::ready(None::<&str>)));
+    assert!(matches!(adapter, Err(DisplayError::NoAdapter)));
+
+  }
+
+  #[test]
+  fn only_the_game_shows_the_indicators() {
+
+    // the shader finds the screen's edges from the grid, so they follow the resolution
+    let mut display = Display::headless();
+    display.set_resolution(HIRES_WIDTH, HIRES_HEIGHT);
+    assert_eq!(display.gen_uniforms().grid, [HIRES_WIDTH as f32, HIRES_HEIGHT as f32, 0.0, 0.0]);
+
+    // and leaves the keypad alone, even though it's drawn on the same grid
+    assert!(display.gen_instances().iter().all(| instance | instance.indicators == 1));
+    assert!(display.gen_keypad_instances().iter().all(| instance | instance.indicators == 0));
+
+  }
+
+  #[test]
+  fn the_buffers_fit_in_webgl2() {
+
+    let limits = wgpu::Limits::downlevel_webgl2_defaults();
+
+    // every pixel at the highest resolution, each of them an instance
+    let mut display = Display::headless();
+    display.set_resolution(HIRES_WIDTH, HIRES_HEIGHT);
+    let instance_bytes = (display.gen_instances().len() * std::mem::size_of::<Instance>()) as u64;
+    assert_eq!(instance_bytes, (HIRES_WIDTH * HIRES_HEIGHT * std::mem::size_of::<Instance>()) as u64);
+    assert!(instance_bytes <= limits.max_buffer_size);
+    assert!(std::mem::size_of::<Uniforms>() as u64 <= limits.max_uniform_buffer_binding_size as u64);
+    assert!(check_limits(&limits).is_ok());
+
+    // and a device that can't fit them says which limit was too small
+    let tiny = wgpu::Limits { max_buffer_size: instance_bytes - 1, ..limits };
+    assert!(matches!(check_limits(&tiny), Err(DisplayError::ExceedsLimit("max_buffer_size"))));
+
+  }
+
+  #[test]
+  fn backend_names_map_to_their_bits() {
+
+    assert_eq!(backends_from_name("vulkan"), Some(wgpu::Backends::VULKAN));
+    assert_eq!(backends_from_name(" VK "), Some(wgpu::Backends::VULKAN));
+    assert_eq!(backends_from_name("Dx12"), Some(wgpu::Backends::DX12));
+    assert_eq!(backends_from_name("d3d11"), Some(wgpu::Backends::DX11));
+    assert_eq!(backends_from_name("metal"), Some(wgpu::Backends::METAL));
+    assert_eq!(backends_from_name("opengl"), Some(wgpu::Backends::GL));
+    assert_eq!(backends_from_name("all"), Some(wgpu::Backends::all()));
+    assert_eq!(backends_from_name("directx"), None);
+    assert_eq!(backends_from_name(""), None);
+
+  }
+
+  #[test]
+  fn frames_can_be_diffed_and_hashed() {
+
+    let mut display = Display::headless();
+    display.set_pixel(10, 5, 1);
+    let grid = display.get_framebuffer();
+
+    // the same frame has nothing different, and one pixel off is one different
+    assert_eq!(display.diff(&grid), 0);
+    let mut moved = grid;
+    moved[5][11] = true;
+    assert_eq!(display.diff(&moved), 1);
+
+    // the hash stays the same until the frame changes
+    let hash = display.hash();
+    assert_eq!(display.hash(), hash);
+    display.set_pixel(0, 0, 1);
+    assert_ne!(display.hash(), hash);
+    display.set_pixel(0, 0, 1);
+    assert_eq!(display.hash(), hash);
+
+  }
+
+  #[test]
+  fn the_off_pixel_level_is_kept_between_0_and_1() {
+
+    let mut display = Display::headless();
+    display.set_off_pixel_level(0.25);
+    assert_eq!(display.off_pixel_level, 0.25);
+
+    display.set_off_pixel_level(1.5);
+    assert_eq!(display.off_pixel_level, 1.0);
+    display.set_off_pixel_level(-0.5);
+    assert_eq!(display.off_pixel_level, 0.0);
+    // and nonsense just turns it off
+    display.set_off_pixel_level(f32::NAN);
+    assert_eq!(display.off_pixel_level, 0.0);
+
+  }
+
+  #[test]
+  fn switching_resolution_clears_the_screen() {
+
+    let mut display = Display::headless();
+    display.pixels[31][63] = 1;
+
+    display.set_resolution(128, 64);
+    assert_eq!((display.width, display.height), (128, 64));
+    assert_eq!((display.pixels.len(), display.pixels[0].len()), (64, 128));
+    assert!(display.pixels.iter().flatten().all(| pixel | *pixel == 0));
+
+    // and back again
+    display.pixels[63][127] = 1;
+    display.set_resolution(WIDTH, HEIGHT);
+    assert_eq!((display.width, display.height), (WIDTH, HEIGHT));
+    assert_eq!((display.pixels.len(), display.pixels[0].len()), (HEIGHT, WIDTH));
+    assert!(display.pixels.iter().flatten().all(| pixel | *pixel == 0));
+
+  }
+
+  #[test]
+  fn the_margin_comes_off_every_side() {
+
+    let mut display = Display::headless();
+    assert_eq!(display.drawable_area(0.0, 0.0, 640.0, 320.0), (0.0, 0.0, 640.0, 320.0));
+
+    display.set_margin(20);
+    assert_eq!(display.drawable_area(0.0, 0.0, 640.0, 320.0), (20.0, 20.0, 600.0, 280.0));
+    assert_eq!(display.drawable_area(10.0, 5.0, 640.0, 320.0), (30.0, 25.0, 600.0, 280.0));
+    // but never so much that there's nothing left
+    display.set_margin(1000);
+    assert_eq!(display.drawable_area(0.0, 0.0, 640.0, 321.0), (160.0, 160.0, 320.0, 1.0));
+
+  }
+
+  #[test]
+  fn inverting_swaps_the_colours_but_not_the_pixels() {
+
+    let mut display = Display::headless();
+    display.set_pixel(3, 4, 1);
+    let pixels = display.pixels.clone();
+    assert_eq!(display.background_colour(), display.palette[0]);
+
+    display.toggle_invert();
+    assert!(display.invert);
+    assert_eq!(display.background_colour(), display.palette[1]);
+    assert_eq!(display.pixels, pixels);
+
+    // and it goes back the way it was
+    display.toggle_invert();
+    assert!(!display.invert);
+    assert_eq!(display.background_colour(), display.palette[0]);
+    assert_eq!(display.pixels, pixels);
+
+  }
+
+  #[test]
+  fn turning_rendering_off_still_keeps_the_pixels() {
+
+    let mut display = Display::headless();
+    display.render_enabled = false;
+    display.set_pixel(3, 4, 1);
+    assert_eq!(display.pixels[4][3], 1);
+
+    // none of the drawing work happens, so the brightness the instances are made from never catches up
+    display.update();
+    display.render_now();
+    display.render_blended();
+    display.upload();
+    display.render();
+    assert_eq!(display.brightness[4][3], 0.0);
+    assert_eq!(display.pixels[4][3], 1);
+
+    // until it's turned back on
+    display.render_enabled = true;
+    display.update();
+    assert_eq!(display.brightness[4][3], 1.0);
+
+  }
+
+}
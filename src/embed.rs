@@ -0,0 +1,169 @@
+/**
+ * A small api for driving the emulator from javascript, for pages that want to draw and handle input themselves
+ * instead of using the built-in canvas. The cpu runs headless, and the page reads the pixels back out after each frame
+ */
+use wasm_bindgen::prelude::*;
+use crate::{cpu::Cpu, command::Command};
+
+#[wasm_bindgen]
+pub struct WasmEmul8 {
+  cpu: Cpu
+}
+
+#[wasm_bindgen]
+impl WasmEmul8 {
+
+  /**
+   * Create an emulator with the font loaded and nothing else, ready for a rom
+   */
+  #[wasm_bindgen(constructor)]
+  pub fn new() -> Self {
+
+    let mut cpu = Cpu::headless();
+    cpu.load_sprites_to_memory();
+    cpu.auto_profile = true;
+
+    return WasmEmul8 { cpu };
+
+  }
+
+  /**
+   * Swap to a new program, throwing if it doesn't fit in memory
+   */
+  pub fn load_rom(&mut self, bytes: &[u8]) -> Result<(), JsValue> {
+
+    return match self.cpu.load_new_rom(bytes.to_vec()) {
+      Ok(_) => Ok(()),
+      Err(error) => Err(JsValue::from_str(&error.to_string()))
+    };
+
+  }
+
+  /**
+   * Run a single frame, so this should be called 60 times a second, like from requestAnimationFrame
+   */
+  pub fn cycle(&mut self) {
+
+    self.cpu.cycle();
+
+  }
+
+  /**
+   * Hold down or let go of a chip-8 key, from 0 to F
+   */
+  pub fn key_down(&mut self, code: u8) {
+
+    if code <= 0xF {
+      self.cpu.keyboard.press(code);
+    }
+
+  }
+
+  pub fn key_up(&mut self, code: u8) {
+
+    if code <= 0xF {
+      self.cpu.keyboard.release(code);
+    }
+
+  }
+
+  /**
+   * Call a function with the key and whether it's down whenever a key (from either this api or the program's own
+   * input) actually changes, like `emul8.set_key_callback((key, down) => ...)`
+   */
+  pub fn set_key_callback(&mut self, callback: js_sys::Function) {
+
+    self.cpu.keyboard.set_on_change(Box::new(move | key, down | {
+      // there's nothing useful to do if the page's callback throws
+      let _ = callback.call2(&JsValue::NULL, &JsValue::from(key), &JsValue::from(down));
+    }));
+
+  }
+
+  /**
+   * Call a function with true when the beep starts and false when it stops, so the page can make its own sound,
+   * like `emul8.set_sound_callback((on) => ...)`
+   */
+  pub fn set_sound_callback(&mut self, callback: js_sys::Function) {
+
+    self.cpu.set_sound_callback(Box::new(move | on | {
+      let _ = callback.call1(&JsValue::NULL, &JsValue::from(on));
+    }));
+
+  }
+
+  /**
+   * Stop running until resume is called, frames still go by but nothing happens in them
+   */
+  pub fn pause(&mut self) {
+
+    self.cpu.handle_command(Command::Pause);
+
+  }
+
+  pub fn resume(&mut self) {
+
+    self.cpu.handle_command(Command::Resume);
+
+  }
+
+  /**
+   * How many pixels across and down the screen is, which is 64x32 unless a SCHIP program switches to 128x64
+   */
+  pub fn width(&self) -> usize {
+
+    return self.cpu.display.width;
+
+  }
+
+  pub fn height(&self) -> usize {
+
+    return self.cpu.display.height;
+
+  }
+
+  /**
+   * The screen one byte per pixel, row by row. Each byte is a bitmask of the planes the pixel is lit on,
+   * so for regular chip-8 it's just 0 for off and 1 for on
+   */
+  pub fn get_framebuffer(&self) -> Vec<u8> {
+
+    return self.cpu.display.pixels.iter().flatten().copied().collect();
+
+  }
+
+}
+
+impl Default for WasmEmul8 {
+
+  fn default() -> Self {
+    return WasmEmul8::new();
+  }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+  use super::*;
+  use wasm_bindgen_test::wasm_bindgen_test;
+
+  #[wasm_bindgen_test]
+  fn loading_a_rom_and_cycling_draws_to_the_framebuffer() {
+
+    let mut emul8 = WasmEmul8::new();
+    assert_eq!(emul8.get_framebuffer().len(), emul8.width() * emul8.height());
+    assert!(emul8.get_framebuffer().iter().all(| pixel | *pixel == 0));
+
+    // draw the 0 from the font in the corner, then stop
+    emul8.load_rom(&[0xA0, 0x00, 0xD0, 0x05, 0x12, 0x04]).unwrap();
+    emul8.cycle();
+
+    let framebuffer = emul8.get_framebuffer();
+    assert_eq!(&framebuffer[..4], &[1, 1, 1, 1]);
+    assert_eq!(framebuffer[emul8.width()], 1);
+    assert_eq!(framebuffer[4], 0);
+
+  }
+
+}
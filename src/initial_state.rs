@@ -0,0 +1,146 @@
+/**
+ * An initial state sets registers and memory before a program starts, so a scenario can be set up without
+ * writing a prologue for it. Directives look like `V0=0x10`, `I=0x300`, `PC=0x240`, `DT=60`, `ST=5` or `[0x300]=0xAB`,
+ * split up by commas or new lines, with anything after a # ignored. Numbers are decimal unless they start with 0x
+ */
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateDirective {
+  // one of v[0] through v[F], and its value
+  Register(usize, u8),
+  I(usize),
+  Pc(usize),
+  DelayTimer(u8),
+  SoundTimer(u8),
+  // an address in memory, and the byte to put there
+  Memory(usize, u8)
+}
+
+/**
+ * A directive that couldn't be understood or doesn't fit
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateError {
+  pub directive: String,
+  pub message: String
+}
+
+impl fmt::Display for StateError {
+
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "\"{}\": {}", self.directive, self.message)
+  }
+
+}
+
+/**
+ * Read every directive out of an initial state's text. Addresses have to fit in memory_size
+ */
+pub fn parse_initial_state(text: &str, memory_size: usize) -> Result<Vec<StateDirective>, StateError> {
+
+  let mut directives: Vec<StateDirective> = vec![];
+
+  for line in text.lines() {
+
+    // get rid of comments first, since they might have commas in them
+    let line = line.split('#').next().unwrap_or("");
+
+    for directive in line.split(',').map(str::trim).filter(|directive| !directive.is_empty()) {
+      directives.push(parse_directive(directive, memory_size).map_err(|message| StateError { directive: directive.to_string(), message })?);
+    }
+
+  }
+
+  return Ok(directives);
+
+}
+
+/**
+ * Work out what a single directive sets
+ */
+fn parse_directive(directive: &str, memory_size: usize) -> Result<StateDirective, String> {
+
+  let (target, value) = directive.split_once('=').ok_or("directives should look like TARGET=VALUE")?;
+  let target = target.trim().to_ascii_uppercase();
+  let value = parse_number(value.trim())?;
+
+  // make sure values fit in whatever they're going into
+  let byte = || -> Result<u8, String> {
+    return u8::try_from(value).map_err(|_| format!("{:#X} doesn't fit in a byte", value));
+  };
+  let addr = | addr: usize | -> Result<usize, String> {
+    if addr >= memory_size {
+      return Err(format!("{:#X} is outside of memory", addr));
+    }
+    return Ok(addr);
+  };
+
+  // memory looks like [ADDRESS]
+  if let Some(inside) = target.strip_prefix('[').and_then(|target| target.strip_suffix(']')) {
+    return Ok(StateDirective::Memory(addr(parse_number(inside.trim())?)?, byte()?));
+  }
+
+  return match target.as_str() {
+    "I" => Ok(StateDirective::I(addr(value)?)),
+    "PC" => Ok(StateDirective::Pc(addr(value)?)),
+    "DT" => Ok(StateDirective::DelayTimer(byte()?)),
+    "ST" => Ok(StateDirective::SoundTimer(byte()?)),
+    _ => match target.strip_prefix('V').and_then(|register| usize::from_str_radix(register, 16).ok()) {
+      Some(register) if target.len() == 2 => Ok(StateDirective::Register(register, byte()?)),
+      _ => Err(format!("{} isn't a register or [address]", target))
+    }
+  };
+
+}
+
+/**
+ * Read a number, in hex if it starts with 0x and decimal otherwise
+ */
+fn parse_number(number: &str) -> Result<usize, String> {
+
+  let parsed = match number.strip_prefix("0x").or_else(|| number.strip_prefix("0X")) {
+    Some(hex) => usize::from_str_radix(hex, 16),
+    None => number.parse::<usize>()
+  };
+
+  return parsed.map_err(|_| format!("\"{}\" isn't a number", number));
+
+}
+
+#[cfg(test)]
+mod tests {
+
+  use super::*;
+
+  #[test]
+  fn directives_are_read_in_order() {
+
+    let directives = parse_initial_state("V0=0x10, vf=3, I=0x300 # the sprite\n[0x300]=0xAB,PC=0x240\nDT=60, ST=5", 4096).unwrap();
+    assert_eq!(directives, vec![
+      StateDirective::Register(0x0, 0x10),
+      StateDirective::Register(0xF, 3),
+      StateDirective::I(0x300),
+      StateDirective::Memory(0x300, 0xAB),
+      StateDirective::Pc(0x240),
+      StateDirective::DelayTimer(60),
+      StateDirective::SoundTimer(5)
+    ]);
+
+  }
+
+  #[test]
+  fn directives_that_dont_fit_are_errors() {
+
+    let message = | text: &str | parse_initial_state(text, 4096).unwrap_err().message;
+
+    assert_eq!(message("V0=256"), "0x100 doesn't fit in a byte");
+    assert_eq!(message("I=0x1000"), "0x1000 is outside of memory");
+    assert_eq!(message("V10=1"), "V10 isn't a register or [address]");
+    assert_eq!(message("V0"), "directives should look like TARGET=VALUE");
+    // and the directive that was wrong is in the error
+    assert_eq!(parse_initial_state("V0=1, [0x300]=zz", 4096).unwrap_err().directive, "[0x300]=zz");
+
+  }
+
+}
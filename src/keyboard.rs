@@ -3,18 +3,34 @@
  */
 use std::collections::{HashMap, HashSet};
 use winit::event::VirtualKeyCode;
+use crate::quirks::KeyWait;
 
 pub struct Keyboard {
 
   // map of keys from wgpu to numbers for the instructions to process
   pub key_map: HashMap<VirtualKeyCode, u8>,
   pub keys_down: HashSet<u8>,
-  // whether or not the execution is paused awaiting next key press
-  pub awaiting_keypress: bool,
-  // the most recent key press
-  pub latest_key: u8,
-  // finally, whether or not the cpu has to handle resumption
-  pub handle_resume: bool
+  // what ends the wait for a key the cpu is in, or None if it isn't waiting on one.
+  // the cpu's run state is what actually stops the program, this is just what the keyboard listens for until the wait's handled
+  pub key_wait: Option<KeyWait>,
+  // the keys that have gone down since the wait started, which are the only ones that can end it by coming up
+  pub wait_keys: HashSet<u8>,
+  // the key that ended the wait, or None if nothing has yet.
+  // when a few keys end it at once (before the cpu gets to handle the resume), the lowest key code wins, so it doesn't matter what order they came in
+  pub latest_key: Option<u8>,
+
+  // the least number of frames a key stays down for, even if it's let go of sooner (0 turns this off)
+  pub debounce_frames: u32,
+  // how many frames each key that's down has been down for
+  pub held_frames: HashMap<u8, u32>,
+  // keys that have been let go of, but haven't been down for long enough to actually come up yet
+  pub pending_releases: HashSet<u8>,
+
+  // called with the key code and whether it's now down whenever a chip-8 key actually goes down or comes up,
+  // for anything embedding the emulator that wants to react to input
+  pub on_change: Option<Box<dyn FnMut(u8, bool)>>,
+  // every key that's gone down or come up (in order) since the cpu last collected them, while a replay's being recorded
+  pub recorded_changes: Option<Vec<(u8, bool)>>
 
 }
 
@@ -46,12 +62,21 @@ impl Keyboard {
     // the list of which keys are currently down
     let keys_down: HashSet<u8> = HashSet::new();
 
-    // whether or not we're awaiting a key press before continuing
-    let awaiting_keypress = false;
-    let handle_resume = false;
-    let latest_key = 0;
+    // not listening for a key to end a wait until the cpu starts one
+    let key_wait: Option<KeyWait> = None;
+    let wait_keys: HashSet<u8> = HashSet::new();
+    let latest_key: Option<u8> = None;
+
+    // no debouncing to start with
+    let debounce_frames = 0;
+    let held_frames: HashMap<u8, u32> = HashMap::new();
+    let pending_releases: HashSet<u8> = HashSet::new();
 
-    return Keyboard { key_map, keys_down, awaiting_keypress, handle_resume, latest_key };
+    // and nobody listening for changes
+    let on_change: Option<Box<dyn FnMut(u8, bool)>> = None;
+    let recorded_changes: Option<Vec<(u8, bool)>> = None;
+
+    return Keyboard { key_map, keys_down, key_wait, wait_keys, latest_key, debounce_frames, held_frames, pending_releases, on_change, recorded_changes };
 
   }
 
@@ -71,40 +96,386 @@ impl Keyboard {
   pub fn on_key_down(&mut self, key: VirtualKeyCode) {
 
     // first, find the chip-8 key code
-    let key_code = self.key_map.get(&key);
+    // if it's not one of our wanted keys, there's nothing to do
+    if let Some(key_code) = self.key_map.get(&key) {
+      self.press(*key_code);
+    }
+
+  }
+
+  /**
+   * The key is up, remove it from the set
+   */
+  pub fn on_key_up(&mut self, key: VirtualKeyCode) {
+
+    if let Some(key_code) = self.key_map.get(&key) {
+      self.release(*key_code);
+    }
+
+  }
 
-    // if key_code is None, it's not one of our wanted keys so we should return now
-    if key_code == None { return };
+  /**
+   * A chip-8 key has gone down, however it was pressed
+   */
+  pub fn press(&mut self, key_code: u8) {
 
-    // now add the pressed key to the pressed key set
-    self.keys_down.insert(*key_code.unwrap());
+    // pressing a key that's waiting to come up again just keeps it down
+    self.pending_releases.remove(&key_code);
 
-    // and set that to the latest key press
-    self.latest_key = *key_code.unwrap();
+    // holding a key down makes winit send the press over and over, but only the first one is a real press
+    // (insert returns false if the key was already down)
+    if !self.keys_down.insert(key_code) { return };
+    self.held_frames.insert(key_code, 0);
+    self.changed(key_code, true);
 
-    // check whether we need to resume execution of the cpu
-    if self.awaiting_keypress == true {
-      // set awaiting keypress to false and tell the cpu to process the resume
-      self.awaiting_keypress = false;
-      self.handle_resume = true;
+    // check whether we need to resume execution of the cpu, or wait for the key to come up again
+    // (a wait that's just ended but hasn't been handled yet still counts, so a key pressed at the same time gets a look in)
+    if let Some(key_wait) = self.key_wait {
+      match key_wait {
+        KeyWait::Press => self.end_wait(key_code),
+        KeyWait::Release => { self.wait_keys.insert(key_code); },
+        KeyWait::FirstKey => if self.wait_keys.is_empty() {
+          self.wait_keys.insert(key_code);
+        }
+      }
     }
 
   }
 
   /**
-   * The key is up, remove it from the set
+   * A chip-8 key has come back up
    */
-  pub fn on_key_up(&mut self, key: VirtualKeyCode) {
+  pub fn release(&mut self, key_code: u8) {
 
-    // first, find the chip-8 key code
-    let key_code = self.key_map.get(&key);
+    // a key that hasn't been down for long enough stays down until it has
+    let held = self.held_frames.get(&key_code).copied().unwrap_or(0);
+    if self.keys_down.contains(&key_code) && held < self.debounce_frames {
+      self.pending_releases.insert(key_code);
+      return;
+    }
+
+    self.release_now(key_code);
+
+  }
 
-    // if key_code is None, it's not one of our wanted keys so we should return now
-    if key_code == None { return };
+  /**
+   * Let go of a chip-8 key straight away, even if it hasn't been down for debounce_frames yet
+   */
+  pub fn release_now(&mut self, key_code: u8) {
+
+    self.pending_releases.remove(&key_code);
 
     // now remove the pressed key frpm the pressed key set
-    self.keys_down.remove(key_code.unwrap());
+    self.held_frames.remove(&key_code);
+    if self.keys_down.remove(&key_code) {
+      self.changed(key_code, false);
+
+      // only keys that went down while waiting can end the wait by coming up
+      if self.key_wait.is_some() && self.wait_keys.contains(&key_code) {
+        self.end_wait(key_code);
+      }
+    }
+
+  }
+
+  /**
+   * Start waiting for a key, which ends whenever key_wait says it does
+   */
+  pub fn wait_for_key(&mut self, key_wait: KeyWait) {
+
+    self.key_wait = Some(key_wait);
+    self.wait_keys.clear();
+    // a key pressed before the wait doesn't count towards it
+    self.latest_key = None;
+
+  }
+
+  // the wait's over, so leave the key that ended it for the cpu to process the resume with,
+  // or the lowest of the keys that did if it's already over and the cpu hasn't got to it yet
+  fn end_wait(&mut self, key_code: u8) {
+
+    self.latest_key = Some(match self.latest_key {
+      Some(latest) => latest.min(key_code),
+      None => key_code
+    });
 
   }
 
-}
\ No newline at end of file
+  /**
+   * Hand over the key that ended the wait and stop listening for one, or None (still listening) if nothing has ended it yet
+   */
+  pub fn finish_wait(&mut self) -> Option<u8> {
+
+    let key_code = self.latest_key.take()?;
+    self.stop_waiting();
+
+    return Some(key_code);
+
+  }
+
+  /**
+   * Stop listening for a key, forgetting about anything that's ended the wait already
+   */
+  pub fn stop_waiting(&mut self) {
+
+    self.key_wait = None;
+    self.wait_keys.clear();
+    self.latest_key = None;
+
+  }
+
+  /**
+   * Run callback whenever a key actually goes down or comes up, with the key code and whether it's down now.
+   * Key repeats and debounced releases that haven't happened yet don't count
+   */
+  pub fn set_on_change(&mut self, callback: Box<dyn FnMut(u8, bool)>) {
+
+    self.on_change = Some(callback);
+
+  }
+
+  // let the callback (and a replay being recorded) know a key went down or came up
+  fn changed(&mut self, key_code: u8, down: bool) {
+
+    if let Some(callback) = &mut self.on_change {
+      callback(key_code, down);
+    }
+    if let Some(changes) = &mut self.recorded_changes {
+      changes.push((key_code, down));
+    }
+
+  }
+
+  /**
+   * Count another frame for every key that's down, letting go of any debounced keys that have now been down long enough
+   */
+  pub fn tick(&mut self) {
+
+    for held in self.held_frames.values_mut() {
+      *held += 1;
+    }
+
+    let ready: Vec<u8> = self.pending_releases.iter().copied()
+      .filter(| key_code | self.held_frames.get(key_code).copied().unwrap_or(0) >= self.debounce_frames)
+      .collect();
+    for key_code in ready {
+      self.release_now(key_code);
+    }
+
+  }
+
+  /**
+   * Let go of every key, for when the window loses focus and the releases might never arrive.
+   * A wait for a key carries on waiting, and a press that already ended one still gets handled
+   */
+  pub fn clear(&mut self) {
+
+    // every key that was down has now come up, as far as the callback is concerned
+    let released: Vec<u8> = self.keys_down.drain().collect();
+    for key_code in released {
+      self.changed(key_code, false);
+    }
+    self.held_frames.clear();
+    self.pending_releases.clear();
+    // keys that went down during a wait won't come up, so it needs a fresh press
+    self.wait_keys.clear();
+
+  }
+
+  /**
+   * Every key that's down as a bitmask, with key 0 in the lowest bit.
+   * keys_down is still what counts, this just packs it up for anything that wants the whole keypad at once
+   */
+  pub fn key_state_bitmask(&self) -> u16 {
+
+    return self.keys_down.iter().fold(0, | mask, key_code | mask | 1 << key_code);
+
+  }
+
+  /**
+   * Press and release keys so exactly the ones in the bitmask are down, without waiting for any debouncing
+   */
+  pub fn set_key_state_bitmask(&mut self, mask: u16) {
+
+    for key_code in 0..16 {
+      if mask & (1 << key_code) != 0 {
+        self.press(key_code);
+      } else {
+        self.release_now(key_code);
+      }
+    }
+
+  }
+
+  /**
+   * The keyboard key for each chip-8 key from 0 to F, or None if any of them aren't bound to anything
+   */
+  pub fn bindings(&self) -> Option<[VirtualKeyCode; 16]> {
+
+    let mut keys: [Option<VirtualKeyCode>; 16] = [None; 16];
+    for (key, key_code) in self.key_map.iter() {
+      keys[*key_code as usize & 0xF] = Some(*key);
+    }
+
+    return keys.iter().copied().collect::<Option<Vec<VirtualKeyCode>>>()?.try_into().ok();
+
+  }
+
+  /**
+   * Bind a keyboard key to each chip-8 key from 0 to F, replacing the whole key map
+   */
+  pub fn set_bindings(&mut self, keys: [VirtualKeyCode; 16]) {
+
+    self.key_map = keys.iter().enumerate().map(| (key_code, key) | (*key, key_code as u8)).collect();
+
+  }
+
+  /**
+   * Find the chip-8 key for a typed character, using the same layout as the keyboard (1234, QWER, ASDF, ZXCV)
+   */
+  pub fn key_for_char(c: char) -> Option<u8> {
+
+    return match c.to_ascii_lowercase() {
+      '1' => Some(0x1), '2' => Some(0x2), '3' => Some(0x3), '4' => Some(0xC),
+      'q' => Some(0x4), 'w' => Some(0x5), 'e' => Some(0x6), 'r' => Some(0xD),
+      'a' => Some(0x7), 's' => Some(0x8), 'd' => Some(0x9), 'f' => Some(0xE),
+      'z' => Some(0xA), 'x' => Some(0x0), 'c' => Some(0xB), 'v' => Some(0xF),
+      _ => None
+    };
+
+  }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+  use super::*;
+
+  #[test]
+  fn held_keys_only_end_a_wait_once() {
+
+    let mut keyboard = Keyboard::new();
+    keyboard.wait_for_key(KeyWait::Press);
+    keyboard.on_key_down(VirtualKeyCode::W);
+    assert_eq!(keyboard.finish_wait(), Some(0x5));
+
+    // the next wait starts with W (chip-8 key 5) still held, and winit sending the press again and again doesn't end it
+    keyboard.wait_for_key(KeyWait::Press);
+    for _ in 0..5 {
+      keyboard.on_key_down(VirtualKeyCode::W);
+    }
+
+    assert_eq!(keyboard.finish_wait(), None);
+    assert_eq!(keyboard.key_wait, Some(KeyWait::Press));
+    assert!(keyboard.is_key_pressed(0x5));
+
+  }
+
+  #[test]
+  fn clearing_lets_go_of_everything_but_keeps_waiting() {
+
+    let mut keyboard = Keyboard::new();
+    keyboard.debounce_frames = 5;
+    let released = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+    let heard = released.clone();
+    keyboard.set_on_change(Box::new(move | key_code, down | if !down { heard.borrow_mut().push(key_code) }));
+
+    // one key held from before the wait, and one pressed (and let go of, but still debouncing) during it
+    keyboard.press(0x1);
+    keyboard.wait_for_key(KeyWait::Release);
+    keyboard.press(0x2);
+    keyboard.release(0x2);
+    keyboard.clear();
+
+    assert!(keyboard.keys_down.is_empty());
+    assert!(keyboard.held_frames.is_empty());
+    assert!(keyboard.pending_releases.is_empty());
+    let mut released = released.borrow().clone();
+    released.sort();
+    assert_eq!(released, vec![0x1, 0x2]);
+
+    // the wait carries on, but the key pressed during it can't end it any more
+    assert_eq!(keyboard.key_wait, Some(KeyWait::Release));
+    assert_eq!(keyboard.latest_key, None);
+    assert!(keyboard.wait_keys.is_empty());
+
+  }
+
+  #[test]
+  fn the_bitmask_has_a_bit_for_each_key_down() {
+
+    let mut keyboard = Keyboard::new();
+    assert_eq!(keyboard.key_state_bitmask(), 0);
+
+    keyboard.press(0x1);
+    keyboard.press(0x5);
+    keyboard.press(0xF);
+    assert_eq!(keyboard.key_state_bitmask(), 0b1000_0000_0010_0010);
+
+    // and setting one presses and releases to match
+    keyboard.set_key_state_bitmask(0b0000_0000_0000_0011);
+    assert_eq!(keyboard.keys_down, HashSet::from([0x0, 0x1]));
+
+  }
+
+  #[test]
+  fn quick_taps_stay_down_for_the_debounce_frames() {
+
+    let mut keyboard = Keyboard::new();
+    keyboard.debounce_frames = 3;
+
+    // let go of straight away, but it still has to count as down for 3 frames
+    keyboard.press(0x7);
+    keyboard.release(0x7);
+    for _ in 0..3 {
+      assert!(keyboard.is_key_pressed(0x7));
+      keyboard.tick();
+    }
+    assert!(!keyboard.is_key_pressed(0x7));
+
+    // with debouncing off, it comes up straight away
+    keyboard.debounce_frames = 0;
+    keyboard.press(0x7);
+    keyboard.release(0x7);
+    assert!(!keyboard.is_key_pressed(0x7));
+
+  }
+
+  #[test]
+  fn the_callback_hears_each_change_once() {
+
+    let mut keyboard = Keyboard::new();
+    let changes = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+    let heard = changes.clone();
+    keyboard.set_on_change(Box::new(move | key_code, down | heard.borrow_mut().push((key_code, down))));
+
+    // winit repeats the press while W (chip-8 key 5) is held, which isn't a change
+    keyboard.on_key_down(VirtualKeyCode::W);
+    keyboard.on_key_down(VirtualKeyCode::W);
+    keyboard.on_key_down(VirtualKeyCode::W);
+    keyboard.on_key_up(VirtualKeyCode::W);
+    // letting go of a key that isn't down isn't one either
+    keyboard.on_key_up(VirtualKeyCode::W);
+    keyboard.press(0xF);
+
+    assert_eq!(*changes.borrow(), vec![(0x5, true), (0x5, false), (0xF, true)]);
+
+  }
+
+  #[test]
+  fn keys_that_end_a_wait_together_go_to_the_lowest() {
+
+    // whichever order they come in, the lower key wins
+    for keys in [[0xC, 0x3], [0x3, 0xC]] {
+      let mut keyboard = Keyboard::new();
+      keyboard.wait_for_key(KeyWait::Press);
+      keyboard.press(keys[0]);
+      keyboard.press(keys[1]);
+      assert_eq!(keyboard.latest_key, Some(0x3));
+      assert_eq!(keyboard.finish_wait(), Some(0x3));
+    }
+
+  }
+
+}
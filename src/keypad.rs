@@ -0,0 +1,85 @@
+/**
+ * An on-screen hex keypad drawn below the display, so the emulator can be played with a mouse or on a touchscreen.
+ * The keys are laid out like the original COSMAC VIP keypad, the same as the keyboard mapping
+ */
+use crate::{display::{WIDTH, HEIGHT}, overlay::{self, Overlay}};
+
+// the chip-8 key in each spot, row by row
+pub const LAYOUT: [[u8; 4]; 4] = [
+  [0x1, 0x2, 0x3, 0xC],
+  [0x4, 0x5, 0x6, 0xD],
+  [0x7, 0x8, 0x9, 0xE],
+  [0xA, 0x0, 0xB, 0xF]
+];
+
+// how many cells of the keypad image each key takes up
+const KEY_WIDTH: usize = WIDTH / 4;
+const KEY_HEIGHT: usize = HEIGHT / 4;
+
+/**
+ * Find the key at a point on the keypad, where x and y go from 0 to 1 across it.
+ * The thin gaps between keys count as part of the nearest key, so nothing falls through the cracks
+ */
+pub fn key_at(x: f64, y: f64) -> Option<u8> {
+
+  if !(0.0..1.0).contains(&x) || !(0.0..1.0).contains(&y) {
+    return None;
+  }
+
+  let col = (x * 4.0) as usize;
+  let row = (y * 4.0) as usize;
+  return Some(LAYOUT[row][col]);
+
+}
+
+/**
+ * Draw the keypad into an overlay-sized image, with the key that's held down lit up
+ */
+pub fn draw(image: &mut Overlay, pressed: Option<u8>) {
+
+  image.clear();
+
+  for (row, keys) in LAYOUT.iter().enumerate() {
+    for (col, key) in keys.iter().enumerate() {
+
+      let x = col * KEY_WIDTH;
+      let y = row * KEY_HEIGHT;
+
+      // leave a cell of space around each key
+      let face = if pressed == Some(*key) { overlay::TEXT_DIM } else { overlay::BACKDROP };
+      image.fill_rect(x + 1, y + 1, KEY_WIDTH - 2, KEY_HEIGHT - 2, face);
+      // with the key's digit in the middle
+      image.draw_text(x + KEY_WIDTH / 2 - 2, y + 1 + (KEY_HEIGHT - 2 - 5) / 2, &format!("{:X}", key), overlay::TEXT);
+
+    }
+  }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+  use super::*;
+
+  #[test]
+  fn points_land_on_the_key_laid_out_there() {
+
+    // the middle of every cell is the key in that spot of the layout
+    for (row, keys) in LAYOUT.iter().enumerate() {
+      for (col, key) in keys.iter().enumerate() {
+        let x = (col as f64 + 0.5) / 4.0;
+        let y = (row as f64 + 0.5) / 4.0;
+        assert_eq!(key_at(x, y), Some(*key));
+      }
+    }
+
+    // the corners are the keys in the corners
+    assert_eq!(key_at(0.0, 0.0), Some(0x1));
+    assert_eq!(key_at(0.999, 0.999), Some(0xF));
+    // and anything off the keypad isn't a key at all
+    assert_eq!(key_at(-0.1, 0.5), None);
+    assert_eq!(key_at(0.5, 1.0), None);
+
+  }
+
+}
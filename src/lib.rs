@@ -0,0 +1,33 @@
+/**
+ * The emulator itself, kept apart from the window so it can be driven by anything: main.rs, the web api,
+ * or tests that want a cpu without a gpu
+ */
+pub mod display;
+pub mod cpu;
+pub mod keyboard;
+pub mod audio;
+pub mod overlay;
+pub mod rewind;
+pub mod roms;
+pub mod menu;
+pub mod quirks;
+pub mod timing;
+pub mod options;
+pub mod recorder;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod terminal;
+pub mod cheats;
+pub mod detect;
+pub mod replay;
+pub mod asm;
+pub mod command;
+pub mod keypad;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod snapshot;
+pub mod watch;
+pub mod initial_state;
+pub mod custom_opcode;
+pub mod settings;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod validate;
+
@@ -1,44 +1,165 @@
-mod display;
-mod cpu;
-mod keyboard;
+use emul8::{cpu, display, cheats, command, keypad, menu, options, recorder, roms, timing, watch};
+#[cfg(not(target_arch = "wasm32"))]
+use emul8::{terminal, snapshot, validate};
+#[cfg(target_arch = "wasm32")]
+mod embed;
 
 use winit::{
-  event::{Event, WindowEvent, KeyboardInput, ElementState, VirtualKeyCode},
-  dpi::LogicalSize,
+  event::{Event, WindowEvent, KeyboardInput, ElementState, VirtualKeyCode, MouseButton},
+  dpi::{LogicalSize, PhysicalPosition, PhysicalSize},
   event_loop::{ControlFlow, EventLoop},
   window::WindowBuilder,
 };
-use std::{time::{Duration}};
+use std::{rc::Rc, cell::RefCell, sync::mpsc};
 use instant::Instant;
+use timing::{FrameTimer, CycleCosts};
+use options::Options;
+use recorder::Recorder;
+use command::Command;
+use menu::{RomMenu, MenuChoice};
 
-use rfd::AsyncFileDialog;
+use rfd::{AsyncFileDialog, MessageDialog, MessageLevel, MessageButtons};
+
+/**
+ * What came back from the file dialog
+ */
+enum PickedRom {
+  Loaded(Vec<u8>),
+  // the dialog was cancelled, or the file couldn't be used
+  Nothing
+}
 
 /**
  * wgpu and winit require asynchronous features to run, so using a seperate function
  * makes most sense
  */
-async fn run(event_loop: EventLoop<()>, window: winit::window::Window) {
-
-  // open a dialogue to find the rom
-  let rom = AsyncFileDialog::new().pick_file().await;
-  // and then read the file
-  let program_bytes = rom.unwrap().read().await;
+async fn run(event_loop: EventLoop<()>, window: winit::window::Window, options: Options) {
 
   // create an instance of the display for rendering 
-  let mut cpu = cpu::Cpu::new(&window).await;
+  // if that fails, say what went wrong in the title and carry on headless rather than crashing
+  let mut cpu = match cpu::Cpu::new(&window, options.backends.unwrap_or_else(wgpu::Backends::all)).await {
+    Ok(cpu) => cpu,
+    Err(error) => {
+      window.set_title(&format!("emul8 - {}", error));
+      show_error(&format!("Couldn't start the display: {}", error));
+      cpu::Cpu::headless()
+    }
+  };
   
-  // load the sprites into memory
+  // load the sprites into memory, using a custom font if there is one
   cpu.load_sprites_to_memory();
+  if let Some(path) = &options.font {
+    load_font(&mut cpu, path);
+  }
+
+  // and behave like whichever interpreter was asked for
+  // (if no profile was given, one gets picked for each rom as it's loaded)
+  match options.profile {
+    Some(profile) => cpu.set_profile(profile),
+    None => cpu.auto_profile = true
+  }
+  if let Some(key_wait) = options.key_wait {
+    cpu.force_key_wait(key_wait);
+  }
+  cpu.load_address = options.load_address;
+  cpu.muted = options.mute;
+  cpu.set_waveform(options.waveform);
+  cpu.render_each_draw = options.render_each_draw;
+  cpu.permissive = options.permissive;
+  cpu.oversize_policy = options.oversize_policy;
+  cpu.warn_on_sys = options.warn_on_sys;
+  cpu.log_collisions = options.log_collisions;
+  cpu.display.set_off_pixel_level(options.off_pixel_level);
+  cpu.display.smooth = options.smooth;
+  cpu.display.set_margin(options.margin);
+  cpu.keyboard.debounce_frames = options.debounce_frames;
+  if options.cycle_costs {
+    cpu.cycle_costs = Some(CycleCosts::new());
+  }
+
+  // the rom picker shows up first so the user can choose what to play
+  let mut menu = RomMenu::new(roms::load_roms());
+
+  // unless a rom was given on the command line, in which case that can be loaded straight away
+  if let Some(path) = &options.rom {
+    match std::fs::read(path) {
+      Ok(bytes) => match cpu.load_new_rom(bytes) {
+        Ok(_) => menu.visible = false,
+        Err(error) => log::error!("Couldn't load {}: {}", path.display(), error)
+      },
+      Err(error) => log::error!("Couldn't read {}: {}", path.display(), error)
+    }
+  }
+
+  // cheats from the command line go in once everything's loaded
+  if let Some(path) = &options.cheats {
+    load_cheats(&mut cpu, path);
+  }
 
-  // finally, pass the bytes to cpu to load into memory
-  cpu.load_program_to_memory(program_bytes);
+  // as does the initial state, which gets set up again whenever the rom starts over
+  if let Some(path) = &options.initial_state {
+    load_initial_state(&mut cpu, path);
+  }
+
+  // and a replay can be played back over the top of the rom (or whichever one gets picked, if none has been yet)
+  if let Some(path) = &options.replay {
+    if let Err(error) = cpu.play_replay(path) {
+      log::error!("Couldn't play {}: {}", path.display(), error);
+    }
+  }
+
+  // random numbers can be logged for a bug report, or played back from one
+  if let Some(path) = &options.rng_log {
+    cpu.set_rng_log(path);
+  }
+  if let Some(path) = &options.inject_rng {
+    if let Err(error) = cpu.inject_rng_log(path) {
+      log::error!("Couldn't read the random numbers from {}: {}", path.display(), error);
+    }
+  }
 
-  // run a cycle (for testing)
-  // this will need to move to a 60x per second loop soon
-  cpu.cycle();
+  // or shared as hex in the url
+  if let Some(hex) = rom_hex_from_url() {
+    match cpu.load_program_from_hex(&hex) {
+      Ok(_) => menu.visible = false,
+      Err(error) => log::error!("Couldn't load the ROM from the url: {}", error)
+    }
+  }
+
+  // roms picked from the file dialog end up here, since on the web the dialog can't block the event loop
+  let picked_rom: Rc<RefCell<Option<PickedRom>>> = Rc::new(RefCell::new(None));
 
   // keep track of timing so that a cpu cycle can be executed every 16.667 milliseconds
-  let mut prev_cycle = Instant::now();
+  let mut frame_timer = FrameTimer::new(Instant::now());
+  frame_timer.set_uncapped(options.uncapped);
+
+  // whether the register inspector is covering the screen, and whether the hud is in the corner
+  let mut inspector_visible = false;
+  let mut hud_visible = false;
+  // whether the memory map is covering the screen
+  let mut memory_map_visible = false;
+  // whether backspace is being held to rewind
+  let mut rewinding = false;
+  // whether the game was paused by the window losing focus, so it can carry on when focus comes back
+  let mut paused_by_focus = false;
+  // records gameplay to a gif while it's turned on
+  let mut recorder = Recorder::new();
+
+  // the on-screen keypad, along with where the mouse is and which key it's holding down
+  cpu.display.show_keypad = options.keypad;
+  keypad::draw(&mut cpu.display.keypad, None);
+  let mut cursor: Option<PhysicalPosition<f64>> = None;
+  let mut keypad_key: Option<u8> = None;
+
+  // commands for driving the emulator from outside, which on desktop can come from stdin
+  let (command_sender, commands) = mpsc::channel::<Command>();
+  #[cfg(not(target_arch = "wasm32"))]
+  if options.stdin_commands {
+    command::read_stdin(command_sender);
+  }
+  // nothing sends commands on the web yet
+  #[cfg(target_arch = "wasm32")]
+  drop(command_sender);
 
   // open up the window!
   event_loop.run(move | event, _, control_flow | {
@@ -46,16 +167,79 @@ async fn run(event_loop: EventLoop<()>, window: winit::window::Window) {
     // make sure window stays open until the close event
     *control_flow = ControlFlow::Poll;
 
-    // If it's been a 60th of a second, run a cpu cycle
-    if Instant::now() > prev_cycle + Duration::from_micros(16667) {
+    // carry out any commands that have come in
+    while let Ok(command) = commands.try_recv() {
+      // roms named by a command are only read now, when it's carried out
+      let command = match command {
+        Command::Load(path) => match std::fs::read(&path) {
+          Ok(bytes) => Command::LoadRom(bytes),
+          Err(error) => {
+            log::error!("Couldn't read {}: {}", path.display(), error);
+            continue;
+          }
+        },
+        command => command
+      };
+      match command {
+        // new roms go through the same path as picking one, so the menu comes back if it doesn't load
+        Command::LoadRom(bytes) => {
+          cpu.display.overlay.clear();
+          menu.visible = false;
+          load_or_show_menu(&mut cpu, &mut menu, bytes);
+        },
+        command => cpu.handle_command(command)
+      }
+    }
+
+    // run a cpu cycle for every 60th of a second that's passed
+    for _frame in 0..frame_timer.tick(Instant::now()) {
+
+      // if a rom just came back from the file dialog, pass the bytes to cpu to load into memory
+      match picked_rom.borrow_mut().take() {
+        Some(PickedRom::Loaded(program_bytes)) => load_or_show_menu(&mut cpu, &mut menu, program_bytes),
+        // if nothing was picked and nothing's running, go back to the picker so there's something to do
+        Some(PickedRom::Nothing) => menu.visible = cpu.program.is_empty(),
+        None => ()
+      }
+
+      // only bother copying the cpu state when someone's actually looking at it
+      if inspector_visible {
+        let snapshot = cpu.debug_snapshot();
+        cpu.display.overlay.draw_inspector(&snapshot);
+      }
+      // the map follows i and the program counter around, so it's redrawn every frame too
+      else if memory_map_visible {
+        cpu.display.overlay.draw_memory_map(&cpu.memory_map());
+      }
+      // the hud gets redrawn from scratch every frame, since what it says changes (the menu uses the overlay itself though)
+      else if hud_visible && !menu.visible {
+        let snapshot = cpu.debug_snapshot();
+        cpu.display.overlay.clear();
+        // the achieved speed is only worth showing when it's not just the speed times 60
+        let uncapped_rate = frame_timer.uncapped.then(|| frame_timer.frames_per_second * snapshot.speed as u32);
+        cpu.display.overlay.draw_hud(&snapshot, uncapped_rate);
+      }
 
-      // now that it's time for a new cycle, reset the previous cycle time
-      // this happens BEFORE the cycle so that the execution time of cycle doesn't affect
-      // how long it takes for a new frame
-      prev_cycle = Instant::now();
+      // nothing runs until a rom's been picked
+      if menu.visible {
+        menu.draw(&mut cpu.display.overlay);
+        cpu.display.update();
+        cpu.display.render();
+      }
+
+      // while rewinding, step back a frame instead of running forwards
+      else if rewinding {
+        cpu.rewind(1);
+        cpu.display.update();
+        cpu.display.render();
+      }
 
       // actually run the cycle!
-      cpu.cycle();
+      else {
+        cpu.cycle();
+        // and keep the frame if it's being recorded
+        recorder.capture(&cpu.display.pixels);
+      }
 
     }
 
@@ -70,12 +254,111 @@ async fn run(event_loop: EventLoop<()>, window: winit::window::Window) {
 
       },
 
+      // with smooth rendering, draw again every time around in between the frames
+      // (vsync holds this to the monitor's refresh rate)
+      Event::MainEventsCleared => if cpu.display.smooth && !menu.visible {
+        cpu.display.render_blended();
+      },
+
       // close the window
       Event::WindowEvent { 
         event: WindowEvent::CloseRequested,
         ..
       } => *control_flow = ControlFlow::Exit,
 
+      // the surface has to match the window's new size, and the margin gets worked out again from it
+      Event::WindowEvent {
+        event: WindowEvent::Resized(size),
+        ..
+      } => cpu.display.resize(size.width, size.height),
+
+      // a rom dropped onto the window replaces whatever's running
+      Event::WindowEvent {
+        event: WindowEvent::DroppedFile(path),
+        ..
+      } => {
+        match std::fs::read(&path) {
+          Ok(bytes) => {
+            cpu.display.overlay.clear();
+            menu.visible = false;
+            load_or_show_menu(&mut cpu, &mut menu, bytes);
+          },
+          Err(error) => show_error(&format!("Couldn't read {}: {}", path.display(), error))
+        }
+      },
+
+      // clicking (or tapping) the keypad presses the key under the pointer until it's let go
+      Event::WindowEvent {
+        event: WindowEvent::MouseInput { state, button: MouseButton::Left, .. },
+        ..
+      } if cpu.display.show_keypad => {
+        match state {
+          ElementState::Pressed => {
+            if let Some(key_code) = keypad_key_under(cursor, window.inner_size()) {
+              cpu.keyboard.press(key_code);
+              keypad_key = Some(key_code);
+              keypad::draw(&mut cpu.display.keypad, keypad_key);
+            }
+          },
+          ElementState::Released => release_keypad_key(&mut cpu, &mut keypad_key)
+        }
+      },
+
+      // sliding off a key lets go of it, the same as a real button
+      Event::WindowEvent {
+        event: WindowEvent::CursorMoved { position, .. },
+        ..
+      } => {
+        cursor = Some(position);
+        if keypad_key.is_some() && keypad_key_under(cursor, window.inner_size()) != keypad_key {
+          release_keypad_key(&mut cpu, &mut keypad_key);
+        }
+      },
+      Event::WindowEvent {
+        event: WindowEvent::CursorLeft { .. },
+        ..
+      } => {
+        cursor = None;
+        release_keypad_key(&mut cpu, &mut keypad_key);
+      },
+
+      // keys held when the window loses focus never get released, so let go of them all
+      Event::WindowEvent {
+        event: WindowEvent::Focused(false),
+        ..
+      } => {
+        if cpu.playback.is_none() {
+          cpu.keyboard.clear();
+        }
+        rewinding = false;
+        cpu.set_turbo(false);
+
+        // and pause until focus comes back, if that's been asked for
+        if options.pause_on_focus_loss && !cpu.is_paused() {
+          cpu.pause();
+          paused_by_focus = true;
+        }
+      },
+
+      // only unpause if it was losing focus that paused the game
+      Event::WindowEvent {
+        event: WindowEvent::Focused(true),
+        ..
+      } => {
+        if paused_by_focus {
+          cpu.resume();
+          paused_by_focus = false;
+        }
+      },
+
+      // the emulator's about to close, so tidy up anything that'd otherwise get cut off
+      Event::LoopDestroyed => {
+        cpu.shutdown();
+        if recorder.recording {
+          finish_recording(&mut recorder);
+        }
+      },
+
       // key pressed or released!
       Event::WindowEvent {
         event: WindowEvent::KeyboardInput { 
@@ -90,19 +373,216 @@ async fn run(event_loop: EventLoop<()>, window: winit::window::Window) {
           // connect with the keyboard struct
           if state == ElementState::Pressed {
 
+            // while the rom picker is up, keys move around it instead of going to the game
+            if menu.visible {
+              match virtual_keycode.unwrap() {
+                VirtualKeyCode::Up | VirtualKeyCode::W => menu.up(),
+                VirtualKeyCode::Down | VirtualKeyCode::S => menu.down(),
+                VirtualKeyCode::Return => {
+                  cpu.display.overlay.clear();
+                  match menu.choose() {
+                    MenuChoice::Rom(rom) => load_or_show_menu(&mut cpu, &mut menu, rom.bytes),
+                    MenuChoice::OpenFile => open_rom_dialog(picked_rom.clone())
+                  }
+                },
+                _ => ()
+              }
+              return;
+            }
+
+            // O opens a different rom, swapping it in once it's picked
+            if virtual_keycode.unwrap() == VirtualKeyCode::O {
+              open_rom_dialog(picked_rom.clone());
+            }
+
             // if the key is escape, pause or unpause the game
             if virtual_keycode.unwrap() == VirtualKeyCode::Escape {
-              cpu.paused = !cpu.paused;
+              cpu.toggle_pause();
+            }
+
+            // M mutes and unmutes the beep
+            if virtual_keycode.unwrap() == VirtualKeyCode::M {
+              cpu.muted = !cpu.muted;
+            }
+
+            // T switches the beep between square, sine and triangle waves
+            if virtual_keycode.unwrap() == VirtualKeyCode::T {
+              cpu.set_waveform(cpu.waveform.next());
+              log::info!("Beep waveform: {:?}", cpu.waveform);
+            }
+
+            // F11 freezes just the cpu, leaving the timers running, and F12 does the opposite
+            if virtual_keycode.unwrap() == VirtualKeyCode::F11 {
+              cpu.toggle_cpu_freeze();
+            }
+            if virtual_keycode.unwrap() == VirtualKeyCode::F12 {
+              cpu.toggle_timer_freeze();
+            }
+
+            // while paused, N runs the next instruction and J steps over subroutine calls
+            if virtual_keycode.unwrap() == VirtualKeyCode::N {
+              cpu.handle_command(Command::Step);
+            }
+            if cpu.is_paused() && virtual_keycode.unwrap() == VirtualKeyCode::J {
+              if let Err(error) = cpu.step_over() {
+                log::error!("Stopped stepping at {:#05X}: {}", cpu.program_addr, error);
+              }
+            }
+
+            // holding backspace rewinds the game
+            if virtual_keycode.unwrap() == VirtualKeyCode::Back {
+              rewinding = true;
+            }
+
+            // holding tab fast-forwards the game
+            if virtual_keycode.unwrap() == VirtualKeyCode::Tab {
+              cpu.set_turbo(true);
+            }
+
+            // B sets or clears a breakpoint wherever the program counter currently is
+            if virtual_keycode.unwrap() == VirtualKeyCode::B {
+              let addr = cpu.program_addr;
+              if cpu.breakpoints.contains(&addr) {
+                cpu.remove_breakpoint(addr);
+              } else {
+                cpu.add_breakpoint(addr);
+              }
+            }
+
+            // I watches the memory the i register points at, pausing as soon as it changes, or stops watching it
+            if virtual_keycode.unwrap() == VirtualKeyCode::I {
+              let target = watch::WatchTarget::Memory(cpu.memory_addr);
+              if cpu.watches.iter().any(|watch| watch.target == target) {
+                cpu.remove_watch(target);
+                log::info!("Stopped watching {}", target);
+              } else {
+                cpu.add_watch(target);
+                log::info!("Watching {}", target);
+              }
+            }
+
+            // F2 turns the phosphor fade on and off
+            if virtual_keycode.unwrap() == VirtualKeyCode::F2 {
+              cpu.display.phosphor = !cpu.display.phosphor;
+            }
+
+            // F3 outlines the last sprite drawn, to help with debugging where sprites end up
+            if virtual_keycode.unwrap() == VirtualKeyCode::F3 {
+              cpu.display.highlight_sprite = !cpu.display.highlight_sprite;
+            }
+
+            // F4 switches between vsync and the lower latency present modes
+            if virtual_keycode.unwrap() == VirtualKeyCode::F4 {
+              cpu.display.cycle_present_mode();
+            }
+
+            // F5 turns the key wait indicator on and off
+            if virtual_keycode.unwrap() == VirtualKeyCode::F5 {
+              cpu.display.show_key_wait = !cpu.display.show_key_wait;
+            }
+
+            // F6 flashes the border whenever the game beeps
+            if virtual_keycode.unwrap() == VirtualKeyCode::F6 {
+              cpu.display.flash_on_beep = !cpu.display.flash_on_beep;
+            }
+
+            // F7 switches between the pixel styles
+            if virtual_keycode.unwrap() == VirtualKeyCode::F7 {
+              let style = cpu.display.pixel_style.next();
+              cpu.display.set_pixel_style(style);
+            }
+
+            // G switches sprites between flipping pixels and only ever turning them on, to see sprite shapes whole
+            if virtual_keycode.unwrap() == VirtualKeyCode::G {
+              cpu.draw_mode = cpu.draw_mode.toggled();
+              log::info!("Drawing sprites with {:?}", cpu.draw_mode);
+            }
+
+            // K inverts the colours, for a lit background with dark pixels (this sticks around until K is pressed again)
+            if virtual_keycode.unwrap() == VirtualKeyCode::K {
+              cpu.display.toggle_invert();
+            }
+
+            // F8 starts the game over, which also gets a finished program going again
+            if virtual_keycode.unwrap() == VirtualKeyCode::F8 {
+              cpu.reset();
+            }
+
+            // F9 starts recording a gif, or stops and saves it
+            if virtual_keycode.unwrap() == VirtualKeyCode::F9 {
+              if recorder.recording {
+                finish_recording(&mut recorder);
+              } else {
+                recorder.start(cpu.display.palette);
+                log::info!("Started recording");
+              }
+            }
+
+            // F10 starts recording a replay of the keys pressed, or stops and saves it
+            if virtual_keycode.unwrap() == VirtualKeyCode::F10 {
+              match cpu.stop_recording() {
+                Some(replay) => save_output(replay.to_bytes(), "emul8_replay", "e8r", "application/octet-stream"),
+                None => {
+                  cpu.start_recording();
+                  log::info!("Started recording a replay");
+                }
+              }
+            }
+
+            // H shows or hides the hud
+            if virtual_keycode.unwrap() == VirtualKeyCode::H {
+              hud_visible = !hud_visible;
+              if !hud_visible && !inspector_visible {
+                cpu.display.overlay.clear();
+              }
+            }
+
+            // U takes the 60Hz frame limiter off (or puts it back) to see how fast the emulator can go
+            if virtual_keycode.unwrap() == VirtualKeyCode::U {
+              frame_timer.set_uncapped(!frame_timer.uncapped);
+              log::info!("Frame limiter {}", if frame_timer.uncapped { "off" } else { "on" });
+            }
+
+            // L shows or hides the memory map
+            if virtual_keycode.unwrap() == VirtualKeyCode::L {
+              memory_map_visible = !memory_map_visible;
+              if !memory_map_visible {
+                cpu.display.overlay.clear();
+              }
+            }
+
+            // F1 shows or hides the register inspector
+            if virtual_keycode.unwrap() == VirtualKeyCode::F1 {
+              inspector_visible = !inspector_visible;
+              // get rid of the panel straight away when it's hidden
+              if !inspector_visible {
+                cpu.display.overlay.clear();
+              }
             }
 
             // key is pressed, run on_key_down
-            cpu.keyboard.on_key_down(virtual_keycode.unwrap());
+            // (unless a replay is playing, since that's in charge of the keys)
+            if cpu.playback.is_none() {
+              cpu.keyboard.on_key_down(virtual_keycode.unwrap());
+            }
             
           }
 
           else if state == ElementState::Released {
+            // stop rewinding once backspace is let go
+            if virtual_keycode.unwrap() == VirtualKeyCode::Back {
+              rewinding = false;
+            }
+
+            // and go back to normal speed once tab is let go
+            if virtual_keycode.unwrap() == VirtualKeyCode::Tab {
+              cpu.set_turbo(false);
+            }
+
             // key is released, run on_key_up
-            cpu.keyboard.on_key_up(virtual_keycode.unwrap());
+            if cpu.playback.is_none() {
+              cpu.keyboard.on_key_up(virtual_keycode.unwrap());
+            }
           }
         }
 
@@ -116,18 +596,333 @@ async fn run(event_loop: EventLoop<()>, window: winit::window::Window) {
   });
 
 }
+
+/**
+ * Find the keypad key under the pointer, which has to be in the bottom half of the window where the keypad is
+ */
+fn keypad_key_under(cursor: Option<PhysicalPosition<f64>>, size: PhysicalSize<u32>) -> Option<u8> {
+
+  let cursor = cursor?;
+  let half = size.height as f64 / 2.0;
+  return keypad::key_at(cursor.x / size.width as f64, (cursor.y - half) / half);
+
+}
+
+/**
+ * Let go of the key the keypad is holding down, if there is one
+ */
+fn release_keypad_key(cpu: &mut cpu::Cpu, keypad_key: &mut Option<u8>) {
+
+  if let Some(key_code) = keypad_key.take() {
+    cpu.keyboard.release(key_code);
+    keypad::draw(&mut cpu.display.keypad, None);
+  }
+
+}
+
+/**
+ * Read a font file, which is 80 bytes of small digits optionally followed by 160 bytes of large ones
+ */
+fn load_font(cpu: &mut cpu::Cpu, path: &std::path::Path) {
+
+  let bytes = match std::fs::read(path) {
+    Ok(bytes) => bytes,
+    Err(error) => {
+      log::error!("Couldn't read {}: {}", path.display(), error);
+      return;
+    }
+  };
+
+  match bytes.len() {
+    80 | 240 => {
+      cpu.load_font(bytes[..80].try_into().unwrap());
+      if bytes.len() == 240 {
+        cpu.load_big_font(bytes[80..].try_into().unwrap());
+      }
+    },
+    length => log::error!("{} is {} bytes long, but fonts need to be 80 bytes (or 240 with the large digits)", path.display(), length)
+  }
+
+}
+
+/**
+ * Read a cheat file and start applying its cheats
+ */
+fn load_cheats(cpu: &mut cpu::Cpu, path: &std::path::Path) {
+
+  let text = match std::fs::read_to_string(path) {
+    Ok(text) => text,
+    Err(error) => {
+      log::error!("Couldn't read {}: {}", path.display(), error);
+      return;
+    }
+  };
+
+  match cheats::parse_cheats(&text) {
+    Ok(cheats) => cpu.cheats.extend(cheats),
+    Err(error) => log::error!("Couldn't load the cheats in {}: {}", path.display(), error)
+  }
+
+}
+
+/**
+ * Read the initial state out of a file and set it up on the cpu
+ */
+fn load_initial_state(cpu: &mut cpu::Cpu, path: &std::path::Path) {
+
+  let text = match std::fs::read_to_string(path) {
+    Ok(text) => text,
+    Err(error) => {
+      log::error!("Couldn't read {}: {}", path.display(), error);
+      return;
+    }
+  };
+
+  if let Err(error) = cpu.apply_initial_state(&text) {
+    log::error!("Couldn't set up the initial state in {}: {}", path.display(), error);
+  }
+
+}
+
+/**
+ * Swap to a rom the user picked, going back to the picker if it can't be loaded and there's nothing else to play
+ */
+fn load_or_show_menu(cpu: &mut cpu::Cpu, menu: &mut RomMenu, bytes: Vec<u8>) {
+
+  let size = bytes.len();
+  match cpu.load_new_rom(bytes) {
+    // a rom that was too big still runs with the truncate policy, but probably not properly
+    Ok(loaded) if loaded < size => show_warning(&format!("The ROM is {} bytes, which is too big to fit in memory, so only the first {} were loaded", size, loaded)),
+    Ok(_) => (),
+    Err(error) => {
+      show_error(&format!("Couldn't load the ROM: {}", error));
+      menu.visible = cpu.program.is_empty();
+    }
+  }
+
+}
+
+/**
+ * Open a dialogue to find a rom, putting its bytes in picked once it's been read
+ */
+fn open_rom_dialog(picked: Rc<RefCell<Option<PickedRom>>>) {
+
+  let pick = async move {
+    *picked.borrow_mut() = Some(pick_rom().await);
+  };
+
+  // the web can't wait around for the user to pick, so let it happen in the background
+  #[cfg(target_arch = "wasm32")]
+  wasm_bindgen_futures::spawn_local(pick);
+  // on desktop the dialog is modal anyway, so just wait for it
+  #[cfg(not(target_arch = "wasm32"))]
+  pollster::block_on(pick);
+
+}
+
+/**
+ * Let the user pick a rom, making sure it's actually something that can be loaded
+ */
+async fn pick_rom() -> PickedRom {
+
+  let rom = match AsyncFileDialog::new().pick_file().await {
+    Some(rom) => rom,
+    None => {
+      log::info!("The file dialog was cancelled");
+      return PickedRom::Nothing;
+    }
+  };
+
+  // and then read the file
+  // whether it fits in memory gets checked when it's loaded
+  let bytes = rom.read().await;
+
+  if bytes.is_empty() {
+    show_error(&format!("{} is empty, or couldn't be read", rom.file_name()));
+    return PickedRom::Nothing;
+  }
+
+  return PickedRom::Loaded(bytes);
+
+}
+
+/**
+ * Tell the user something went wrong, in a message box as well as the log
+ */
+fn show_error(message: &str) {
+
+  log::error!("{}", message);
+  MessageDialog::new()
+    .set_level(MessageLevel::Error)
+    .set_title("emul8")
+    .set_description(message)
+    .set_buttons(MessageButtons::Ok)
+    .show();
+
+}
+
+/**
+ * Let the user know about something that went wrong but didn't stop anything from working
+ */
+fn show_warning(message: &str) {
+
+  log::warn!("{}", message);
+  MessageDialog::new()
+    .set_level(MessageLevel::Warning)
+    .set_title("emul8")
+    .set_description(message)
+    .set_buttons(MessageButtons::Ok)
+    .show();
+
+}
+
+/**
+ * Stop recording and save whatever was recorded
+ */
+fn finish_recording(recorder: &mut Recorder) {
+
+  match recorder.stop() {
+    Ok(bytes) => save_output(bytes, "emul8_recording", "gif", "image/gif"),
+    Err(error) => log::error!("Couldn't encode the recording: {}", error)
+  }
+
+}
+
+/**
+ * Save a file to the folder emul8 was run from, named like name_1.extension but without overwriting any earlier ones
+ */
+#[cfg(not(target_arch = "wasm32"))]
+fn save_output(bytes: Vec<u8>, name: &str, extension: &str, _mime_type: &str) {
+
+  // find the first name that isn't taken yet
+  let mut number = 1;
+  let path = loop {
+    let path = std::path::PathBuf::from(format!("{}_{}.{}", name, number, extension));
+    if !path.exists() {
+      break path;
+    }
+    number += 1;
+  };
+
+  match std::fs::write(&path, bytes) {
+    Ok(()) => log::info!("Saved {}", path.display()),
+    Err(error) => log::error!("Couldn't save {}: {}", path.display(), error)
+  }
+
+}
+
+/**
+ * On the web there's nowhere to save to, so download the file instead
+ */
+#[cfg(target_arch = "wasm32")]
+fn save_output(bytes: Vec<u8>, name: &str, extension: &str, mime_type: &str) {
+
+  use wasm_bindgen::JsCast;
+
+  // put the bytes in a blob and click a link to it, which is how browsers like downloads to be started
+  let download = || -> Option<()> {
+    let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(&bytes[..]));
+    let blob = web_sys::Blob::new_with_u8_array_sequence_and_options(&parts, web_sys::BlobPropertyBag::new().type_(mime_type)).ok()?;
+    let url = web_sys::Url::create_object_url_with_blob(&blob).ok()?;
+
+    let link = web_sys::window()?.document()?.create_element("a").ok()?.dyn_into::<web_sys::HtmlAnchorElement>().ok()?;
+    link.set_href(&url);
+    link.set_download(&format!("{}.{}", name, extension));
+    link.click();
+
+    web_sys::Url::revoke_object_url(&url).ok()?;
+    return Some(());
+  };
+
+  if download().is_none() {
+    log::error!("Couldn't download {}.{}", name, extension);
+  }
+
+}
+
+/**
+ * On the web, a ROM can be shared as hex in the url fragment, like index.html#00E0,A22A,600C
+ */
+#[cfg(target_arch = "wasm32")]
+fn rom_hex_from_url() -> Option<String> {
+
+  let hash = web_sys::window()?.location().hash().ok()?;
+  // get rid of the # at the start, and turn any encoded spaces back into real ones
+  let hex = hash.trim_start_matches('#').replace("%20", " ");
+
+  if hex.is_empty() {
+    return None;
+  }
+  return Some(hex);
+
+}
+
+// there's no url to read from on desktop
+#[cfg(not(target_arch = "wasm32"))]
+fn rom_hex_from_url() -> Option<String> {
+  return None;
+}
+
+/**
+ * The window size that makes every chip-8 pixel exactly scale by scale screen pixels
+ */
+fn window_size_for_scale(scale: u32) -> LogicalSize<u32> {
+
+  let scale = scale.clamp(options::MIN_SCALE, options::MAX_SCALE);
+  return LogicalSize::new(display::WIDTH as u32 * scale, display::HEIGHT as u32 * scale);
+
+}
+
 fn main() {
 
+  // start logging first, so any problems with the options get reported
+  #[cfg(not(target_arch = "wasm32"))]
+  env_logger::init();
+
+  // read any settings given on the command line
+  let options = Options::from_args();
+
+  // the terminal doesn't need a window at all
+  #[cfg(not(target_arch = "wasm32"))]
+  if options.terminal {
+    terminal::run(options);
+    return;
+  }
+
+  // and neither do snapshots, which exit with an error if the hash didn't match
+  #[cfg(not(target_arch = "wasm32"))]
+  if options.snapshot {
+    let matched = snapshot::run(options);
+    std::process::exit(if matched { 0 } else { 1 });
+  }
+
+  // validating a rom exits the same way, with an error if there were any problems
+  #[cfg(not(target_arch = "wasm32"))]
+  if options.validate {
+    let valid = validate::run(options);
+    std::process::exit(if valid { 0 } else { 1 });
+  }
+
+  // the window's size comes from the scale if there is one
+  let mut size = match options.scale {
+    Some(scale) => window_size_for_scale(scale),
+    None => LogicalSize::new(600, 300)
+  };
+  // and the keypad goes underneath, just as big again
+  if options.keypad {
+    size.height *= 2;
+  }
+
   // define the window's properties
+  // on the web, winit applies the size straight to the canvas
   let event_loop = EventLoop::new();
-  let window = WindowBuilder::new().with_title("emul8 😏").with_inner_size(LogicalSize::new(600, 300)).build(&event_loop).unwrap();
+  let window = WindowBuilder::new().with_title("emul8 😏").with_inner_size(size).build(&event_loop).unwrap();
   
   // WASM needs a canvas created and appended
   #[cfg(not(target_arch = "wasm32"))]
   {
-    env_logger::init();
     // run the asynchronous run function until completion
-    pollster::block_on(run(event_loop, window));
+    pollster::block_on(run(event_loop, window, options));
   }
   // here's where we run the web code
   #[cfg(target_arch = "wasm32")]
@@ -148,7 +943,38 @@ fn main() {
       .expect("Couldn't append canvas to the document body :/");
 
     // finally, run the program!
-    wasm_bindgen_futures::spawn_local(run(event_loop, window));
+    wasm_bindgen_futures::spawn_local(run(event_loop, window, options));
+  }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+  use super::*;
+
+  #[test]
+  fn window_sizes_are_whole_multiples_of_the_screen() {
+
+    assert_eq!(window_size_for_scale(1), LogicalSize::new(64, 32));
+    assert_eq!(window_size_for_scale(10), LogicalSize::new(640, 320));
+    // and scales outside the range are clamped into it
+    assert_eq!(window_size_for_scale(0), LogicalSize::new(64, 32));
+    assert_eq!(window_size_for_scale(100), LogicalSize::new(64 * 40, 32 * 40));
+
+  }
+
+  #[test]
+  fn only_the_bottom_half_of_the_window_is_the_keypad() {
+
+    let size = PhysicalSize::new(400, 400);
+    assert_eq!(keypad_key_under(None, size), None);
+    // the top half is the display
+    assert_eq!(keypad_key_under(Some(PhysicalPosition::new(10.0, 100.0)), size), None);
+    // and the bottom half is split into the keys
+    assert_eq!(keypad_key_under(Some(PhysicalPosition::new(10.0, 210.0)), size), Some(keypad::LAYOUT[0][0]));
+    assert_eq!(keypad_key_under(Some(PhysicalPosition::new(390.0, 390.0)), size), Some(keypad::LAYOUT[3][3]));
+
   }
 
 }
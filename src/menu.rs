@@ -0,0 +1,94 @@
+/**
+ * An in-window list for picking which ROM to play, drawn onto the overlay
+ */
+use crate::{roms::RomEntry, overlay::{self, Overlay}, display::WIDTH};
+
+// how many lines of text fit on screen at once
+const VISIBLE_LINES: usize = 5;
+// each line is a 5 pixel tall glyph plus a pixel of space
+const LINE_HEIGHT: usize = 6;
+// the text of the last entry, which opens the file dialog instead of loading a ROM
+const OPEN_FILE: &str = "OPEN FILE...";
+
+/**
+ * What the user chose from the menu
+ */
+pub enum MenuChoice {
+  Rom(RomEntry),
+  OpenFile
+}
+
+pub struct RomMenu {
+  pub entries: Vec<RomEntry>,
+  // which line is highlighted, where entries.len() is the open file option
+  pub selected: usize,
+  pub visible: bool
+}
+
+impl RomMenu {
+
+  pub fn new(entries: Vec<RomEntry>) -> Self {
+
+    return RomMenu { entries, selected: 0, visible: true };
+
+  }
+
+  /**
+   * Move the highlight up a line, wrapping around to the bottom
+   */
+  pub fn up(&mut self) {
+
+    let lines = self.entries.len() + 1;
+    self.selected = (self.selected + lines - 1) % lines;
+
+  }
+
+  /**
+   * Move the highlight down a line, wrapping around to the top
+   */
+  pub fn down(&mut self) {
+
+    self.selected = (self.selected + 1) % (self.entries.len() + 1);
+
+  }
+
+  /**
+   * Pick the highlighted line, hiding the menu
+   */
+  pub fn choose(&mut self) -> MenuChoice {
+
+    self.visible = false;
+
+    return match self.entries.get(self.selected) {
+      Some(rom) => MenuChoice::Rom(rom.clone()),
+      None => MenuChoice::OpenFile
+    };
+
+  }
+
+  /**
+   * Draw the lines around the highlighted one, scrolling so it's always on screen
+   */
+  pub fn draw(&self, overlay: &mut Overlay) {
+
+    overlay.fill_rect(0, 0, WIDTH, VISIBLE_LINES * LINE_HEIGHT + 2, overlay::BACKDROP);
+
+    let first = self.selected.saturating_sub(VISIBLE_LINES - 1);
+    let names = self.entries.iter().map(|rom| rom.name.as_str()).chain([OPEN_FILE]);
+
+    for (line, (i, name)) in names.enumerate().skip(first).take(VISIBLE_LINES).enumerate() {
+
+      let y = 1 + line * LINE_HEIGHT;
+
+      if i == self.selected {
+        let cursor = overlay.draw_text(0, y, ">", overlay::TEXT);
+        overlay.draw_text(cursor, y, name, overlay::TEXT);
+      } else {
+        overlay.draw_text(4, y, name, overlay::TEXT_DIM);
+      }
+
+    }
+
+  }
+
+}
@@ -0,0 +1,269 @@
+/**
+ * Settings passed in on the command line when launching, like `emul8 --eti-660`
+ */
+use std::path::PathBuf;
+use crate::{cpu::{self, OversizePolicy}, display, quirks::{QuirkProfile, KeyWait}, audio::Waveform};
+
+// an environment variable that picks the graphics backend, for when passing --backend isn't convenient
+pub const BACKEND_VAR: &str = "EMUL8_BACKEND";
+
+// the range of window scales allowed, so the window can't end up tiny or too big for any screen
+pub const MIN_SCALE: u32 = 1;
+pub const MAX_SCALE: u32 = 40;
+
+pub struct Options {
+  // where roms get loaded into memory and the program counter starts
+  pub load_address: usize,
+  // how many screen pixels wide each chip-8 pixel starts out as, if it was given
+  pub scale: Option<u32>,
+  // which interpreter's quirks to copy, or None to guess from each rom
+  pub profile: Option<QuirkProfile>,
+  // what ends a wait for a key, or None to go with the profile
+  pub key_wait: Option<KeyWait>,
+  // which graphics apis wgpu can pick from, or None for any of them
+  pub backends: Option<wgpu::Backends>,
+  // whether instructions take different amounts of time, like on the original hardware
+  pub cycle_costs: bool,
+  // run frames as fast as possible instead of 60 times a second, for benchmarking
+  pub uncapped: bool,
+  // a file of custom digit sprites
+  pub font: Option<PathBuf>,
+  // a file of cheats to apply to the rom
+  pub cheats: Option<PathBuf>,
+  // a file of registers and memory to set up before the rom starts
+  pub initial_state: Option<PathBuf>,
+  // the least number of frames a key press lasts for
+  pub debounce_frames: u32,
+  // space to leave around the game, in window pixels
+  pub margin: u32,
+  // how brightly unlit pixels show, for an LCD look
+  pub off_pixel_level: f32,
+  // render at the monitor's refresh rate, blending between frames
+  pub smooth: bool,
+  // skip unknown opcodes instead of stopping at them
+  pub permissive: bool,
+  // what to do with roms too big for memory
+  pub oversize_policy: OversizePolicy,
+  // warn about calls to machine code routines
+  pub warn_on_sys: bool,
+  // log every sprite drawn and whether it collided
+  pub log_collisions: bool,
+  // render after every sprite is drawn, not just at the end of each frame
+  pub render_each_draw: bool,
+  // pause while the window doesn't have focus
+  pub pause_on_focus_loss: bool,
+  // start with the sound off
+  pub mute: bool,
+  // the shape of the beep
+  pub waveform: Waveform,
+  // a replay to play back once the rom is loaded
+  pub replay: Option<PathBuf>,
+  // where to log the random numbers the rom uses, and a log to take them from instead of the rng
+  pub rng_log: Option<PathBuf>,
+  pub inject_rng: Option<PathBuf>,
+  // a rom to start playing straight away instead of showing the picker
+  pub rom: Option<PathBuf>,
+  // show a clickable keypad under the game, which the web always does since it might be on a touchscreen
+  pub keypad: bool,
+  // read commands like `pause` and `press 5` from stdin, one per line
+  pub stdin_commands: bool,
+  // draw to the terminal instead of opening a window
+  pub terminal: bool,
+  // run the rom headless and print a hash of its last frame, optionally checking it against an expected one
+  pub snapshot: bool,
+  pub expect_hash: Option<u64>,
+  // run the rom headless and report any problems it runs into
+  pub validate: bool
+}
+
+impl Options {
+
+  pub fn new() -> Self {
+
+    return Options { load_address: cpu::PROGRAM_START, scale: None, profile: None, key_wait: None, backends: None, cycle_costs: false, uncapped: false, font: None, cheats: None, initial_state: None, debounce_frames: 0, margin: 0, off_pixel_level: 0.0, smooth: false, permissive: false, oversize_policy: OversizePolicy::Reject, warn_on_sys: false, log_collisions: false, render_each_draw: false, pause_on_focus_loss: false, mute: false, waveform: Waveform::Square, replay: None, rng_log: None, inject_rng: None, rom: None, keypad: cfg!(target_arch = "wasm32"), stdin_commands: false, terminal: false, snapshot: false, expect_hash: None, validate: false };
+
+  }
+
+  /**
+   * Read the options from the arguments the program was started with. There aren't any arguments on the web,
+   * so everything stays at the defaults there
+   */
+  pub fn from_args() -> Self {
+
+    // skip the first argument, which is the program itself
+    let mut options = Options::parse(std::env::args().skip(1));
+
+    // the backend can also come from the environment, although --backend wins
+    if options.backends.is_none() {
+      if let Ok(name) = std::env::var(BACKEND_VAR) {
+        match display::backends_from_name(&name) {
+          Some(backends) => options.backends = Some(backends),
+          None => log::warn!("Ignoring {}={}, which isn't a backend emul8 knows", BACKEND_VAR, name)
+        }
+      }
+    }
+
+    return options;
+
+  }
+
+  /**
+   * Work through a list of arguments, warning about (and ignoring) any that don't make sense
+   */
+  pub fn parse(args: impl IntoIterator<Item = String>) -> Self {
+
+    let mut options = Options::new();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+
+      match arg.as_str() {
+        // ETI-660 programs load at 0x600 instead of 0x200
+        "--eti-660" => options.load_address = cpu::ETI_660_START,
+        // or any other address, written in hex
+        "--load-address" => {
+          match args.next().as_deref().map(parse_address) {
+            Some(Some(addr)) => options.load_address = addr,
+            _ => log::warn!("--load-address needs a hex address below 0x1000, like 0x600")
+          }
+        },
+        // start the window at an exact multiple of the chip-8 resolution
+        "--scale" => {
+          match args.next().and_then(| scale | scale.parse::<u32>().ok()) {
+            Some(scale) => options.scale = Some(scale.clamp(MIN_SCALE, MAX_SCALE)),
+            _ => log::warn!("--scale needs a whole number, like 10")
+          }
+        },
+        // match the quirks of a particular interpreter
+        "--profile" => {
+          match args.next().as_deref().and_then(QuirkProfile::from_name) {
+            Some(profile) => options.profile = Some(profile),
+            _ => log::warn!("--profile needs one of modern, vip, schip or xochip")
+          }
+        },
+        // end waits for a key the way a particular interpreter did
+        "--key-wait" => {
+          match args.next().as_deref().and_then(KeyWait::from_name) {
+            Some(key_wait) => options.key_wait = Some(key_wait),
+            _ => log::warn!("--key-wait needs one of press, release or first")
+          }
+        },
+        // force a particular graphics api, for getting around a broken driver
+        "--backend" => {
+          match args.next().as_deref().and_then(display::backends_from_name) {
+            Some(backends) => options.backends = Some(backends),
+            _ => log::warn!("--backend needs one of vulkan, dx12, dx11, metal, gl, webgpu or all")
+          }
+        },
+        "--margin" => {
+          match args.next().and_then(| pixels | pixels.parse::<u32>().ok()) {
+            Some(pixels) => options.margin = pixels,
+            _ => log::warn!("--margin needs a number of pixels, like 16")
+          }
+        },
+        // keep quick presses down long enough for the game to notice them
+        "--debounce" => {
+          match args.next().and_then(| frames | frames.parse::<u32>().ok()) {
+            Some(frames) => options.debounce_frames = frames,
+            _ => log::warn!("--debounce needs a number of frames, like 3")
+          }
+        },
+        "--terminal" => options.terminal = true,
+        "--snapshot" => options.snapshot = true,
+        "--validate" => options.validate = true,
+        "--expect-hash" => {
+          match args.next().and_then(| hash | u64::from_str_radix(&hash, 16).ok()) {
+            Some(hash) => options.expect_hash = Some(hash),
+            None => log::warn!("--expect-hash needs the hash printed by --snapshot")
+          }
+        },
+        "--stdin-commands" => options.stdin_commands = true,
+        "--keypad" => options.keypad = true,
+        "--cycle-costs" => options.cycle_costs = true,
+        "--uncapped" => options.uncapped = true,
+        "--off-pixel-level" => {
+          match args.next().and_then(| level | level.parse::<f32>().ok()) {
+            Some(level) => options.off_pixel_level = level,
+            _ => log::warn!("--off-pixel-level needs a number from 0 to 1, like 0.1")
+          }
+        },
+        "--smooth" => options.smooth = true,
+        "--mute" => options.mute = true,
+        "--waveform" => {
+          match args.next().as_deref().and_then(Waveform::from_name) {
+            Some(waveform) => options.waveform = waveform,
+            _ => log::warn!("--waveform needs one of square, sine or triangle")
+          }
+        },
+        "--permissive" => options.permissive = true,
+        "--oversize" => {
+          match args.next().as_deref().and_then(OversizePolicy::from_name) {
+            Some(policy) => options.oversize_policy = policy,
+            _ => log::warn!("--oversize needs one of reject or truncate")
+          }
+        },
+        "--warn-on-sys" => options.warn_on_sys = true,
+        "--log-collisions" => options.log_collisions = true,
+        "--render-each-draw" => options.render_each_draw = true,
+        "--pause-on-focus-loss" => options.pause_on_focus_loss = true,
+        "--replay" => {
+          match args.next() {
+            Some(path) => options.replay = Some(PathBuf::from(path)),
+            None => log::warn!("--replay needs the path to a replay file")
+          }
+        },
+        "--rng-log" => {
+          match args.next() {
+            Some(path) => options.rng_log = Some(PathBuf::from(path)),
+            None => log::warn!("--rng-log needs the path to write the random numbers to")
+          }
+        },
+        "--inject-rng" => {
+          match args.next() {
+            Some(path) => options.inject_rng = Some(PathBuf::from(path)),
+            None => log::warn!("--inject-rng needs the path to a log from --rng-log")
+          }
+        },
+        "--font" => {
+          match args.next() {
+            Some(path) => options.font = Some(PathBuf::from(path)),
+            None => log::warn!("--font needs the path to a font file")
+          }
+        },
+        "--cheats" => {
+          match args.next() {
+            Some(path) => options.cheats = Some(PathBuf::from(path)),
+            None => log::warn!("--cheats needs the path to a cheat file")
+          }
+        },
+        "--initial-state" => {
+          match args.next() {
+            Some(path) => options.initial_state = Some(PathBuf::from(path)),
+            None => log::warn!("--initial-state needs the path to a file of registers and memory to set")
+          }
+        },
+        // anything that isn't an option is the rom to play
+        _ if !arg.starts_with("--") => options.rom = Some(PathBuf::from(arg)),
+        _ => log::warn!("Ignoring unknown argument {}", arg)
+      }
+
+    }
+
+    return options;
+
+  }
+
+}
+
+/**
+ * Parse an address like 0x600 or 600, making sure it's actually inside chip-8 memory
+ */
+fn parse_address(text: &str) -> Option<usize> {
+
+  let addr = usize::from_str_radix(text.trim_start_matches("0x"), 16).ok()?;
+  if addr >= 0x1000 {
+    return None;
+  }
+  return Some(addr);
+
+}
@@ -0,0 +1,248 @@
+/**
+ * A layer drawn on top of the display for debugging tools, without touching the game's own pixels
+ */
+use crate::{display::{WIDTH, HEIGHT}, cpu::{CpuSnapshot, MemoryRegion}};
+
+// overlay cells are palette indices that come after the four plane colours, with 0 meaning see-through
+pub const TRANSPARENT: u8 = 0;
+pub const TEXT: u8 = 4;
+pub const TEXT_DIM: u8 = 5;
+pub const BACKDROP: u8 = 6;
+pub const HIGHLIGHT: u8 = 7;
+
+// a tiny 3x5 font, since the regular chip-8 sprites are too big to fit much on screen (and only have digits)
+// each row uses the lowest 3 bits, most significant bit on the left
+const GLYPH_WIDTH: usize = 3;
+const GLYPH_HEIGHT: usize = 5;
+
+fn glyph(c: char) -> [u8; GLYPH_HEIGHT] {
+
+  return match c.to_ascii_uppercase() {
+    '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+    '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+    '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+    '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+    '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+    '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+    '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+    '7' => [0b111, 0b001, 0b010, 0b100, 0b100],
+    '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+    '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+    'A' => [0b111, 0b101, 0b111, 0b101, 0b101],
+    'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+    'C' => [0b111, 0b100, 0b100, 0b100, 0b111],
+    'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+    'E' => [0b111, 0b100, 0b111, 0b100, 0b111],
+    'F' => [0b111, 0b100, 0b111, 0b100, 0b100],
+    'G' => [0b111, 0b100, 0b101, 0b101, 0b111],
+    'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+    'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+    'J' => [0b001, 0b001, 0b001, 0b101, 0b111],
+    'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+    'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+    'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+    'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+    'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+    'P' => [0b111, 0b101, 0b111, 0b100, 0b100],
+    'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+    'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+    'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+    'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+    'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+    'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+    'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+    'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+    'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+    'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+    '>' => [0b100, 0b010, 0b001, 0b010, 0b100],
+    '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+    '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+    ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+    // anything else (including spaces) is left blank
+    _ => [0; GLYPH_HEIGHT]
+  };
+
+}
+
+/**
+ * The line of text the hud shows
+ */
+pub fn hud_text(snapshot: &CpuSnapshot) -> String {
+
+  let mut text = format!("{} {}", snapshot.speed, snapshot.profile.short_name());
+  if snapshot.paused {
+    text += " P";
+  }
+  if snapshot.muted {
+    text += " M";
+  }
+
+  return text;
+
+}
+
+pub struct Overlay {
+  pub cells: [[u8; WIDTH]; HEIGHT]
+}
+
+impl Overlay {
+
+  pub fn new() -> Self {
+
+    return Overlay { cells: [[TRANSPARENT; WIDTH]; HEIGHT] };
+
+  }
+
+  /**
+   * Make the whole overlay see-through again
+   */
+  pub fn clear(&mut self) {
+
+    self.cells = [[TRANSPARENT; WIDTH]; HEIGHT];
+
+  }
+
+  /**
+   * Fill a rectangle with a single value, cutting off anything past the edges
+   */
+  pub fn fill_rect(&mut self, x: usize, y: usize, width: usize, height: usize, value: u8) {
+
+    for row in y..(y + height).min(HEIGHT) {
+      for col in x..(x + width).min(WIDTH) {
+        self.cells[row][col] = value;
+      }
+    }
+
+  }
+
+  /**
+   * Write text with its top left corner at x, y, returning the x position after the last character
+   */
+  pub fn draw_text(&mut self, x: usize, y: usize, text: &str, value: u8) -> usize {
+
+    let mut cursor = x;
+
+    for c in text.chars() {
+
+      for (row, bits) in glyph(c).iter().enumerate() {
+        for col in 0..GLYPH_WIDTH {
+          // only draw the lit parts of the glyph, and nothing that falls off the screen
+          if bits & (0b100 >> col) != 0 && cursor + col < WIDTH && y + row < HEIGHT {
+            self.cells[y + row][cursor + col] = value;
+          }
+        }
+      }
+
+      // leave a column of space between characters
+      cursor += GLYPH_WIDTH + 1;
+
+    }
+
+    return cursor;
+
+  }
+
+  /**
+   * Show how the emulator's running in the top left corner: the speed, the quirk profile, and P and M when paused or muted.
+   * While the frame limiter's off, a second line shows U and how many instructions actually ran in the last second
+   */
+  pub fn draw_hud(&mut self, snapshot: &CpuSnapshot, uncapped_rate: Option<u32>) {
+
+    let text = hud_text(snapshot);
+    // a backdrop that leaves a pixel of space around the text
+    self.fill_rect(0, 0, text.len() * (GLYPH_WIDTH + 1) + 1, GLYPH_HEIGHT + 2, BACKDROP);
+    self.draw_text(1, 1, &text, TEXT);
+
+    if let Some(rate) = uncapped_rate {
+      let rate = format!("U {}", rate);
+      self.fill_rect(0, GLYPH_HEIGHT + 2, rate.len() * (GLYPH_WIDTH + 1) + 1, GLYPH_HEIGHT + 1, BACKDROP);
+      self.draw_text(1, GLYPH_HEIGHT + 2, &rate, TEXT);
+    }
+
+  }
+
+  /**
+   * Cover the whole screen with a map of memory, a cell for every few bytes (2 of them for regular chip-8), coloured by what they're used for.
+   * The program's lit up like the game's pixels, the font's dimmed, i is in the text colour and the program counter is highlighted
+   */
+  pub fn draw_memory_map(&mut self, regions: &[MemoryRegion]) {
+
+    let bytes_per_cell = (regions.len() / (WIDTH * HEIGHT)).max(1);
+
+    for (cell, bytes) in regions.chunks(bytes_per_cell).take(WIDTH * HEIGHT).enumerate() {
+
+      // the most important thing in the cell is what it shows, so a lone byte at i doesn't get lost
+      let region = bytes.iter().copied().max().unwrap_or(MemoryRegion::Unused);
+      self.cells[cell / WIDTH][cell % WIDTH] = match region {
+        MemoryRegion::Unused => BACKDROP,
+        MemoryRegion::Program => 1,
+        MemoryRegion::Font => TEXT_DIM,
+        MemoryRegion::Index => TEXT,
+        MemoryRegion::ProgramCounter => HIGHLIGHT
+      };
+
+    }
+
+  }
+
+  /**
+   * Lay out the cpu's registers, timers and stack as a panel covering the whole screen
+   */
+  pub fn draw_inspector(&mut self, snapshot: &CpuSnapshot) {
+
+    self.fill_rect(0, 0, WIDTH, HEIGHT, BACKDROP);
+
+    // the first two rows are v[0] through v[F], 8 per row
+    // every other register is dimmed so the pairs of digits are easy to tell apart
+    for (i, register) in snapshot.v.iter().enumerate() {
+      let value = if i % 2 == 0 { TEXT } else { TEXT_DIM };
+      self.draw_text((i % 8) * 8, (i / 8) * 6, &format!("{:02X}", register), value);
+    }
+
+    // then i and the program counter
+    let cursor = self.draw_text(0, 12, "I", TEXT_DIM);
+    self.draw_text(cursor, 12, &format!("{:04X}", snapshot.i), TEXT);
+    let cursor = self.draw_text(32, 12, "P", TEXT_DIM);
+    self.draw_text(cursor, 12, &format!("{:04X}", snapshot.pc), TEXT);
+
+    // the timers
+    let cursor = self.draw_text(0, 18, "DT", TEXT_DIM);
+    self.draw_text(cursor, 18, &format!("{:02X}", snapshot.delay_timer), TEXT);
+    let cursor = self.draw_text(32, 18, "ST", TEXT_DIM);
+    self.draw_text(cursor, 18, &format!("{:02X}", snapshot.sound_timer), TEXT);
+
+    // and finally the top few entries of the stack, most recent first
+    let mut cursor = self.draw_text(0, 24, "S", TEXT_DIM);
+    for addr in snapshot.stack.iter().rev().take(3) {
+      cursor = self.draw_text(cursor, 24, &format!("{:03X} ", addr), TEXT);
+    }
+
+  }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+  use super::*;
+  use crate::{cpu::Cpu, quirks::QuirkProfile};
+
+  #[test]
+  fn the_hud_shows_the_speed_and_whether_its_paused() {
+
+    let mut cpu = Cpu::headless();
+    cpu.speed = 20;
+    assert_eq!(hud_text(&cpu.debug_snapshot()), "20 MOD");
+
+    cpu.pause();
+    assert_eq!(hud_text(&cpu.debug_snapshot()), "20 MOD P");
+
+    cpu.resume();
+    cpu.speed = 7;
+    cpu.muted = true;
+    cpu.set_profile(QuirkProfile::Schip);
+    assert_eq!(hud_text(&cpu.debug_snapshot()), "7 SCHIP M");
+
+  }
+
+}
@@ -0,0 +1,140 @@
+/**
+ * Different chip-8 interpreters disagree on how some instructions behave, and ROMs tend to rely on
+ * whichever one they were written for. These flags switch between those behaviours
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+  // sprites drawn past the right or bottom edge are cut off instead of wrapping around to the other side
+  pub clip_sprites: bool,
+  // Bnnn jumps to nnn + v[x] (where x is the top digit of nnn) like SCHIP, instead of the original nnn + v[0]
+  pub jump_vx: bool,
+  // 8xy6 and 8xyE shift v[y] into v[x] like the original, instead of shifting v[x] in place
+  pub shift_vy: bool,
+  // Fx55 and Fx65 leave i pointing just past the last register, like the original
+  pub load_store_increment: bool,
+  // 8xy1, 8xy2 and 8xy3 reset v[F] to 0, like the original
+  pub logic_resets_vf: bool,
+  // Dxyn waits for the next frame before anything else runs, which limits drawing to 60 times a second like the COSMAC VIP
+  pub display_wait: bool,
+  // Fx1E sets v[F] when i goes past 0xFFF (and wraps i back around), like the Amiga interpreter. Spacefight 2091! needs this
+  pub fx1e_overflow: bool,
+  // what it takes for Fx0A to stop waiting for a key
+  pub key_wait: KeyWait
+}
+
+/**
+ * The ways interpreters decide a wait for a key (Fx0A) is over
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyWait {
+  // as soon as any key goes down
+  Press,
+  // when a key that went down during the wait comes back up, like the COSMAC VIP. Whichever comes up first is the one that counts
+  Release,
+  // the first key to go down during the wait is the only one that counts, and the wait is over when it comes back up
+  FirstKey
+}
+
+impl KeyWait {
+
+  /**
+   * Find a key wait from the name it's given on the command line
+   */
+  pub fn from_name(name: &str) -> Option<Self> {
+
+    return match name.to_ascii_lowercase().as_str() {
+      "press" => Some(KeyWait::Press),
+      "release" => Some(KeyWait::Release),
+      "first" | "first-key" => Some(KeyWait::FirstKey),
+      _ => None
+    };
+
+  }
+
+}
+
+/**
+ * Sets of quirks that match well known interpreters
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuirkProfile {
+  // what most modern ROMs expect, and what emul8 has always done
+  Modern,
+  // the original interpreter on the COSMAC VIP
+  CosmacVip,
+  // SUPER-CHIP on the HP-48 calculators
+  Schip,
+  // Octo's XO-CHIP
+  XoChip
+}
+
+impl QuirkProfile {
+
+  /**
+   * Find a profile from the name it's given on the command line
+   */
+  pub fn from_name(name: &str) -> Option<Self> {
+
+    return match name.to_ascii_lowercase().as_str() {
+      "modern" => Some(QuirkProfile::Modern),
+      "vip" | "cosmac-vip" => Some(QuirkProfile::CosmacVip),
+      "schip" | "superchip" => Some(QuirkProfile::Schip),
+      "xochip" | "xo-chip" => Some(QuirkProfile::XoChip),
+      _ => None
+    };
+
+  }
+
+  /**
+   * The name it's given on the command line, which from_name turns back into the profile
+   */
+  pub fn name(&self) -> &'static str {
+
+    return match self {
+      QuirkProfile::Modern => "modern",
+      QuirkProfile::CosmacVip => "vip",
+      QuirkProfile::Schip => "schip",
+      QuirkProfile::XoChip => "xochip"
+    };
+
+  }
+
+  /**
+   * A name short enough to fit in the corner of the screen
+   */
+  pub fn short_name(&self) -> &'static str {
+
+    return match self {
+      QuirkProfile::Modern => "MOD",
+      QuirkProfile::CosmacVip => "VIP",
+      QuirkProfile::Schip => "SCHIP",
+      QuirkProfile::XoChip => "XO"
+    };
+
+  }
+
+}
+
+impl Quirks {
+
+  pub fn new() -> Self {
+
+    return Quirks::from_profile(QuirkProfile::Modern);
+
+  }
+
+  /**
+   * Every quirk set the way a profile needs it
+   */
+  pub fn from_profile(profile: QuirkProfile) -> Self {
+
+    return match profile {
+      QuirkProfile::Modern => Quirks { clip_sprites: true, jump_vx: false, shift_vy: false, load_store_increment: false, logic_resets_vf: false, display_wait: false, fx1e_overflow: false, key_wait: KeyWait::Release },
+      QuirkProfile::CosmacVip => Quirks { clip_sprites: true, jump_vx: false, shift_vy: true, load_store_increment: true, logic_resets_vf: true, display_wait: true, fx1e_overflow: false, key_wait: KeyWait::Release },
+      QuirkProfile::Schip => Quirks { clip_sprites: true, jump_vx: true, shift_vy: false, load_store_increment: false, logic_resets_vf: false, display_wait: false, fx1e_overflow: false, key_wait: KeyWait::Press },
+      QuirkProfile::XoChip => Quirks { clip_sprites: false, jump_vx: false, shift_vy: true, load_store_increment: true, logic_resets_vf: false, display_wait: false, fx1e_overflow: false, key_wait: KeyWait::Release }
+    };
+
+  }
+
+}
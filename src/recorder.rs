@@ -0,0 +1,189 @@
+/**
+ * Records gameplay as an animated GIF. Frames are kept as plain copies of the pixels while recording,
+ * and only turned into a GIF once recording stops, so the game doesn't slow down
+ */
+use std::borrow::Cow;
+
+// 30 seconds of frames, which is plenty for a clip and only takes up a few megabytes
+pub const MAX_FRAMES: usize = 1800;
+// each chip-8 pixel is this many pixels wide in the GIF, since 64x32 is pretty tiny to look at
+pub const GIF_SCALE: usize = 4;
+// the emulator runs at 60 frames per second
+const FRAME_RATE: usize = 60;
+
+pub struct Recorder {
+  pub recording: bool,
+  // every frame recorded so far, as the plane bitmask of each pixel
+  pub frames: Vec<Vec<Vec<u8>>>,
+  // the colours to save the GIF with, taken from the display when recording started
+  pub palette: [[f32; 4]; 4]
+}
+
+impl Recorder {
+
+  pub fn new() -> Self {
+
+    return Recorder { recording: false, frames: vec![], palette: [[0.0; 4]; 4] };
+
+  }
+
+  /**
+   * Start a new recording, throwing away anything from before
+   */
+  pub fn start(&mut self, palette: [[f32; 4]; 4]) {
+
+    self.frames.clear();
+    self.palette = palette;
+    self.recording = true;
+
+  }
+
+  /**
+   * Save a copy of the current frame, as long as we're recording and there's still room
+   */
+  pub fn capture(&mut self, pixels: &[Vec<u8>]) {
+
+    if !self.recording || self.frames.len() >= MAX_FRAMES {
+      return;
+    }
+
+    // a GIF is the same size all the way through, so frames after a change of resolution are left out
+    if let Some(first) = self.frames.first() {
+      if first.len() != pixels.len() {
+        return;
+      }
+    }
+
+    self.frames.push(pixels.to_vec());
+
+    if self.frames.len() == MAX_FRAMES {
+      log::warn!("Recording is full, no more frames will be added until it's stopped");
+    }
+
+  }
+
+  /**
+   * Stop recording and turn everything recorded into a GIF
+   */
+  pub fn stop(&mut self) -> Result<Vec<u8>, gif::EncodingError> {
+
+    self.recording = false;
+
+    let frames = std::mem::take(&mut self.frames);
+    return encode_gif(&frames, &self.palette);
+
+  }
+
+}
+
+/**
+ * Encode a list of frames as a looping GIF, with each pixel's plane bitmask used as its palette index
+ */
+pub fn encode_gif(frames: &[Vec<Vec<u8>>], palette: &[[f32; 4]; 4]) -> Result<Vec<u8>, gif::EncodingError> {
+
+  // every frame is the same size as the first
+  let (columns, rows) = match frames.first() {
+    Some(frame) => (frame.first().map_or(0, | row | row.len()), frame.len()),
+    None => (crate::display::WIDTH, crate::display::HEIGHT)
+  };
+  let width = columns * GIF_SCALE;
+  let height = rows * GIF_SCALE;
+
+  // the palette goes in as plain rgb bytes
+  let mut colours: Vec<u8> = vec![];
+  for colour in palette.iter() {
+    for channel in colour.iter().take(3) {
+      colours.push((channel.clamp(0.0, 1.0) * 255.0).round() as u8);
+    }
+  }
+
+  let mut bytes: Vec<u8> = vec![];
+
+  {
+    // the encoder finishes off the file when it's dropped at the end of this block
+    let mut encoder = gif::Encoder::new(&mut bytes, width as u16, height as u16, &colours)?;
+    encoder.set_repeat(gif::Repeat::Infinite)?;
+
+    let mut start = 0;
+    while start < frames.len() {
+
+      // lots of frames are the same as the one before, so those just make the previous frame last longer
+      let mut end = start + 1;
+      while end < frames.len() && frames[end] == frames[start] {
+        end += 1;
+      }
+
+      // scale the frame up
+      let mut buffer: Vec<u8> = vec![0; width * height];
+      for (y, row) in buffer.chunks_mut(width).enumerate() {
+        for (x, pixel) in row.iter_mut().enumerate() {
+          *pixel = frames[start][y / GIF_SCALE][x / GIF_SCALE] & 0x3;
+        }
+      }
+
+      let frame = gif::Frame {
+        width: width as u16,
+        height: height as u16,
+        // gif delays are in hundredths of a second, so work them out from when the frame starts and ends
+        // to keep the overall timing right even though a 60th of a second doesn't fit evenly
+        delay: centiseconds(end) - centiseconds(start),
+        buffer: Cow::Owned(buffer),
+        ..Default::default()
+      };
+      encoder.write_frame(&frame)?;
+
+      start = end;
+
+    }
+  }
+
+  return Ok(bytes);
+
+}
+
+// how many hundredths of a second into the recording a frame starts
+fn centiseconds(frame: usize) -> u16 {
+
+  return ((frame * 100 + FRAME_RATE / 2) / FRAME_RATE) as u16;
+
+}
+
+#[cfg(test)]
+mod tests {
+
+  use super::*;
+  use crate::display::{WIDTH, HEIGHT};
+
+  #[test]
+  fn recordings_become_gifs_with_a_frame_each() {
+
+    let mut recorder = Recorder::new();
+    recorder.start([[0.0, 0.0, 0.0, 1.0], [1.0, 1.0, 1.0, 1.0], [1.0, 0.0, 0.0, 1.0], [0.0, 0.0, 1.0, 1.0]]);
+
+    // a pixel that moves along one each frame, so every frame is different
+    for frame in 0..10 {
+      let mut pixels = vec![vec![0; WIDTH]; HEIGHT];
+      pixels[0][frame] = 1;
+      recorder.capture(&pixels);
+    }
+
+    let bytes = recorder.stop().unwrap();
+    assert!(!recorder.recording);
+    assert!(bytes.starts_with(b"GIF89a"));
+
+    // read it back to check it's all there
+    let mut decoder = gif::DecodeOptions::new().read_info(&bytes[..]).unwrap();
+    assert_eq!(decoder.width() as usize, WIDTH * GIF_SCALE);
+    assert_eq!(decoder.height() as usize, HEIGHT * GIF_SCALE);
+
+    let mut delays: Vec<u16> = vec![];
+    while let Some(frame) = decoder.read_next_frame().unwrap() {
+      delays.push(frame.delay);
+    }
+    assert_eq!(delays.len(), 10);
+    // ten 60ths of a second comes to 17 hundredths, spread out over the frames
+    assert_eq!(delays.iter().sum::<u16>(), 17);
+
+  }
+
+}
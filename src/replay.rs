@@ -0,0 +1,148 @@
+/**
+ * Replays keep track of every key that went down or came up and which frame it happened on, along with the seed
+ * the random numbers came from and the ROM it was all played on, so a session can be played back exactly the same way
+ */
+use std::fmt;
+
+// the start of every replay file, so other files aren't mistaken for one
+const MAGIC: &[u8; 4] = b"E8R2";
+// what replays started with back when they saved the keys held on every frame instead
+const OLD_MAGIC: &[u8; 4] = b"E8RP";
+// the magic bytes, the rom's hash, the seed and the number of frames
+const HEADER_LENGTH: usize = 24;
+// each change is the frame it happened on, then the key with whether it went down in the top bit
+const EVENT_LENGTH: usize = 5;
+
+/**
+ * A key going down or coming up, at the start of a frame
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+  pub frame: u32,
+  pub key_code: u8,
+  pub down: bool
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Replay {
+  // the settings hash of the rom it was recorded on, since playing it on anything else won't make sense
+  pub rom_hash: u64,
+  pub seed: u64,
+  // how many frames long it is, which can go on past the last key
+  pub frames: u32,
+  // every change to the keys, in the order they happened
+  pub events: Vec<KeyEvent>
+}
+
+/**
+ * A replay file that couldn't be read, or played
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayError {
+  // the file couldn't be read at all
+  Unreadable(String),
+  // it doesn't start the way a replay file does
+  NotAReplay,
+  // it was made by an older version of emul8, which saved replays differently
+  OldFormat,
+  // it ends partway through
+  Truncated,
+  // it was recorded on a different rom to the one that's loaded
+  WrongRom
+}
+
+impl fmt::Display for ReplayError {
+
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ReplayError::Unreadable(error) => write!(f, "couldn't read the replay: {}", error),
+      ReplayError::NotAReplay => write!(f, "this isn't an emul8 replay"),
+      ReplayError::OldFormat => write!(f, "this replay was recorded by an older version of emul8, and can't be played back"),
+      ReplayError::Truncated => write!(f, "the replay is cut off partway through"),
+      ReplayError::WrongRom => write!(f, "the replay was recorded on a different ROM")
+    }
+  }
+
+}
+
+impl Replay {
+
+  pub fn new(rom_hash: u64, seed: u64) -> Self {
+
+    return Replay { rom_hash, seed, frames: 0, events: vec![] };
+
+  }
+
+  /**
+   * Write the replay out as the magic bytes, the rom's hash, the seed and the length, then 5 bytes per change to the keys
+   */
+  pub fn to_bytes(&self) -> Vec<u8> {
+
+    let mut bytes: Vec<u8> = Vec::with_capacity(HEADER_LENGTH + self.events.len() * EVENT_LENGTH);
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&self.rom_hash.to_le_bytes());
+    bytes.extend_from_slice(&self.seed.to_le_bytes());
+    bytes.extend_from_slice(&self.frames.to_le_bytes());
+    for event in self.events.iter() {
+      bytes.extend_from_slice(&event.frame.to_le_bytes());
+      bytes.push(event.key_code & 0xF | if event.down { 0x80 } else { 0 });
+    }
+
+    return bytes;
+
+  }
+
+  /**
+   * Read a replay back from the bytes to_bytes made
+   */
+  pub fn from_bytes(bytes: &[u8]) -> Result<Self, ReplayError> {
+
+    if bytes.len() >= 4 && &bytes[..4] == OLD_MAGIC {
+      return Err(ReplayError::OldFormat);
+    }
+    if bytes.len() < 4 || &bytes[..4] != MAGIC {
+      return Err(ReplayError::NotAReplay);
+    }
+    if bytes.len() < HEADER_LENGTH || !(bytes.len() - HEADER_LENGTH).is_multiple_of(EVENT_LENGTH) {
+      return Err(ReplayError::Truncated);
+    }
+
+    let u64_at = | start: usize | u64::from_le_bytes(bytes[start..start + 8].try_into().unwrap());
+    let u32_at = | start: usize | u32::from_le_bytes(bytes[start..start + 4].try_into().unwrap());
+
+    let events = bytes[HEADER_LENGTH..].chunks_exact(EVENT_LENGTH).map(| event | KeyEvent {
+      frame: u32::from_le_bytes([event[0], event[1], event[2], event[3]]),
+      key_code: event[4] & 0xF,
+      down: event[4] & 0x80 != 0
+    }).collect();
+
+    return Ok(Replay { rom_hash: u64_at(4), seed: u64_at(12), frames: u32_at(20), events });
+
+  }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+  use super::*;
+
+  #[test]
+  fn replays_round_trip_through_bytes() {
+
+    let mut replay = Replay::new(0x1234, 0xABCD);
+    replay.events = vec![KeyEvent { frame: 0, key_code: 0x5, down: true }, KeyEvent { frame: 3, key_code: 0x5, down: false }, KeyEvent { frame: 3, key_code: 0xF, down: true }];
+    replay.frames = 10;
+
+    let bytes = replay.to_bytes();
+    assert_eq!(bytes.len(), HEADER_LENGTH + 3 * EVENT_LENGTH);
+    assert_eq!(Replay::from_bytes(&bytes), Ok(replay));
+
+    // cutting it short or getting the start wrong are both caught
+    assert_eq!(Replay::from_bytes(&bytes[..bytes.len() - 1]), Err(ReplayError::Truncated));
+    assert_eq!(Replay::from_bytes(b"GIF89a"), Err(ReplayError::NotAReplay));
+    assert_eq!(Replay::from_bytes(b"E8RP\0\0\0\0\0\0\0\0"), Err(ReplayError::OldFormat));
+
+  }
+
+}
@@ -0,0 +1,89 @@
+/**
+ * Keeps a rolling history of the last few seconds of cpu state so the game can be rewound
+ */
+use std::{collections::VecDeque, rc::Rc};
+
+// 10 seconds worth of frames
+pub const DEFAULT_CAPACITY: usize = 600;
+
+/**
+ * Everything needed to put the cpu back the way it was at the end of a frame.
+ * Memory and pixels are shared between frames until they actually change, so most frames only cost a few bytes
+ */
+#[derive(Clone)]
+pub struct SavedState {
+  pub v: [u8; 16],
+  pub memory_addr: usize,
+  pub program_addr: usize,
+  pub delay_timer: u8,
+  pub sound_timer: u8,
+  pub stack: Vec<usize>,
+  pub memory: Rc<Vec<u8>>,
+  pub pixels: Rc<Vec<Vec<u8>>>
+}
+
+pub struct RewindBuffer {
+  // oldest frame at the front, most recent at the back
+  pub states: VecDeque<SavedState>,
+  // how many frames are kept before the oldest start getting thrown away
+  pub capacity: usize
+}
+
+impl RewindBuffer {
+
+  pub fn new(capacity: usize) -> Self {
+
+    return RewindBuffer { states: VecDeque::with_capacity(capacity), capacity };
+
+  }
+
+  /**
+   * Add the state at the end of the latest frame, forgetting the oldest one if the buffer is full
+   */
+  pub fn push(&mut self, state: SavedState) {
+
+    if self.capacity == 0 {
+      return;
+    }
+    if self.states.len() >= self.capacity {
+      self.states.pop_front();
+    }
+    self.states.push_back(state);
+
+  }
+
+  /**
+   * The most recently saved state, if there is one
+   */
+  pub fn latest(&self) -> Option<&SavedState> {
+
+    return self.states.back();
+
+  }
+
+  /**
+   * Throw away the newest frames and return the state from that many frames ago, or the oldest we have if
+   * there isn't that much history. The returned state stays in the buffer since it's now the current one
+   */
+  pub fn rewind(&mut self, frames: usize) -> Option<SavedState> {
+
+    // always keep at least one state around to go back to
+    let frames = frames.min(self.states.len().saturating_sub(1));
+    for _ in 0..frames {
+      self.states.pop_back();
+    }
+
+    return self.states.back().cloned();
+
+  }
+
+  /**
+   * Forget all history, for example when a new program gets loaded
+   */
+  pub fn clear(&mut self) {
+
+    self.states.clear();
+
+  }
+
+}
@@ -0,0 +1,82 @@
+/**
+ * The ROMs the picker menu can offer: a few small ones built into the emulator, plus (on desktop)
+ * anything in a roms folder next to where it's run from
+ */
+// the most a program can take up, since it's loaded in at 0x200
+pub const MAX_ROM_SIZE: usize = 4096 - 0x200;
+
+// built in so the picker works on the web too, where there's no file system to read from
+const EMBEDDED: [(&str, &[u8]); 2] = [
+  ("FONT", include_bytes!("../roms/font.ch8")),
+  ("KEYPAD", include_bytes!("../roms/keypad.ch8"))
+];
+
+/**
+ * A ROM the user can pick to play
+ */
+#[derive(Debug, Clone)]
+pub struct RomEntry {
+  pub name: String,
+  pub bytes: Vec<u8>
+}
+
+/**
+ * Just the ROMs baked into the emulator
+ */
+pub fn embedded_roms() -> Vec<RomEntry> {
+
+  return EMBEDDED.iter().map(|(name, bytes)| RomEntry { name: name.to_string(), bytes: bytes.to_vec() }).collect();
+
+}
+
+/**
+ * Every ROM available to pick from, with the embedded ones first
+ */
+pub fn load_roms() -> Vec<RomEntry> {
+
+  let mut roms = embedded_roms();
+
+  // on desktop, also look through the roms folder
+  #[cfg(not(target_arch = "wasm32"))]
+  if let Ok(dir) = std::fs::read_dir("roms") {
+
+    let mut found: Vec<RomEntry> = dir
+      .filter_map(|entry| entry.ok())
+      .filter_map(|entry| {
+        let path = entry.path();
+        let name = path.file_stem()?.to_string_lossy().to_uppercase();
+        let bytes = std::fs::read(&path).ok()?;
+        Some(RomEntry { name, bytes })
+      })
+      // skip the embedded ones (they're the same files when run from the repo) and anything that can't fit
+      .filter(|rom| !roms.iter().any(|existing| existing.name == rom.name) && rom.bytes.len() <= MAX_ROM_SIZE)
+      .collect();
+
+    found.sort_by(|a, b| a.name.cmp(&b.name));
+    roms.append(&mut found);
+
+  }
+
+  return roms;
+
+}
+
+#[cfg(test)]
+mod tests {
+
+  use super::*;
+
+  #[test]
+  fn embedded_roms_are_there_and_fit_in_memory() {
+
+    let roms = embedded_roms();
+
+    assert!(!roms.is_empty());
+    for rom in roms {
+      assert!(!rom.bytes.is_empty(), "{} is empty", rom.name);
+      assert!(rom.bytes.len() <= MAX_ROM_SIZE, "{} is too big", rom.name);
+    }
+
+  }
+
+}
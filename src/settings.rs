@@ -0,0 +1,257 @@
+/**
+ * Settings remembered for each game, so a ROM that needs a different speed, profile, palette or keys only has to be set up once.
+ * They're kept in a text file with a line per ROM, found by a hash of the ROM's bytes, like
+ * `3f2a9c01d4e5b687 speed=20 profile=vip palette=000000,FFFFFF,FF0000,00FF00 keys=X123QWEASDZCR4FV`
+ */
+use std::{fs, path::Path};
+use winit::event::VirtualKeyCode;
+use crate::quirks::QuirkProfile;
+
+// the keys that bindings can be saved for, along with the character they're written as
+const BINDABLE_KEYS: [(char, VirtualKeyCode); 36] = [
+  ('0', VirtualKeyCode::Key0), ('1', VirtualKeyCode::Key1), ('2', VirtualKeyCode::Key2), ('3', VirtualKeyCode::Key3),
+  ('4', VirtualKeyCode::Key4), ('5', VirtualKeyCode::Key5), ('6', VirtualKeyCode::Key6), ('7', VirtualKeyCode::Key7),
+  ('8', VirtualKeyCode::Key8), ('9', VirtualKeyCode::Key9), ('A', VirtualKeyCode::A), ('B', VirtualKeyCode::B),
+  ('C', VirtualKeyCode::C), ('D', VirtualKeyCode::D), ('E', VirtualKeyCode::E), ('F', VirtualKeyCode::F),
+  ('G', VirtualKeyCode::G), ('H', VirtualKeyCode::H), ('I', VirtualKeyCode::I), ('J', VirtualKeyCode::J),
+  ('K', VirtualKeyCode::K), ('L', VirtualKeyCode::L), ('M', VirtualKeyCode::M), ('N', VirtualKeyCode::N),
+  ('O', VirtualKeyCode::O), ('P', VirtualKeyCode::P), ('Q', VirtualKeyCode::Q), ('R', VirtualKeyCode::R),
+  ('S', VirtualKeyCode::S), ('T', VirtualKeyCode::T), ('U', VirtualKeyCode::U), ('V', VirtualKeyCode::V),
+  ('W', VirtualKeyCode::W), ('X', VirtualKeyCode::X), ('Y', VirtualKeyCode::Y), ('Z', VirtualKeyCode::Z)
+];
+
+/**
+ * Everything that's remembered for a game. Anything that's None is left however it already is
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct RomSettings {
+  pub speed: Option<u16>,
+  pub profile: Option<QuirkProfile>,
+  pub palette: Option<[[f32; 4]; 4]>,
+  // the keyboard key for each chip-8 key, from 0 to F
+  pub keys: Option<[VirtualKeyCode; 16]>
+}
+
+impl RomSettings {
+
+  pub fn new() -> Self {
+
+    return RomSettings { speed: None, profile: None, palette: None, keys: None };
+
+  }
+
+  /**
+   * Read settings from the part of a line after the hash. Anything that can't be understood is skipped
+   */
+  pub fn parse(text: &str) -> Self {
+
+    let mut settings = RomSettings::new();
+
+    for field in text.split_whitespace() {
+      let (name, value) = match field.split_once('=') {
+        Some(field) => field,
+        None => continue
+      };
+      match name {
+        "speed" => settings.speed = value.parse().ok(),
+        "profile" => settings.profile = QuirkProfile::from_name(value),
+        "palette" => settings.palette = parse_palette(value),
+        "keys" => settings.keys = parse_keys(value),
+        _ => log::warn!("Ignoring unknown setting {}", name)
+      }
+    }
+
+    return settings;
+
+  }
+
+  /**
+   * Just the settings that are different to the ones in original, with everything else left as None
+   */
+  pub fn changed_from(&self, original: &RomSettings) -> RomSettings {
+
+    return RomSettings {
+      speed: self.speed.filter(| _ | self.speed != original.speed),
+      profile: self.profile.filter(| _ | self.profile != original.profile),
+      palette: self.palette.filter(| _ | self.palette != original.palette),
+      keys: self.keys.filter(| _ | self.keys != original.keys)
+    };
+
+  }
+
+  /**
+   * Write the settings out the way parse reads them
+   */
+  pub fn to_line(&self) -> String {
+
+    let mut fields: Vec<String> = vec![];
+
+    if let Some(speed) = self.speed {
+      fields.push(format!("speed={}", speed));
+    }
+    if let Some(profile) = self.profile {
+      fields.push(format!("profile={}", profile.name()));
+    }
+    if let Some(palette) = self.palette {
+      let colours: Vec<String> = palette.iter()
+        .map(| colour | format!("{:02X}{:02X}{:02X}", to_byte(colour[0]), to_byte(colour[1]), to_byte(colour[2])))
+        .collect();
+      fields.push(format!("palette={}", colours.join(",")));
+    }
+    // keys without a character to write them as are left out of the save, and come back as whatever they were
+    if let Some(keys) = self.keys {
+      if let Some(chars) = keys.iter().map(| key | char_for_key(*key)).collect::<Option<String>>() {
+        fields.push(format!("keys={}", chars));
+      }
+    }
+
+    return fields.join(" ");
+
+  }
+
+}
+
+/**
+ * The hash a ROM's settings are saved under, which is 64 bit FNV-1a over its bytes (the same as the display's hash)
+ */
+pub fn rom_hash(rom_bytes: &[u8]) -> u64 {
+
+  let mut hash: u64 = 0xcbf29ce484222325;
+  for byte in rom_bytes {
+    hash ^= *byte as u64;
+    hash = hash.wrapping_mul(0x100000001b3);
+  }
+
+  return hash;
+
+}
+
+/**
+ * Find the settings saved for a ROM in the settings file, if there are any
+ */
+pub fn load_for(path: &Path, rom_bytes: &[u8]) -> Option<RomSettings> {
+
+  // a missing file just means nothing's been saved yet
+  let text = fs::read_to_string(path).ok()?;
+  let hash = format!("{:016x}", rom_hash(rom_bytes));
+
+  return text.lines()
+    .find_map(| line | line.strip_prefix(&hash))
+    .map(RomSettings::parse);
+
+}
+
+/**
+ * Save the settings for a ROM to the settings file, replacing whatever was saved for it before
+ */
+pub fn save_for(path: &Path, rom_bytes: &[u8], settings: &RomSettings) -> Result<(), std::io::Error> {
+
+  let hash = format!("{:016x}", rom_hash(rom_bytes));
+  let text = fs::read_to_string(path).unwrap_or_default();
+
+  // every other rom's line stays as it is, with this one's at the end
+  let mut lines: Vec<String> = text.lines()
+    .filter(| line | !line.starts_with(&hash))
+    .map(String::from)
+    .collect();
+  lines.push(format!("{} {}", hash, settings.to_line()));
+
+  return fs::write(path, lines.join("\n") + "\n");
+
+}
+
+// colours are 0 to 1, but saved as the usual 0 to 255
+fn to_byte(channel: f32) -> u8 {
+
+  return (channel.clamp(0.0, 1.0) * 255.0).round() as u8;
+
+}
+
+// four RRGGBB colours split up by commas
+fn parse_palette(text: &str) -> Option<[[f32; 4]; 4]> {
+
+  let mut palette = [[0.0, 0.0, 0.0, 1.0]; 4];
+  let colours: Vec<&str> = text.split(',').collect();
+  if colours.len() != 4 {
+    return None;
+  }
+
+  for (colour, hex) in palette.iter_mut().zip(colours) {
+    let hex = hex.trim_start_matches('#');
+    if hex.len() != 6 {
+      return None;
+    }
+    let rgb = u32::from_str_radix(hex, 16).ok()?;
+    colour[0] = ((rgb >> 16) & 0xFF) as f32 / 255.0;
+    colour[1] = ((rgb >> 8) & 0xFF) as f32 / 255.0;
+    colour[2] = (rgb & 0xFF) as f32 / 255.0;
+  }
+
+  return Some(palette);
+
+}
+
+// a character for each chip-8 key, from 0 to F
+fn parse_keys(text: &str) -> Option<[VirtualKeyCode; 16]> {
+
+  let keys: Vec<VirtualKeyCode> = text.chars().map(key_for_char).collect::<Option<_>>()?;
+  return keys.try_into().ok();
+
+}
+
+fn key_for_char(c: char) -> Option<VirtualKeyCode> {
+
+  return BINDABLE_KEYS.iter().find(| (key_char, _) | *key_char == c.to_ascii_uppercase()).map(| (_, key) | *key);
+
+}
+
+fn char_for_key(key: VirtualKeyCode) -> Option<char> {
+
+  return BINDABLE_KEYS.iter().find(| (_, bindable) | *bindable == key).map(| (key_char, _) | *key_char);
+
+}
+
+#[cfg(test)]
+mod tests {
+
+  use super::*;
+
+  #[test]
+  fn roms_are_hashed_with_fnv_1a() {
+
+    // the published FNV-1a values, so hashes saved before stay the same
+    assert_eq!(rom_hash(&[]), 0xcbf29ce484222325);
+    assert_eq!(rom_hash(b"a"), 0xaf63dc4c8601ec8c);
+
+  }
+
+  #[test]
+  fn settings_are_saved_and_loaded_for_each_rom() {
+
+    let path = std::env::temp_dir().join(format!("emul8-settings-{}.txt", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let first = [0x12, 0x00];
+    let second = [0x00, 0xE0];
+
+    // nothing's saved before there's a file
+    assert_eq!(load_for(&path, &first), None);
+
+    let fast = RomSettings { speed: Some(30), ..RomSettings::new() };
+    let vip = RomSettings { profile: Some(QuirkProfile::CosmacVip), ..RomSettings::new() };
+    save_for(&path, &first, &fast).unwrap();
+    save_for(&path, &second, &vip).unwrap();
+    assert_eq!(load_for(&path, &first), Some(fast));
+    assert_eq!(load_for(&path, &second), Some(vip.clone()));
+    assert_eq!(load_for(&path, &[0xFF]), None);
+
+    // saving a rom again replaces its line, and leaves the other one alone
+    let slow = RomSettings { speed: Some(5), ..RomSettings::new() };
+    save_for(&path, &first, &slow).unwrap();
+    assert_eq!(load_for(&path, &first), Some(slow));
+    assert_eq!(load_for(&path, &second), Some(vip));
+    assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 2);
+    fs::remove_file(&path).unwrap();
+
+  }
+
+}
@@ -1,15 +1,39 @@
+struct Uniforms {
+  // the four plane colours (off, plane 1, plane 2, both planes) followed by the overlay's colours
+  palette: array<vec4<f32>, 8>,
+  // how strongly the key wait indicator is showing in x, and whether the beep flash is on in y (the rest is padding)
+  indicator: vec4<f32>,
+  // the pixel style in x: 0 is sharp, 1 is rounded and 2 is crt. y is 1 when the background and first plane's colours are swapped
+  style: vec4<u32>,
+  // how brightly unlit pixels still show in x, like the cells of an LCD, and how far to blend from the last frame to the newest in y
+  // (which is always 1 unless smooth rendering is on)
+  levels: vec4<f32>,
+  // how many pixels across and down the screen is in x and y, which changes in SCHIP's high resolution mode
+  grid: vec4<f32>,
+};
+
+@group(0) @binding(0)
+var<uniform> uniforms: Uniforms;
+
 struct VertexOutput {
   @builtin(position) pos: vec4<f32>,
-  @location(0) @interpolate(flat) on: u32,
+  @location(0) @interpolate(flat) color_index: u32,
+  @location(1) @interpolate(flat) brightness: f32,
+  @location(2) @interpolate(flat) cell: vec2<u32>,
+  // where in the pixel this fragment is, from 0 to 1 on each side
+  @location(3) uv: vec2<f32>,
+  @location(4) @interpolate(flat) indicators: u32,
 };
 
 @vertex
-fn vs_main(@location(0) vpos: vec2<f32>, @location(1) ipos: vec2<u32>, @location(2) on: u32) -> VertexOutput {
-  // vpos is the vertex position, ipos is the instance position, on is whether or not this tile is illuminated
+fn vs_main(@location(0) vpos: vec2<f32>, @location(1) ipos: vec2<u32>, @location(2) color_index: u32, @location(3) brightness: f32, @location(4) previous: f32, @location(5) indicators: u32) -> VertexOutput {
+  // vpos is the vertex position, ipos is the instance position, color_index is which palette entry this tile
+  // is coloured with, brightness is how strongly it's glowing and previous is how strongly it glowed last frame.
+  // indicators is 1 for the game's pixels, which can show the key wait indicator and beep flash, and 0 for the keypad's
 
   var output: VertexOutput;
-  var width: f32 = 64.0;
-  var height: f32 = 32.0;
+  var width: f32 = uniforms.grid[0];
+  var height: f32 = uniforms.grid[1];
   // size of the tile
   var twidth = 2.0 / width;
   var theight = 2.0 / height;
@@ -23,15 +47,71 @@ fn vs_main(@location(0) vpos: vec2<f32>, @location(1) ipos: vec2<u32>, @location
   var y = ybase - vpos[1] * theight;
 
   output.pos = vec4<f32>(x, y, 0.0, 1.0);
-  output.on = on;
+  output.color_index = color_index;
+  output.brightness = mix(previous, brightness, uniforms.levels[1]);
+  output.cell = ipos;
+  output.uv = vpos;
+  output.indicators = indicators;
 
   return output;
 }
 
 @fragment
 fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
-  // the colour will be on for each rgb value: if on is zero it should be black, and if on is 1 it should be white perfect
-  var c: f32 = f32(input.on);
+  // fade from the background colour up to the pixel's colour
+  var brightness = input.brightness;
+  var color_index = input.color_index;
+
+  // unlit pixels show faintly in the first plane's colour, if that's turned on
+  if (brightness < uniforms.levels[0]) {
+    brightness = uniforms.levels[0];
+    if (color_index == 0u) {
+      color_index = 1u;
+    }
+  }
+
+  // rounded pixels fade out towards their corners
+  if (uniforms.style[0] == 1u) {
+    var dist = length(input.uv - vec2<f32>(0.5, 0.5));
+    brightness = brightness * (1.0 - smoothstep(0.42, 0.5, dist));
+  }
+
+  // crt pixels get darker towards their top and bottom edges, leaving a gap like a scanline,
+  // and glow a little brighter in the middle
+  if (uniforms.style[0] == 2u) {
+    var scanline = 0.65 + 0.35 * sin(input.uv[1] * 3.14159);
+    brightness = brightness * scanline * 1.1;
+  }
+
+  // inverting swaps the background and the first plane's colour, but leaves the other planes and the overlay alone
+  var background = uniforms.palette[0].rgb;
+  var foreground = uniforms.palette[color_index].rgb;
+  var lit = uniforms.palette[1].rgb;
+  if (uniforms.style[1] == 1u) {
+    background = uniforms.palette[1].rgb;
+    lit = uniforms.palette[0].rgb;
+    if (color_index <= 1u) {
+      foreground = uniforms.palette[0].rgb;
+    }
+  }
+
+  var colour = mix(background, foreground, clamp(brightness, 0.0, 1.0));
+
+  // the edges of the screen, at whatever resolution it's at
+  var right = u32(uniforms.grid[0]) - 1u;
+  var bottom = u32(uniforms.grid[1]) - 1u;
+
+  if (input.indicators == 1u) {
+    // the top right pixel glows in the overlay's text colour while the game is waiting for a key
+    if (input.cell[0] == right && input.cell[1] == 0u) {
+      colour = mix(colour, uniforms.palette[4].rgb, uniforms.indicator[0]);
+    }
+
+    // and the border lights up while the sound timer is running
+    if (input.cell[0] == 0u || input.cell[0] == right || input.cell[1] == 0u || input.cell[1] == bottom) {
+      colour = mix(colour, lit, uniforms.indicator[1]);
+    }
+  }
 
-  return vec4<f32>(c, c, c, 1.0);
+  return vec4<f32>(colour, 1.0);
 }
@@ -0,0 +1,95 @@
+/**
+ * Runs a rom headless with a fixed seed and prints a fingerprint of the frame it ends up on, so test ROMs can be
+ * checked against a known good result whenever the cpu changes
+ */
+use crate::{cpu::Cpu, options::Options, timing::CycleCosts};
+
+// every snapshot uses the same seed, so random numbers come out the same each run
+pub const SNAPSHOT_SEED: u64 = 0;
+// how many frames a rom gets to finish in, which is 10 seconds of running
+pub const SNAPSHOT_FRAMES: u32 = 600;
+
+/**
+ * Run the loaded program until it halts (or runs out of frames), then hash whatever's on screen
+ */
+pub fn take_snapshot(cpu: &mut Cpu) -> u64 {
+
+  for _frame in 0..SNAPSHOT_FRAMES {
+    if cpu.is_halted() || cpu.is_paused() {
+      break;
+    }
+    cpu.cycle();
+  }
+
+  return cpu.display.hash();
+
+}
+
+/**
+ * Snapshot the rom from the options and print its hash, returning false if it didn't match the expected one
+ */
+pub fn run(options: Options) -> bool {
+
+  let mut cpu = match load_headless(&options, "--snapshot") {
+    Some(cpu) => cpu,
+    None => return false
+  };
+  // which there's always a path for, once it's loaded
+  let path = options.rom.as_ref().unwrap();
+
+  let hash = take_snapshot(&mut cpu);
+  println!("{:016x}  {}", hash, path.display());
+
+  return match options.expect_hash {
+    Some(expected) if expected != hash => {
+      log::error!("{} should have ended on {:016x}", path.display(), expected);
+      false
+    },
+    _ => true
+  };
+
+}
+
+/**
+ * Load the rom from the options into a headless cpu, set up with the same seed every time.
+ * Anything that goes wrong is logged (mentioning the flag that needed the rom) and gives None
+ */
+pub fn load_headless(options: &Options, flag: &str) -> Option<Cpu> {
+
+  let path = match &options.rom {
+    Some(path) => path,
+    None => {
+      log::error!("{} needs a ROM to run", flag);
+      return None;
+    }
+  };
+  let bytes = match std::fs::read(path) {
+    Ok(bytes) => bytes,
+    Err(error) => {
+      log::error!("Couldn't read {}: {}", path.display(), error);
+      return None;
+    }
+  };
+
+  // set up the same way as the window would be
+  let mut cpu = Cpu::headless();
+  cpu.load_sprites_to_memory();
+  match options.profile {
+    Some(profile) => cpu.set_profile(profile),
+    None => cpu.auto_profile = true
+  }
+  cpu.load_address = options.load_address;
+  cpu.oversize_policy = options.oversize_policy;
+  if options.cycle_costs {
+    cpu.cycle_costs = Some(CycleCosts::new());
+  }
+
+  if let Err(error) = cpu.load_new_rom(bytes) {
+    log::error!("Couldn't load {}: {}", path.display(), error);
+    return None;
+  }
+  cpu.seed_rng(SNAPSHOT_SEED);
+
+  return Some(cpu);
+
+}
@@ -0,0 +1,167 @@
+/**
+ * Runs the emulator in a terminal instead of a window, drawing the pixels with block characters.
+ * Handy over SSH, or anywhere there's no gpu
+ */
+use std::{io::{self, BufRead, Write}, sync::mpsc, thread, time::Duration};
+use instant::Instant;
+use crate::{cpu::Cpu, display::Screen, keyboard::Keyboard, options::Options, roms, timing::{FrameTimer, CycleCosts}};
+
+// terminals can't tell us when a key is let go, so typed keys are held down for this many frames
+const HOLD_FRAMES: u32 = 6;
+
+/**
+ * Draws each frame to the terminal using ANSI escapes
+ */
+pub struct TerminalDisplay {
+  // where the frames get written, which is normally stdout
+  pub output: Box<dyn Write>
+}
+
+impl TerminalDisplay {
+
+  pub fn new() -> Self {
+
+    return TerminalDisplay { output: Box::new(io::stdout()) };
+
+  }
+
+}
+
+impl Screen for TerminalDisplay {
+
+  fn present(&mut self, pixels: &[Vec<u8>]) {
+
+    // move the cursor back to the top left and draw over the last frame
+    let frame = format!("\x1b[H{}", render_to_string(pixels));
+    // there's not much to do if the terminal's gone away, so errors are ignored
+    let _ = self.output.write_all(frame.as_bytes());
+    let _ = self.output.flush();
+
+  }
+
+}
+
+/**
+ * Turn the pixels into lines of text. Each pixel is two characters wide, since characters are about twice as tall as they are wide
+ */
+pub fn render_to_string(pixels: &[Vec<u8>]) -> String {
+
+  let width = pixels.first().map_or(0, | row | row.len());
+  let mut text = String::with_capacity((width * 2 + 1) * pixels.len());
+
+  for row in pixels.iter() {
+    for pixel in row.iter() {
+      text.push_str(if *pixel != 0 { "██" } else { "  " });
+    }
+    text.push('\n');
+  }
+
+  return text;
+
+}
+
+/**
+ * Run a rom in the terminal until the program is killed. Keys are typed (followed by enter) using the usual layout
+ */
+pub fn run(options: Options) {
+
+  let mut cpu = Cpu::headless();
+  cpu.load_sprites_to_memory();
+  // if no profile was given, one gets picked for each rom as it's loaded
+  match options.profile {
+    Some(profile) => cpu.set_profile(profile),
+    None => cpu.auto_profile = true
+  }
+  cpu.load_address = options.load_address;
+  if options.cycle_costs {
+    cpu.cycle_costs = Some(CycleCosts::new());
+  }
+
+  // there's no menu in the terminal, so play the rom that was asked for or the first built in one
+  let rom = match &options.rom {
+    Some(path) => match std::fs::read(path) {
+      Ok(bytes) => bytes,
+      Err(error) => {
+        log::error!("Couldn't read {}: {}", path.display(), error);
+        return;
+      }
+    },
+    None => roms::embedded_roms().remove(0).bytes
+  };
+  if let Err(error) = cpu.load_new_rom(rom) {
+    log::error!("Couldn't load the ROM: {}", error);
+    return;
+  }
+
+  // stdin blocks, so it's read on another thread and each key is sent over
+  let (sender, receiver) = mpsc::channel::<u8>();
+  thread::spawn(move || {
+    for line in io::stdin().lock().lines() {
+      let line = match line {
+        Ok(line) => line,
+        Err(_) => return
+      };
+      for key_code in line.chars().filter_map(Keyboard::key_for_char) {
+        if sender.send(key_code).is_err() {
+          return;
+        }
+      }
+    }
+  });
+
+  let mut screen = TerminalDisplay::new();
+  // clear the terminal before the first frame
+  let _ = screen.output.write_all(b"\x1b[2J");
+
+  // keys that have been typed, along with how many more frames they stay down for
+  let mut held: Vec<(u8, u32)> = vec![];
+  let mut frame_timer = FrameTimer::new(Instant::now());
+
+  loop {
+
+    for _frame in 0..frame_timer.tick(Instant::now()) {
+
+      // press anything that's been typed since last frame
+      while let Ok(key_code) = receiver.try_recv() {
+        cpu.keyboard.press(key_code);
+        held.push((key_code, HOLD_FRAMES));
+      }
+
+      cpu.cycle();
+      screen.present(&cpu.display.pixels);
+
+      // and let go of keys once they've been held long enough
+      for (key_code, frames) in held.iter_mut() {
+        *frames -= 1;
+        if *frames == 0 {
+          cpu.keyboard.release(*key_code);
+        }
+      }
+      held.retain(| (_, frames) | *frames > 0);
+
+    }
+
+    // don't spin the cpu while waiting for the next frame
+    thread::sleep(Duration::from_millis(1));
+
+  }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+  use super::*;
+
+  #[test]
+  fn pixels_turn_into_lines_of_blocks() {
+
+    let pixels = vec![
+      vec![1, 0, 0],
+      vec![0, 2, 3]
+    ];
+    assert_eq!(render_to_string(&pixels), "██    \n  ████\n");
+
+  }
+
+}
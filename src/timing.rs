@@ -0,0 +1,223 @@
+/**
+ * Works out how many 60Hz frames should run, using a fixed timestep so the game runs at the
+ * right speed even when the event loop wakes up early, late, or not at all for a while
+ */
+use std::time::Duration;
+use instant::Instant;
+
+// a 60th of a second
+pub const FRAME: Duration = Duration::from_micros(16667);
+// the most frames that'll be run to catch up at once, so a long hitch doesn't turn into a long fast-forward
+pub const MAX_CATCH_UP: u32 = 5;
+
+/**
+ * How far to blend from the last frame to the newest one when rendering in between frames, from 0 to 1.
+ * It's 1 once a whole frame's passed, so a paused game just shows what's on screen
+ */
+pub fn blend_factor(since_frame: Duration) -> f32 {
+
+  return (since_frame.as_secs_f32() / FRAME.as_secs_f32()).clamp(0.0, 1.0);
+
+}
+
+pub struct FrameTimer {
+  // time that's passed but hasn't been used up by a frame yet
+  pub accumulator: Duration,
+  // when the timer was last checked
+  pub last_tick: Instant,
+  // when uncapped, a frame runs every time the timer's checked instead of 60 times a second, for benchmarking.
+  // the cpu's timers count down once per frame, so they go just as fast and games won't run at their real speed
+  pub uncapped: bool,
+  // how many frames have run since the rate was last worked out, and when that was
+  pub rate_frames: u32,
+  pub rate_started: Instant,
+  // how many frames actually ran in the last full second
+  pub frames_per_second: u32
+}
+
+impl FrameTimer {
+
+  pub fn new(now: Instant) -> Self {
+
+    return FrameTimer { accumulator: Duration::ZERO, last_tick: now, uncapped: false, rate_frames: 0, rate_started: now, frames_per_second: 0 };
+
+  }
+
+  /**
+   * Check the clock, returning how many frames should run now
+   */
+  pub fn tick(&mut self, now: Instant) -> u32 {
+
+    let elapsed = now.saturating_duration_since(self.last_tick);
+    self.last_tick = now;
+
+    let frames = if self.uncapped { 1 } else { self.advance(elapsed) };
+    self.count_frames(now, frames);
+
+    return frames;
+
+  }
+
+  /**
+   * Turn the 60Hz cap off or on. Any time left over is thrown away, so going back to 60Hz doesn't start with a burst of catching up
+   */
+  pub fn set_uncapped(&mut self, uncapped: bool) {
+
+    self.uncapped = uncapped;
+    self.accumulator = Duration::ZERO;
+
+  }
+
+  /**
+   * Keep a tally of frames, and once a second has gone by, turn it into frames per second
+   */
+  fn count_frames(&mut self, now: Instant, frames: u32) {
+
+    self.rate_frames += frames;
+
+    let since = now.saturating_duration_since(self.rate_started);
+    if since >= Duration::from_secs(1) {
+      self.frames_per_second = (self.rate_frames as f64 / since.as_secs_f64()).round() as u32;
+      self.rate_frames = 0;
+      self.rate_started = now;
+    }
+
+  }
+
+  /**
+   * Add some elapsed time, returning how many whole frames now fit in the accumulated time
+   */
+  pub fn advance(&mut self, elapsed: Duration) -> u32 {
+
+    self.accumulator += elapsed;
+
+    let mut frames = 0;
+    while self.accumulator >= FRAME {
+      self.accumulator -= FRAME;
+      frames += 1;
+    }
+
+    // if we've fallen way behind (the window was unfocused, or the machine hitched), don't try
+    // to make it all up, just carry on from here
+    if frames > MAX_CATCH_UP {
+      frames = MAX_CATCH_UP;
+      self.accumulator = Duration::ZERO;
+    }
+
+    return frames;
+
+  }
+
+}
+
+/**
+ * How long each instruction takes compared to a simple one like 6xkk. With these, a frame runs until its
+ * budget is used up instead of running a fixed number of instructions, which is closer to how the original hardware paced things
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CycleCosts {
+  // the cost of each kind of instruction, by its first digit
+  pub by_group: [u32; 16],
+  // a few of the 0xF instructions are much slower than the rest
+  pub bcd: u32,
+  pub load_store: u32
+}
+
+impl CycleCosts {
+
+  /**
+   * Rough costs based on the COSMAC VIP, where clearing and drawing (and BCD) were by far the slowest
+   */
+  pub fn new() -> Self {
+
+    let mut by_group = [1; 16];
+    by_group[0x0] = 2;
+    by_group[0xD] = 8;
+
+    return CycleCosts { by_group, bcd: 6, load_store: 3 };
+
+  }
+
+  /**
+   * How much of the frame's budget an instruction uses, which is always at least 1 so a frame can't run forever
+   */
+  pub fn cost(&self, instruction: u16) -> u32 {
+
+    let cost = match instruction & 0xF0FF {
+      0xF033 => self.bcd,
+      0xF055 | 0xF065 => self.load_store,
+      _ => self.by_group[(instruction >> 12) as usize]
+    };
+
+    return cost.max(1);
+
+  }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+  use super::*;
+
+  #[test]
+  fn frames_run_for_each_60th_of_a_second() {
+
+    let start = Instant::now();
+    let mut timer = FrameTimer::new(start);
+
+    // not quite a frame, then enough to finish it off, then two and a half at once
+    assert_eq!(timer.tick(start + Duration::from_millis(10)), 0);
+    assert_eq!(timer.tick(start + Duration::from_millis(17)), 1);
+    assert_eq!(timer.tick(start + Duration::from_millis(59)), 2);
+    // with the half left over making up the next one
+    assert_eq!(timer.advance(Duration::from_millis(9)), 1);
+
+  }
+
+  #[test]
+  fn long_hitches_only_catch_up_a_few_frames() {
+
+    let mut timer = FrameTimer::new(Instant::now());
+
+    assert_eq!(timer.advance(Duration::from_secs(3)), MAX_CATCH_UP);
+    // and the rest of the time is forgotten
+    assert_eq!(timer.advance(Duration::ZERO), 0);
+
+  }
+
+  #[test]
+  fn uncapped_runs_a_frame_every_tick_and_counts_them() {
+
+    let start = Instant::now();
+    let mut timer = FrameTimer::new(start);
+    timer.advance(Duration::from_millis(10));
+    timer.set_uncapped(true);
+    // with the time that was building up thrown away
+    assert_eq!(timer.accumulator, Duration::ZERO);
+
+    // however little time goes by, every check is a frame
+    for tick in 1..=500 {
+      assert_eq!(timer.tick(start + Duration::from_millis(tick * 2)), 1);
+    }
+    // and a second's worth of them is 500 a second
+    assert_eq!(timer.frames_per_second, 500);
+
+    // back to 60Hz, it goes back to needing a whole frame's worth of time
+    timer.set_uncapped(false);
+    assert_eq!(timer.tick(start + Duration::from_millis(1005)), 0);
+
+  }
+
+  #[test]
+  fn blending_follows_the_time_since_the_last_frame() {
+
+    assert_eq!(blend_factor(Duration::ZERO), 0.0);
+    assert!((blend_factor(FRAME / 2) - 0.5).abs() < 0.001);
+    assert_eq!(blend_factor(FRAME), 1.0);
+    // and it stops at the newest frame, however long it's been
+    assert_eq!(blend_factor(Duration::from_secs(2)), 1.0);
+
+  }
+
+}
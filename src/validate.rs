@@ -0,0 +1,202 @@
+/**
+ * Checks a rom over without opening a window, for build scripts and CI: print a disassembly, run it for a while, and
+ * report anything that went wrong on the way, like unknown opcodes, reading past the end of memory or overflowing the stack
+ */
+use std::collections::HashSet;
+use crate::{cpu::{Cpu, CpuError}, options::Options, snapshot, asm};
+
+// how many instructions a rom gets to run, which is about 3 minutes at the default speed
+pub const VALIDATE_INSTRUCTIONS: u64 = 100_000;
+
+/**
+ * Validate the rom from the options, printing a report and returning whether it came through without any problems
+ */
+pub fn run(options: Options) -> bool {
+
+  let mut cpu = match snapshot::load_headless(&options, "--validate") {
+    Some(cpu) => cpu,
+    None => return false
+  };
+  let path = options.rom.as_ref().unwrap();
+
+  println!("{}", path.display());
+  print_disassembly(&cpu);
+
+  let (executed, problems) = find_problems(&mut cpu);
+
+  println!();
+  println!("Ran {} instructions", executed);
+  for problem in problems.iter() {
+    println!("{}", problem);
+  }
+  if problems.is_empty() {
+    println!("No problems found");
+  }
+
+  return problems.is_empty();
+
+}
+
+/**
+ * Every instruction in the program, with its address. Sprite data gets disassembled too, since there's no telling it apart
+ */
+pub fn print_disassembly(cpu: &Cpu) {
+
+  let start = cpu.load_address;
+  // a truncated program only goes as far as the end of memory
+  let end = (start + cpu.program.len()).min(cpu.memory_size());
+
+  for addr in (start..end).step_by(2) {
+    // an odd length leaves a single byte on the end
+    if addr + 1 == end {
+      println!("{:03X}  {:02X}", addr, cpu.read_memory(addr));
+      continue;
+    }
+    let instruction = (cpu.read_memory(addr) as u16) << 8 | cpu.read_memory(addr + 1) as u16;
+    println!("{:03X}  {:04X}  {}", addr, instruction, asm::disassemble(instruction));
+  }
+
+}
+
+/**
+ * Run the program for up to VALIDATE_INSTRUCTIONS instructions, returning how many ran and a line for each problem.
+ * The same problem at the same address is only reported once, so a loop doesn't fill the report up.
+ * Waits for a key are answered with key 0 so the run can carry on past them
+ */
+pub fn find_problems(cpu: &mut Cpu) -> (u64, Vec<String>) {
+
+  let mut problems: Vec<String> = vec![];
+  let mut reported: HashSet<(usize, &str)> = HashSet::new();
+  // instructions since the timers last ticked
+  let mut frame_spent: u32 = 0;
+  let mut executed: u64 = 0;
+
+  while executed < VALIDATE_INSTRUCTIONS && !cpu.is_halted() {
+
+    if cpu.is_awaiting_key() {
+      cpu.keyboard.press(0);
+      cpu.keyboard.release(0);
+    }
+
+    let pc = cpu.program_addr;
+    let instruction = cpu.current_opcode();
+    let mut report = | kind: &'static str, problem: String | {
+      if reported.insert((pc, kind)) {
+        problems.push(format!("{:03X}  {:04X}  {}", pc, instruction, problem));
+      }
+    };
+
+    if let Some(problem) = out_of_bounds(cpu, instruction) {
+      report("bounds", problem);
+    }
+
+    match cpu.step() {
+      Ok(()) => (),
+      // the program counter's already past it, so this can carry on
+      Err(CpuError::UnknownOpcode(opcode)) => report("opcode", format!("unknown opcode {:#06X}", opcode)),
+      // but there's no carrying on from a broken stack
+      Err(error) => {
+        report("stack", error.to_string());
+        break;
+      }
+    }
+    executed += 1;
+
+    // tick the timers as often as a real frame would, so delay loops finish
+    frame_spent += cpu.instruction_cost(instruction);
+    if frame_spent >= cpu.instructions_per_frame() as u32 {
+      frame_spent = 0;
+      cpu.update_timers();
+    }
+
+  }
+
+  return (executed, problems);
+
+}
+
+/**
+ * Whether an instruction is about to read (or write) past the end of memory, which wraps around to the start instead
+ */
+fn out_of_bounds(cpu: &Cpu, instruction: u16) -> Option<String> {
+
+  let size = cpu.memory_size();
+  let pc = cpu.program_addr;
+  if pc + cpu.instruction_length(instruction) > size {
+    return Some(format!("the program counter ran off the end of memory at {:#05X}", pc));
+  }
+
+  let x = ((instruction & 0x0F00) >> 8) as usize;
+  let i = cpu.memory_addr;
+  // how many bytes from i the instruction uses
+  let length = match instruction & 0xF0FF {
+    0xF033 => 3,
+    0xF055 | 0xF065 => x + 1,
+    // n rows for each selected plane, the same way the cpu reads them (so Dxy0 doesn't read anything)
+    _ if instruction & 0xF000 == 0xD000 => (instruction as usize & 0xF) * cpu.display.plane.count_ones() as usize,
+    _ => return None
+  };
+
+  if i + length > size {
+    return Some(format!("uses {} bytes from i = {:#05X}, which goes past the end of memory", length, i));
+  }
+  return None;
+
+}
+
+#[cfg(test)]
+mod tests {
+
+  use super::*;
+
+  // write a rom out to a file, then validate it the way --validate would
+  fn validate_rom(name: &str, hex: &str) -> (bool, Vec<String>) {
+
+    let path = std::env::temp_dir().join(format!("emul8-validate-{}-{}.ch8", name, std::process::id()));
+    std::fs::write(&path, crate::cpu::parse_hex(hex).unwrap()).unwrap();
+    let options = | | Options::parse(["--validate".to_string(), path.display().to_string()]);
+
+    let valid = run(options());
+    let mut cpu = snapshot::load_headless(&options(), "--validate").unwrap();
+    let (_, problems) = find_problems(&mut cpu);
+    std::fs::remove_file(&path).unwrap();
+
+    return (valid, problems);
+
+  }
+
+  #[test]
+  fn good_roms_pass_and_bad_ones_are_reported() {
+
+    let (valid, problems) = validate_rom("good", "6005 A000 D005 1206");
+    assert!(valid);
+    assert!(problems.is_empty());
+
+    // reading 3 bytes from the very end of memory, then an opcode that doesn't exist, both inside a loop
+    let (valid, problems) = validate_rom("bad", "AFFF F265 5121 1202");
+    assert!(!valid);
+    assert_eq!(problems, vec![
+      "202  F265  uses 3 bytes from i = 0xFFF, which goes past the end of memory".to_string(),
+      "204  5121  unknown opcode 0x5121".to_string()
+    ]);
+
+  }
+
+  #[test]
+  fn sprites_are_as_long_as_the_planes_they_draw_on() {
+
+    let mut cpu = Cpu::headless();
+    cpu.memory_addr = cpu.memory_size() - 4;
+
+    // 4 rows fit, but 4 rows on each of two planes don't
+    assert_eq!(out_of_bounds(&cpu, 0xD014), None);
+    cpu.display.plane = 3;
+    assert!(out_of_bounds(&cpu, 0xD014).is_some());
+
+    // and Dxy0 doesn't read any rows at all
+    cpu.memory_addr = cpu.memory_size();
+    assert_eq!(out_of_bounds(&cpu, 0xD010), None);
+
+  }
+
+}
@@ -0,0 +1,33 @@
+/**
+ * Watches keep an eye on a memory address or register and pause the cpu as soon as its value changes,
+ * for tracking down whatever's corrupting a variable
+ */
+use std::fmt;
+
+/**
+ * The byte a watch is keeping an eye on
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchTarget {
+  Memory(usize),
+  // one of v[0] through v[F]
+  Register(usize)
+}
+
+impl fmt::Display for WatchTarget {
+
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      WatchTarget::Memory(addr) => write!(f, "{:#05X}", addr),
+      WatchTarget::Register(register) => write!(f, "V{:X}", register)
+    }
+  }
+
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watch {
+  pub target: WatchTarget,
+  // what the value was the last time it was checked
+  pub value: u8
+}
@@ -0,0 +1,92 @@
+/**
+ * Runs ROMs headless until they halt, and checks the screen they end on against the hashes in snapshots.txt.
+ * The bundled ROMs are in roms/, and the ones that only exist to test the cpu are in tests/roms/
+ */
+use emul8::{asm, cpu::Cpu, snapshot};
+
+// load a rom the same way --snapshot does, then run it until it halts (or runs out of frames)
+fn run_to_halt(rom: &[u8]) -> Cpu {
+
+  let mut cpu = Cpu::headless();
+  cpu.auto_profile = true;
+  cpu.load_new_rom(rom.to_vec()).unwrap();
+  cpu.seed_rng(snapshot::SNAPSHOT_SEED);
+  snapshot::take_snapshot(&mut cpu);
+
+  return cpu;
+
+}
+
+// the hash snapshots.txt has for a rom, going by the path it's listed under
+fn expected_hash(path: &str) -> u64 {
+
+  let snapshots = std::fs::read_to_string(format!("{}/snapshots.txt", env!("CARGO_MANIFEST_DIR"))).unwrap();
+  let hash = snapshots.lines()
+    .filter(| line | !line.starts_with('#'))
+    .find_map(| line | match line.split_whitespace().collect::<Vec<&str>>()[..] {
+      [hash, listed] if listed == path => Some(hash),
+      _ => None
+    })
+    .unwrap_or_else(|| panic!("{} isn't in snapshots.txt", path));
+
+  return u64::from_str_radix(hash, 16).unwrap();
+
+}
+
+#[test]
+fn font() {
+
+  let cpu = run_to_halt(include_bytes!("../roms/font.ch8"));
+
+  assert!(cpu.is_halted());
+  assert_eq!(cpu.display.hash(), expected_hash("roms/font.ch8"));
+
+}
+
+#[test]
+fn keypad() {
+
+  // this one never halts, it sits waiting for keys to light up once it's drawn the keypad
+  let cpu = run_to_halt(include_bytes!("../roms/keypad.ch8"));
+
+  assert!(!cpu.is_halted());
+  assert_eq!(cpu.display.hash(), expected_hash("roms/keypad.ch8"));
+
+}
+
+// the test roms are written with emul8's assembler, and draw a tick for every check that passes and a cross for
+// every one that doesn't, so a failing hash can be looked into by running the rom in the window
+fn run_test_rom(name: &str, rom: &[u8]) -> Cpu {
+
+  // make sure the rom is still what its source assembles to, so the two can't drift apart
+  let source = std::fs::read_to_string(format!("{}/tests/roms/{}.asm", env!("CARGO_MANIFEST_DIR"), name)).unwrap();
+  assert_eq!(asm::assemble(&source).unwrap(), rom, "tests/roms/{}.ch8 needs reassembling", name);
+
+  let cpu = run_to_halt(rom);
+  assert!(cpu.is_halted());
+  assert_eq!(cpu.display.hash(), expected_hash(&format!("tests/roms/{}.ch8", name)));
+
+  return cpu;
+
+}
+
+#[test]
+fn opcodes() {
+
+  run_test_rom("opcodes", include_bytes!("roms/opcodes.ch8"));
+
+}
+
+#[test]
+fn flags() {
+
+  run_test_rom("flags", include_bytes!("roms/flags.ch8"));
+
+}
+
+#[test]
+fn draw() {
+
+  run_test_rom("draw", include_bytes!("roms/draw.ch8"));
+
+}
@@ -0,0 +1,131 @@
+; checks vf after drawing sprites over and next to each other, drawing a tick for each one that's right and a
+; cross for each one that isn't. every check rubs out what it drew, so only the marks are left at the end
+; the sprite is the 8 from the font, which is solid along the top
+  CLS
+  LD V6, 0
+  LD V7, 0
+
+; drawing onto a blank spot doesn't collide
+  LD VE, 0
+  LD V0, 8
+  LD F, V0
+  LD V1, 20
+  LD V2, 20
+  DRW V1, V2, 5
+  LD V3, VF
+  DRW V1, V2, 5
+  SE V3, 0
+  JP draw_0
+  LD VE, 1
+draw_0:
+  CALL mark
+
+; drawing over the same sprite does, and rubs it out
+  LD VE, 0
+  LD V0, 8
+  LD F, V0
+  LD V1, 20
+  LD V2, 20
+  DRW V1, V2, 5
+  DRW V1, V2, 5
+  LD V3, VF
+  SE V3, 1
+  JP draw_1
+  LD VE, 1
+draw_1:
+  CALL mark
+
+; sprites that only overlap partway collide
+  LD VE, 0
+  LD V0, 8
+  LD F, V0
+  LD V1, 20
+  LD V2, 20
+  DRW V1, V2, 5
+  LD V1, 22
+  DRW V1, V2, 5
+  LD V3, VF
+  DRW V1, V2, 5
+  LD V1, 20
+  DRW V1, V2, 5
+  SE V3, 1
+  JP draw_2
+  LD VE, 1
+draw_2:
+  CALL mark
+
+; sprites right next to each other don't
+  LD VE, 0
+  LD V0, 8
+  LD F, V0
+  LD V1, 40
+  LD V2, 20
+  DRW V1, V2, 5
+  LD V1, 44
+  DRW V1, V2, 5
+  LD V3, VF
+  DRW V1, V2, 5
+  LD V1, 40
+  DRW V1, V2, 5
+  SE V3, 0
+  JP draw_3
+  LD VE, 1
+draw_3:
+  CALL mark
+
+; a sprite starting past the right edge wraps around to the left
+  LD VE, 0
+  LD V0, 8
+  LD F, V0
+  LD V1, 70
+  LD V2, 20
+  DRW V1, V2, 5
+  LD V1, 6
+  DRW V1, V2, 5
+  LD V3, VF
+  SE V3, 1
+  JP draw_4
+  LD VE, 1
+draw_4:
+  CALL mark
+
+; and one starting past the bottom wraps around to the top
+  LD VE, 0
+  LD V0, 8
+  LD F, V0
+  LD V1, 10
+  LD V2, 52
+  DRW V1, V2, 5
+  LD V2, 20
+  DRW V1, V2, 5
+  LD V3, VF
+  SE V3, 1
+  JP draw_5
+  LD VE, 1
+draw_5:
+  CALL mark
+
+halt:
+  JP halt
+
+; draws a tick if ve is 1 and a cross otherwise, then moves along to where the next one goes
+mark:
+  LD I, cross
+  SNE VE, 1
+  LD I, tick
+  DRW V6, V7, 5
+  ADD V6, 8
+  SE V6, 64
+  RET
+  LD V6, 0
+  ADD V7, 6
+  RET
+
+tick:
+  DW 0x0102
+  DW 0x84C8
+  DW 0x7000
+cross:
+  DW 0x8850
+  DW 0x2050
+  DW 0x8800
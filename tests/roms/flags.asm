@@ -0,0 +1,248 @@
+; checks the flag each arithmetic instruction leaves in vf, along with the result, drawing a tick for each one
+; that's right and a cross for each one that isn't, eight to a row
+; the last few use vf as vx, where the flag has to win over the result
+  CLS
+  LD V6, 0
+  LD V7, 0
+
+; 8xy4 sets vf when it carries
+  LD VE, 0
+  LD VF, 0x55
+  LD V1, 0xF0
+  LD V2, 0x20
+  ADD V1, V2
+  SE V1, 0x10
+  JP flag_0
+  SE VF, 1
+  JP flag_0
+  LD VE, 1
+flag_0:
+  CALL mark
+
+; and clears it when it doesn't
+  LD VE, 0
+  LD VF, 0x55
+  LD V1, 0x10
+  LD V2, 0x20
+  ADD V1, V2
+  SE V1, 0x30
+  JP flag_1
+  SE VF, 0
+  JP flag_1
+  LD VE, 1
+flag_1:
+  CALL mark
+
+; 8xy5 sets vf when there's nothing to borrow
+  LD VE, 0
+  LD VF, 0x55
+  LD V1, 0x30
+  LD V2, 0x10
+  SUB V1, V2
+  SE V1, 0x20
+  JP flag_2
+  SE VF, 1
+  JP flag_2
+  LD VE, 1
+flag_2:
+  CALL mark
+
+; and clears it when there is
+  LD VE, 0
+  LD VF, 0x55
+  LD V1, 0x10
+  LD V2, 0x30
+  SUB V1, V2
+  SE V1, 0xE0
+  JP flag_3
+  SE VF, 0
+  JP flag_3
+  LD VE, 1
+flag_3:
+  CALL mark
+
+; 8xy5 counts an equal subtraction as not borrowing
+  LD VE, 0
+  LD VF, 0x55
+  LD V1, 0x30
+  LD V2, 0x30
+  SUB V1, V2
+  SE V1, 0
+  JP flag_4
+  SE VF, 1
+  JP flag_4
+  LD VE, 1
+flag_4:
+  CALL mark
+
+; 8xy7 sets vf when there's nothing to borrow
+  LD VE, 0
+  LD VF, 0x55
+  LD V1, 0x10
+  LD V2, 0x30
+  SUBN V1, V2
+  SE V1, 0x20
+  JP flag_5
+  SE VF, 1
+  JP flag_5
+  LD VE, 1
+flag_5:
+  CALL mark
+
+; and clears it when there is
+  LD VE, 0
+  LD VF, 0x55
+  LD V1, 0x30
+  LD V2, 0x10
+  SUBN V1, V2
+  SE V1, 0xE0
+  JP flag_6
+  SE VF, 0
+  JP flag_6
+  LD VE, 1
+flag_6:
+  CALL mark
+
+; and counts an equal subtraction as not borrowing too
+  LD VE, 0
+  LD VF, 0x55
+  LD V1, 0x30
+  LD V2, 0x30
+  SUBN V1, V2
+  SE V1, 0
+  JP flag_subn_equal
+  SE VF, 1
+  JP flag_subn_equal
+  LD VE, 1
+flag_subn_equal:
+  CALL mark
+
+; 8xy6 puts the bit shifted out in vf
+  LD VE, 0
+  LD VF, 0x55
+  LD V1, 0x05
+  SHR V1
+  SE V1, 0x02
+  JP flag_7
+  SE VF, 1
+  JP flag_7
+  LD VE, 1
+flag_7:
+  CALL mark
+
+; which is 0 for an even number
+  LD VE, 0
+  LD VF, 0x55
+  LD V1, 0x04
+  SHR V1
+  SE V1, 0x02
+  JP flag_8
+  SE VF, 0
+  JP flag_8
+  LD VE, 1
+flag_8:
+  CALL mark
+
+; 8xyE puts the top bit in vf
+  LD VE, 0
+  LD VF, 0x55
+  LD V1, 0x81
+  SHL V1
+  SE V1, 0x02
+  JP flag_9
+  SE VF, 1
+  JP flag_9
+  LD VE, 1
+flag_9:
+  CALL mark
+
+; which is 0 when it was clear
+  LD VE, 0
+  LD VF, 0x55
+  LD V1, 0x41
+  SHL V1
+  SE V1, 0x82
+  JP flag_10
+  SE VF, 0
+  JP flag_10
+  LD VE, 1
+flag_10:
+  CALL mark
+
+; 8FF4 with vf as vx leaves the carry
+  LD VE, 0
+  LD VF, 0xFF
+  LD V2, 0x01
+  ADD VF, V2
+  SE VF, 1
+  JP vf_0
+  LD VE, 1
+vf_0:
+  CALL mark
+
+; 8FF5 leaves not borrowing
+  LD VE, 0
+  LD VF, 0x20
+  LD V2, 0x10
+  SUB VF, V2
+  SE VF, 1
+  JP vf_1
+  LD VE, 1
+vf_1:
+  CALL mark
+
+; 8FF7 leaves borrowing
+  LD VE, 0
+  LD VF, 0x20
+  LD V2, 0x10
+  SUBN VF, V2
+  SE VF, 0
+  JP vf_2
+  LD VE, 1
+vf_2:
+  CALL mark
+
+; 8FF6 leaves the bit shifted out
+  LD VE, 0
+  LD VF, 0x02
+  SHR VF
+  SE VF, 0
+  JP vf_3
+  LD VE, 1
+vf_3:
+  CALL mark
+
+; 8FFE leaves the top bit
+  LD VE, 0
+  LD VF, 0x80
+  SHL VF
+  SE VF, 1
+  JP vf_4
+  LD VE, 1
+vf_4:
+  CALL mark
+
+halt:
+  JP halt
+
+; draws a tick if ve is 1 and a cross otherwise, then moves along to where the next one goes
+mark:
+  LD I, cross
+  SNE VE, 1
+  LD I, tick
+  DRW V6, V7, 5
+  ADD V6, 8
+  SE V6, 64
+  RET
+  LD V6, 0
+  ADD V7, 6
+  RET
+
+tick:
+  DW 0x0102
+  DW 0x84C8
+  DW 0x7000
+cross:
+  DW 0x8850
+  DW 0x2050
+  DW 0x8800
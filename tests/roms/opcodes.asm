@@ -0,0 +1,304 @@
+; works through the instructions every chip-8 has, drawing a tick for each one that does what it should and a
+; cross for each one that doesn't, eight to a row. when every one of them passes, the screen is three rows of ticks
+; each check leaves ve as 1 if it passed, then calls mark
+  CLS
+  LD V6, 0
+  LD V7, 0
+
+; 00E0 clears the screen, so drawing in the same spot afterwards doesn't collide
+  LD VE, 0
+  LD I, tick
+  DRW V6, V7, 5
+  CLS
+  DRW V6, V7, 5
+  CLS
+  SE VF, 0
+  JP clear
+  LD VE, 1
+clear:
+  CALL mark
+
+; 3xkk skips when they match
+  LD VE, 0
+  LD V1, 0x2A
+  SE V1, 0x2A
+  JP se_byte
+  LD VE, 1
+se_byte:
+  CALL mark
+
+; 4xkk skips when they don't
+  LD VE, 0
+  SNE V1, 0x2B
+  JP sne_byte
+  LD VE, 1
+sne_byte:
+  CALL mark
+
+; 5xy0 skips when the registers match
+  LD VE, 0
+  LD V2, 0x2A
+  SE V1, V2
+  JP se_reg
+  LD VE, 1
+se_reg:
+  CALL mark
+
+; 9xy0 skips when they don't
+  LD VE, 0
+  LD V2, 0x2B
+  SNE V1, V2
+  JP sne_reg
+  LD VE, 1
+sne_reg:
+  CALL mark
+
+; 7xkk wraps around without touching vf
+  LD VE, 0
+  LD VF, 7
+  LD V1, 0xFF
+  ADD V1, 2
+  SE V1, 1
+  JP add_byte
+  SE VF, 7
+  JP add_byte
+  LD VE, 1
+add_byte:
+  CALL mark
+
+; 8xy0 copies
+  LD VE, 0
+  LD V2, 0x55
+  LD V1, V2
+  SE V1, 0x55
+  JP copy
+  LD VE, 1
+copy:
+  CALL mark
+
+; 8xy1 ors
+  LD VE, 0
+  LD V1, 0x0F
+  LD V2, 0x30
+  OR V1, V2
+  SE V1, 0x3F
+  JP or
+  LD VE, 1
+or:
+  CALL mark
+
+; 8xy2 ands
+  LD VE, 0
+  LD V1, 0x3C
+  LD V2, 0x0F
+  AND V1, V2
+  SE V1, 0x0C
+  JP and
+  LD VE, 1
+and:
+  CALL mark
+
+; 8xy3 xors
+  LD VE, 0
+  LD V1, 0x3C
+  LD V2, 0x0F
+  XOR V1, V2
+  SE V1, 0x33
+  JP xor
+  LD VE, 1
+xor:
+  CALL mark
+
+; 8xy4 adds
+  LD VE, 0
+  LD V1, 0x20
+  LD V2, 0x22
+  ADD V1, V2
+  SE V1, 0x42
+  JP add_reg
+  LD VE, 1
+add_reg:
+  CALL mark
+
+; 8xy5 subtracts vy from vx
+  LD VE, 0
+  LD V1, 0x50
+  LD V2, 0x20
+  SUB V1, V2
+  SE V1, 0x30
+  JP sub
+  LD VE, 1
+sub:
+  CALL mark
+
+; 8xy7 subtracts vx from vy
+  LD VE, 0
+  LD V1, 0x20
+  LD V2, 0x50
+  SUBN V1, V2
+  SE V1, 0x30
+  JP subn
+  LD VE, 1
+subn:
+  CALL mark
+
+; 8xy6 halves, shifting vx by itself so it's the same with either shift quirk
+  LD VE, 0
+  LD V1, 0x84
+  SHR V1
+  SE V1, 0x42
+  JP shr
+  LD VE, 1
+shr:
+  CALL mark
+
+; 8xyE doubles
+  LD VE, 0
+  LD V1, 0x21
+  SHL V1
+  SE V1, 0x42
+  JP shl
+  LD VE, 1
+shl:
+  CALL mark
+
+; Annn and Fx65 read back the data below
+  LD VE, 0
+  LD I, data
+  LD V1, [I]
+  SE V0, 0x12
+  JP load
+  SE V1, 0x34
+  JP load
+  LD VE, 1
+load:
+  CALL mark
+
+; Fx1E moves i along
+  LD VE, 0
+  LD I, data
+  LD V1, 1
+  ADD I, V1
+  LD V0, [I]
+  SE V0, 0x34
+  JP add_i
+  LD VE, 1
+add_i:
+  CALL mark
+
+; Fx55 writes registers out, and they can be read back
+  LD VE, 0
+  LD V1, 0x56
+  LD V2, 0x78
+  LD I, scratch
+  LD [I], V2
+  LD V1, 0
+  LD V2, 0
+  LD I, scratch
+  LD V2, [I]
+  SE V1, 0x56
+  JP store
+  SE V2, 0x78
+  JP store
+  LD VE, 1
+store:
+  CALL mark
+
+; Fx33 writes out the decimal digits
+  LD VE, 0
+  LD V1, 234
+  LD I, scratch
+  LD B, V1
+  LD V2, [I]
+  SE V0, 2
+  JP bcd
+  SE V1, 3
+  JP bcd
+  SE V2, 4
+  JP bcd
+  LD VE, 1
+bcd:
+  CALL mark
+
+; Fx15 and Fx07 keep the delay timer
+  LD VE, 0
+  LD V1, 0x80
+  LD DT, V1
+  LD V1, DT
+  SNE V1, 0
+  JP timer
+  LD VE, 1
+timer:
+  CALL mark
+
+; Bnnn jumps to nnn plus v0
+  LD VE, 0
+  LD V0, 2
+  JP V0, jump_table
+jump_table:
+  JP jump_done
+  LD VE, 1
+jump_done:
+  CALL mark
+
+; 2nnn and 00EE nest
+  LD VE, 0
+  LD V1, 0
+  CALL inner
+  SE V1, 2
+  JP call
+  LD VE, 1
+call:
+  CALL mark
+
+; Fx29 points at the font, so drawing a 0 over itself clears it and sets vf
+  LD VE, 0
+  LD V1, 0
+  LD F, V1
+  LD V2, 0
+  LD V3, 26
+  DRW V2, V3, 5
+  DRW V2, V3, 5
+  SE VF, 1
+  JP font
+  LD VE, 1
+font:
+  CALL mark
+
+halt:
+  JP halt
+
+inner:
+  ADD V1, 1
+  CALL innermost
+  RET
+innermost:
+  ADD V1, 1
+  RET
+
+; draws a tick if ve is 1 and a cross otherwise, then moves along to where the next one goes
+mark:
+  LD I, cross
+  SNE VE, 1
+  LD I, tick
+  DRW V6, V7, 5
+  ADD V6, 8
+  SE V6, 64
+  RET
+  LD V6, 0
+  ADD V7, 6
+  RET
+
+tick:
+  DW 0x0102
+  DW 0x84C8
+  DW 0x7000
+cross:
+  DW 0x8850
+  DW 0x2050
+  DW 0x8800
+data:
+  DW 0x1234
+scratch:
+  DW 0
+  DW 0